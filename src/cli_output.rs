@@ -1,9 +1,19 @@
-use comfy_table::{self, presets, Attribute::Bold, Cell, CellAlignment::Right};
+use comfy_table::{self, presets, Attribute::Bold, Cell, CellAlignment::Right, Color};
 use klayan::{
+    compare::ComparisonRow,
+    geometry::{Column, Row},
     hands::{Finger, Hand},
+    heatmap::{self, HeatmapKey},
     kalamine::Symbol,
+    keyseq,
+    lint::{Diagnostic, Severity},
+    score::Score,
     stats::Stats,
 };
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
 use strum::IntoEnumIterator;
 
 pub fn print_output(stats: Stats, full_lists: bool) {
@@ -14,6 +24,13 @@ pub fn print_output(stats: Stats, full_lists: bool) {
     header.push(Cell::new("usage").set_alignment(Right).add_attribute(Bold));
     header.push(Cell::new("sfb").add_attribute(Bold));
     header.push(Cell::new("sku").add_attribute(Bold));
+    header.push(Cell::new("sfb dist").add_attribute(Bold));
+    header.push(Cell::new("travel").add_attribute(Bold));
+    header.push(
+        Cell::new("extra col")
+            .set_alignment(Right)
+            .add_attribute(Bold),
+    );
 
     for (i, finger) in Finger::iter().enumerate() {
         let name = match finger {
@@ -37,6 +54,28 @@ pub fn print_output(stats: Stats, full_lists: bool) {
 
         let sku = stats.bigrams.per_finger_sku.get(&finger).unwrap();
         rows[i].push(Cell::new(format!("{sku:.2}")));
+
+        let sfb_distance = stats.bigrams.per_finger_sfb_distance.get(&finger).unwrap();
+        rows[i].push(Cell::new(format!("{sfb_distance:.2}")));
+
+        let travel = stats.bigrams.per_finger_travel.get(&finger).unwrap();
+        rows[i].push(Cell::new(format!("{travel:.1}")));
+
+        // inner column of the index fingers, outer column of the pinkies
+        let extra_column = match finger {
+            Finger::LeftPinky => Some(Column::LeftPinkyOuter),
+            Finger::LeftIndex => Some(Column::LeftIndexInner),
+            Finger::RightIndex => Some(Column::RightIndexInner),
+            Finger::RightPinky => Some(Column::RightPinkyOuter),
+            _ => None,
+        };
+        rows[i].push(match extra_column {
+            Some(column) => {
+                let usage = stats.unigrams.column_usage.get(&column).unwrap();
+                Cell::new(format!("{usage:.1}")).set_alignment(Right)
+            }
+            None => Cell::new(""),
+        });
     }
 
     header.push(ngram_header("symbol stats", 19));
@@ -52,11 +91,21 @@ pub fn print_output(stats: Stats, full_lists: bool) {
         let usage = stats.unigrams.hand_usage.get(&hand).unwrap();
         rows[i + 1].push(ngram_stat(name, *usage));
     }
-    for i in 4..8 {
-        rows[i].push(Cell::new(""));
+    for (i, row) in [Row::Middle, Row::Upper, Row::Lower, Row::Digits]
+        .into_iter()
+        .enumerate()
+    {
+        let name = match row {
+            Row::Middle => "home row",
+            Row::Upper => "upper row",
+            Row::Lower => "lower row",
+            _ => "digit row",
+        };
+        let usage = stats.unigrams.row_usage.get(&row).unwrap();
+        rows[i + 4].push(ngram_stat(name, *usage));
     }
 
-    header.push(ngram_header("bigram stats", 16));
+    header.push(ngram_header("bigram stats", 20));
     rows[0].push(ngram_stat("sku", stats.bigrams.total_sku));
     rows[1].push(ngram_stat("sfb", stats.bigrams.total_sfb));
     rows[2].push(ngram_stat("lsb", stats.bigrams.total_lsb));
@@ -64,6 +113,7 @@ pub fn print_output(stats: Stats, full_lists: bool) {
     rows[4].push(ngram_stat("in rolls", stats.bigrams.total_in_rolls));
     rows[5].push(ngram_stat("out rolls", stats.bigrams.total_out_rolls));
     rows[6].push(ngram_stat("all rolls", stats.bigrams.total_all_rolls));
+    rows[7].push(ngram_stat("sfb distance", stats.bigrams.total_sfb_distance));
 
     header.push(ngram_header("trigram stats", 20));
     rows[0].push(ngram_stat("sks", stats.trigrams.total_sks));
@@ -77,6 +127,34 @@ pub fn print_output(stats: Stats, full_lists: bool) {
         "all redirects",
         stats.trigrams.total_all_redirects,
     ));
+    for row in rows.iter_mut().skip(5) {
+        row.push(Cell::new(""));
+    }
+
+    header.push(ngram_header("trigram rhythm", 18));
+    rows[0].push(ngram_stat("alternation", stats.trigrams.total_alternations));
+    rows[1].push(ngram_stat(
+        "in 3-rolls",
+        stats.trigrams.total_one_hand_in_rolls,
+    ));
+    rows[2].push(ngram_stat(
+        "out 3-rolls",
+        stats.trigrams.total_one_hand_out_rolls,
+    ));
+    rows[3].push(ngram_stat(
+        "roll + alt",
+        stats.trigrams.total_roll_alternations,
+    ));
+    rows[4].push(ngram_stat("other", stats.trigrams.total_others));
+    for row in rows.iter_mut().skip(5) {
+        row.push(Cell::new(""));
+    }
+
+    header.push(ngram_header("skipgram stats", 16));
+    rows[0].push(ngram_stat("sfs", stats.skipgrams.total_sfs));
+    for (i, sfs) in stats.skipgrams.per_distance_sfs.iter().enumerate().take(7) {
+        rows[i + 1].push(ngram_stat(&format!("sfs {}", i + 1), *sfs));
+    }
 
     let mut table1 = comfy_table::Table::new();
     table1.load_preset(presets::NOTHING).set_header(header);
@@ -128,6 +206,163 @@ pub fn print_output(stats: Stats, full_lists: bool) {
     println!("{table1}");
     println!();
     println!("{table2}");
+
+    if !stats.symbols.list_alternatives.is_empty() {
+        let mut table3 = comfy_table::Table::new();
+        table3.load_preset(presets::NOTHING).set_header(vec![
+            Cell::new("symbol").add_attribute(Bold),
+            Cell::new("key sequences usage").add_attribute(Bold),
+        ]);
+        let alternatives = &stats.symbols.list_alternatives;
+        for (c, keyseqs) in alternatives
+            .iter()
+            .take(list_len.unwrap_or(alternatives.len()))
+        {
+            let usage: Vec<String> = keyseqs
+                .iter()
+                .map(|(keyseq, usage)| format!("{} {usage:.1}", keyseq::keyseq_name(keyseq)))
+                .collect();
+            table3.add_row(vec![Cell::new(c), Cell::new(usage.join("   "))]);
+        }
+        println!();
+        println!("{table3}");
+    }
+
+    if stats.dead_keys.total_presses > 0.0 {
+        let mut table4 = comfy_table::Table::new();
+        table4.load_preset(presets::NOTHING).set_header(vec![
+            Cell::new("dead key").add_attribute(Bold),
+            Cell::new("presses")
+                .set_alignment(Right)
+                .add_attribute(Bold),
+            Cell::new("sfb").set_alignment(Right).add_attribute(Bold),
+            Cell::new("redirects")
+                .set_alignment(Right)
+                .add_attribute(Bold),
+            Cell::new("characters").add_attribute(Bold),
+        ]);
+        let dead_keys = &stats.dead_keys;
+        let mut presses: Vec<_> = dead_keys.per_dead_key_presses.iter().collect();
+        presses.sort_by(|(dk1, p1), (dk2, p2)| p2.total_cmp(p1).then(dk1.cmp(dk2)));
+        for (dk, presses) in presses {
+            let chars: Vec<String> = dead_keys.list_chars[dk]
+                .iter()
+                .take(list_len.unwrap_or(usize::MAX))
+                .map(|(c, freq)| format!("{c} {freq:.2}"))
+                .collect();
+            table4.add_row(vec![
+                Cell::new(dk),
+                Cell::new(format!("{presses:.2}")).set_alignment(Right),
+                Cell::new(format!("{:.2}", dead_keys.per_dead_key_sfb[dk])).set_alignment(Right),
+                Cell::new(format!("{:.2}", dead_keys.per_dead_key_redirects[dk]))
+                    .set_alignment(Right),
+                Cell::new(chars.join("  ")),
+            ]);
+        }
+        table4.add_row(vec![
+            Cell::new("all").add_attribute(Bold),
+            Cell::new(format!("{:.2}", dead_keys.total_presses)).set_alignment(Right),
+            Cell::new(format!("{:.2}", dead_keys.total_sfb)).set_alignment(Right),
+            Cell::new(format!("{:.2}", dead_keys.total_redirects)).set_alignment(Right),
+            Cell::new(format!(
+                "{:.3} keys per character",
+                dead_keys.average_keyseq_len
+            )),
+        ]);
+        println!();
+        println!("{table4}");
+    }
+}
+
+pub fn print_score(score: &Score) {
+    let mut table = comfy_table::Table::new();
+    table.load_preset(presets::NOTHING).set_header(vec![
+        Cell::new("score").add_attribute(Bold),
+        Cell::new("value").set_alignment(Right).add_attribute(Bold),
+        Cell::new("weight").set_alignment(Right).add_attribute(Bold),
+        Cell::new("contribution")
+            .set_alignment(Right)
+            .add_attribute(Bold),
+    ]);
+    for contribution in score.contributions.iter() {
+        table.add_row(vec![
+            Cell::new(&contribution.name),
+            Cell::new(format!("{:.2}", contribution.value)).set_alignment(Right),
+            Cell::new(format!("{:.2}", contribution.weight)).set_alignment(Right),
+            Cell::new(format!("{:.2}", contribution.score)).set_alignment(Right),
+        ]);
+    }
+    table.add_row(vec![
+        Cell::new("total").add_attribute(Bold),
+        Cell::new(""),
+        Cell::new(""),
+        Cell::new(format!("{:.2}", score.total))
+            .set_alignment(Right)
+            .add_attribute(Bold),
+    ]);
+    println!("{table}");
+}
+
+pub fn print_comparison(names: &[String], rows: &[ComparisonRow]) {
+    let mut header = vec![Cell::new("stat").add_attribute(Bold)];
+    header.extend(
+        names
+            .iter()
+            .map(|name| Cell::new(name).set_alignment(Right).add_attribute(Bold)),
+    );
+
+    let mut table = comfy_table::Table::new();
+    table.load_preset(presets::NOTHING).set_header(header);
+    for row in rows {
+        let best = row.best();
+        let mut cells = vec![Cell::new(&row.name)];
+        for (i, (value, delta)) in row.values.iter().zip(row.deltas()).enumerate() {
+            let text = if i == 0 {
+                format!("{value:.2}")
+            } else {
+                format!("{value:.2} ({delta:+.2})")
+            };
+            let mut cell = Cell::new(text).set_alignment(Right);
+            if best.contains(&i) {
+                cell = cell.add_attribute(Bold).fg(Color::Green);
+            }
+            cells.push(cell);
+        }
+        table.add_row(cells);
+    }
+    println!("{table}");
+}
+
+pub fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    if diagnostics.is_empty() {
+        println!("No problem found");
+        return;
+    }
+    let mut table = comfy_table::Table::new();
+    table.load_preset(presets::NOTHING);
+    for diagnostic in diagnostics {
+        let color = match diagnostic.severity() {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Info => Color::Reset,
+        };
+        table.add_row(vec![
+            Cell::new(diagnostic.severity())
+                .add_attribute(Bold)
+                .fg(color),
+            Cell::new(diagnostic.code()),
+            Cell::new(diagnostic),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Keyboard diagram with the label and usage of each key, colored by usage
+/// when the output is a terminal and NO_COLOR is not set
+pub fn print_heatmap(keys: &[HeatmapKey]) {
+    let colored = io::stdout().is_terminal()
+        && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty());
+    print!("{}", heatmap::text(keys, colored));
 }
 
 fn ngram_header(name: &str, size: usize) -> Cell {
@@ -137,7 +372,7 @@ fn ngram_header(name: &str, size: usize) -> Cell {
 }
 
 fn ngram_stat(name: &str, val: f32) -> Cell {
-    let n = if val >= 10.0 || val < 0.01 { 1 } else { 2 };
+    let n = if (0.01..10.0).contains(&val) { 2 } else { 1 };
     Cell::new(format!("{name}  {val:>4.0$}", n)).set_alignment(Right)
 }
 
@@ -0,0 +1,224 @@
+use strum::IntoEnumIterator;
+
+use crate::{
+    geometry::{Column, Row},
+    hands::{Finger, Hand},
+    stats::Stats,
+};
+
+/// Which values of a compared statistic are the best
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Better {
+    Lower,
+    Higher,
+    /// No value is better than another (e.g. usage of a finger)
+    Neither,
+}
+
+/// One statistic, for each compared layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonRow {
+    pub name: String,
+    pub values: Vec<f32>,
+    pub better: Better,
+}
+
+impl ComparisonRow {
+    /// Indices of the best value, all of them when several layouts tie for it,
+    /// and none when every layout has the same value
+    pub fn best(&self) -> Vec<usize> {
+        let values = self.values.iter();
+        let best = match self.better {
+            Better::Lower => values.min_by(|a, b| a.total_cmp(b)),
+            Better::Higher => values.max_by(|a, b| a.total_cmp(b)),
+            Better::Neither => None,
+        };
+        let Some(best) = best else {
+            return Vec::new();
+        };
+        if self.values.iter().all(|v| v == best) {
+            return Vec::new();
+        }
+        (0..self.values.len())
+            .filter(|&i| self.values[i] == *best)
+            .collect()
+    }
+
+    /// Difference of each value with the first one
+    pub fn deltas(&self) -> Vec<f32> {
+        match self.values.first() {
+            Some(first) => self.values.iter().map(|v| v - first).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Side-by-side statistics of several layouts, analysed with the same corpus
+pub fn compare(stats: &[Stats]) -> Vec<ComparisonRow> {
+    let mut rows = Vec::new();
+    let mut push = |name: &str, better: Better, value: &dyn Fn(&Stats) -> f32| {
+        rows.push(ComparisonRow {
+            name: String::from(name),
+            values: stats.iter().map(value).collect(),
+            better,
+        })
+    };
+
+    for finger in Finger::iter() {
+        push(&format!("{finger} usage"), Better::Neither, &|s| {
+            s.unigrams.finger_usage[&finger]
+        });
+    }
+    for hand in Hand::iter() {
+        let name = match hand {
+            Hand::Left => "left hand usage",
+            Hand::Right => "right hand usage",
+            Hand::Thumbs => "thumbs usage",
+        };
+        push(name, Better::Neither, &|s| s.unigrams.hand_usage[&hand]);
+    }
+    push("hand imbalance", Better::Lower, &|s| {
+        (s.unigrams.hand_usage[&Hand::Left] - s.unigrams.hand_usage[&Hand::Right]).abs()
+    });
+    for row in [Row::Middle, Row::Upper, Row::Lower, Row::Digits] {
+        push(&format!("{row} row usage"), Better::Neither, &|s| {
+            s.unigrams.row_usage[&row]
+        });
+    }
+    push("index inner columns usage", Better::Lower, &|s| {
+        s.unigrams.column_usage[&Column::LeftIndexInner]
+            + s.unigrams.column_usage[&Column::RightIndexInner]
+    });
+    push("pinky outer columns usage", Better::Lower, &|s| {
+        s.unigrams.column_usage[&Column::LeftPinkyOuter]
+            + s.unigrams.column_usage[&Column::RightPinkyOuter]
+    });
+
+    push("unsupported", Better::Lower, &|s| {
+        s.symbols.total_unsupported
+    });
+    push("sku", Better::Lower, &|s| s.bigrams.total_sku);
+    push("sfb", Better::Lower, &|s| s.bigrams.total_sfb);
+    push("sfb distance", Better::Lower, &|s| {
+        s.bigrams.total_sfb_distance
+    });
+    push("finger travel", Better::Lower, &|s| s.bigrams.total_travel);
+    push("lsb", Better::Lower, &|s| s.bigrams.total_lsb);
+    push("scissors", Better::Lower, &|s| s.bigrams.total_scissors);
+    push("in rolls", Better::Higher, &|s| s.bigrams.total_in_rolls);
+    push("out rolls", Better::Higher, &|s| s.bigrams.total_out_rolls);
+    push("all rolls", Better::Higher, &|s| s.bigrams.total_all_rolls);
+    push("sks", Better::Lower, &|s| s.trigrams.total_sks);
+    push("sfs", Better::Lower, &|s| s.trigrams.total_sfs);
+    push("redirects", Better::Lower, &|s| s.trigrams.total_redirects);
+    push("bad redirects", Better::Lower, &|s| {
+        s.trigrams.total_bad_redirects
+    });
+    push("all redirects", Better::Lower, &|s| {
+        s.trigrams.total_all_redirects
+    });
+    push("alternations", Better::Higher, &|s| {
+        s.trigrams.total_alternations
+    });
+    push("one-hand in rolls", Better::Higher, &|s| {
+        s.trigrams.total_one_hand_in_rolls
+    });
+    push("one-hand out rolls", Better::Higher, &|s| {
+        s.trigrams.total_one_hand_out_rolls
+    });
+    push("roll alternations", Better::Higher, &|s| {
+        s.trigrams.total_roll_alternations
+    });
+    push("other trigrams", Better::Neither, &|s| {
+        s.trigrams.total_others
+    });
+    push("skipgram sfs", Better::Lower, &|s| s.skipgrams.total_sfs);
+    push("dead key presses", Better::Lower, &|s| {
+        s.dead_keys.total_presses
+    });
+    push("keys per character", Better::Lower, &|s| {
+        s.dead_keys.average_keyseq_len
+    });
+    push("dead key sfb", Better::Lower, &|s| s.dead_keys.total_sfb);
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        geometry::{BuiltinGeometry, Geometry},
+        kalamine::{Corpus, Layout, ModMapping, PhysicalKey::*},
+    };
+
+    #[test]
+    fn compare_layouts() {
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('a', 2.0), ('b', 1.0)]),
+            digrams: HashMap::from([(['a', 'b'], 1.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        // "a" and "b" on the same finger, then on different hands
+        let layouts = [
+            HashMap::from([(KeyF, "a"), (KeyR, "b")]),
+            HashMap::from([(KeyF, "a"), (KeyJ, "b")]),
+        ];
+        let stats: Vec<Stats> = layouts
+            .into_iter()
+            .map(|keys| Layout {
+                keymap: keys
+                    .into_iter()
+                    .map(|(k, s)| (k, ModMapping::from(vec![s])))
+                    .collect(),
+                deadkeys: HashMap::new(),
+            })
+            .map(|layout| {
+                crate::analyse(
+                    &layout,
+                    &corpus,
+                    &Geometry::from(BuiltinGeometry::ISO),
+                    &Default::default(),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let rows = compare(&stats);
+        let row = |name: &str| rows.iter().find(|r| r.name == name).unwrap();
+
+        assert_eq!(row("sfb").values, vec![1.0, 0.0]);
+        assert_eq!(row("sfb").best(), vec![1]);
+        assert_eq!(row("hand imbalance").values, vec![3.0, 1.0]);
+        assert_eq!(row("hand imbalance").deltas(), vec![0.0, -2.0]);
+        assert!(row("left-index usage").best().is_empty());
+    }
+
+    #[test]
+    fn best_value() {
+        let row = ComparisonRow {
+            name: String::from("in rolls"),
+            values: vec![1.0, 3.0, 2.0],
+            better: Better::Higher,
+        };
+        assert_eq!(row.best(), vec![1]);
+        assert_eq!(row.deltas(), vec![0.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn tied_best_values() {
+        let mut row = ComparisonRow {
+            name: String::from("sfb"),
+            values: vec![2.0, 1.0, 3.0, 1.0],
+            better: Better::Lower,
+        };
+        assert_eq!(row.best(), vec![1, 3]);
+        row.values = vec![1.0, 1.0, 1.0];
+        assert!(row.best().is_empty());
+    }
+}
@@ -1,43 +1,364 @@
 use std::collections::HashMap;
 
-use crate::keyseq::KeySymbol;
+use crate::geometry::Geometry;
+use crate::keyseq::{self, KeySeqChoice, KeySymbol};
 
 /// Garanties uniqueness of n-gram in the returned vector
 pub fn keysym_ngram_freq<const N: usize>(
     ngrams_freq: &HashMap<[char; N], f32>,
-    sym_to_keystrokes: &HashMap<char, Vec<KeySymbol>>,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    choice: KeySeqChoice,
+    geometry: &Geometry,
 ) -> Vec<([KeySymbol; N], f32)> {
     let mut ngram_map: HashMap<[KeySymbol; N], f32> = HashMap::new();
 
-    'outer: for (ngram, freq) in ngrams_freq {
-        let mut ngram_key_seq: Vec<&KeySymbol> = Vec::new();
-        for c in ngram {
-            let sym_key_seq = match sym_to_keystrokes.get(&c) {
-                Some(key_seq) => key_seq,
-                None => continue 'outer,
-            };
-            ngram_key_seq.extend(sym_key_seq.iter());
-        }
-        for window in ngram_key_seq.windows(N) {
-            if let Ok(tuple) = <[&KeySymbol; N]>::try_from(window) {
-                let keysym_ngram: [KeySymbol; N] = tuple.map(|key| key.clone());
-                ngram_map
-                    .entry(keysym_ngram)
-                    .and_modify(|f| *f += freq)
-                    .or_insert(*freq);
+    for (ngram, freq) in ngrams_freq {
+        let Some(alternatives) = ngram_alternatives(ngram, sym_to_keystrokes) else {
+            continue;
+        };
+        for (choices, weight) in choose_keyseqs(&alternatives, choice, geometry) {
+            let ngram_key_seq = chosen_keyseq(&alternatives, &choices);
+            for window in ngram_key_seq.windows(N) {
+                if let Ok(tuple) = <[&KeySymbol; N]>::try_from(window) {
+                    let keysym_ngram: [KeySymbol; N] = tuple.map(|key| key.clone());
+                    ngram_map
+                        .entry(keysym_ngram)
+                        .and_modify(|f| *f += freq * weight)
+                        .or_insert(freq * weight);
+                }
             }
         }
     }
     ngram_map.into_iter().collect()
 }
 
+/// Share of each equivalent key sequence in the typing of the characters that have several,
+/// as chosen in the n-grams. The characters found in no n-gram (e.g. only next to spaces)
+/// are chosen alone, with their frequency.
+pub fn keyseq_usage<const N: usize>(
+    char_freq: &HashMap<char, f32>,
+    ngrams_freq: &HashMap<[char; N], f32>,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    choice: KeySeqChoice,
+    geometry: &Geometry,
+) -> HashMap<char, Vec<f32>> {
+    let mut usage: HashMap<char, Vec<f32>> = HashMap::new();
+    for (ngram, freq) in ngrams_freq {
+        add_keyseq_usage(
+            &mut usage,
+            ngram,
+            *freq,
+            sym_to_keystrokes,
+            choice,
+            geometry,
+        );
+    }
+    let mut alone = HashMap::new();
+    for (c, freq) in char_freq {
+        if !usage.contains_key(c) {
+            add_keyseq_usage(
+                &mut alone,
+                &[*c],
+                *freq,
+                sym_to_keystrokes,
+                choice,
+                geometry,
+            );
+        }
+    }
+    usage.extend(alone);
+    for shares in usage.values_mut() {
+        let total: f32 = shares.iter().sum();
+        if total > 0.0 {
+            shares.iter_mut().for_each(|share| *share /= total);
+        }
+    }
+    usage
+}
+
+fn add_keyseq_usage<const N: usize>(
+    usage: &mut HashMap<char, Vec<f32>>,
+    ngram: &[char; N],
+    freq: f32,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    choice: KeySeqChoice,
+    geometry: &Geometry,
+) {
+    let Some(alternatives) = ngram_alternatives(ngram, sym_to_keystrokes) else {
+        return;
+    };
+    for (choices, weight) in choose_keyseqs(&alternatives, choice, geometry) {
+        for ((c, keyseqs), chosen) in ngram.iter().zip(&alternatives).zip(choices) {
+            if keyseqs.len() > 1 {
+                let shares = usage.entry(*c).or_insert_with(|| vec![0.0; keyseqs.len()]);
+                shares[chosen] += freq * weight;
+            }
+        }
+    }
+}
+
+/// Equivalent key sequences of each character of an n-gram, if they can all be typed
+fn ngram_alternatives<'a, const N: usize>(
+    ngram: &[char; N],
+    sym_to_keystrokes: &'a HashMap<char, Vec<Vec<KeySymbol>>>,
+) -> Option<Vec<&'a [Vec<KeySymbol>]>> {
+    ngram
+        .iter()
+        .map(|c| {
+            let keyseqs = keyseq::equivalent_keyseqs(sym_to_keystrokes.get(c)?);
+            (!keyseqs.is_empty()).then_some(keyseqs)
+        })
+        .collect()
+}
+
+fn chosen_keyseq<'a>(
+    alternatives: &[&'a [Vec<KeySymbol>]],
+    choices: &[usize],
+) -> Vec<&'a KeySymbol> {
+    alternatives
+        .iter()
+        .zip(choices)
+        .flat_map(|(keyseqs, chosen)| &keyseqs[*chosen])
+        .collect()
+}
+
+/// Key sequence chosen for each character of an n-gram, given their alternatives,
+/// with the share of the n-gram frequency they get
+fn choose_keyseqs(
+    alternatives: &[&[Vec<KeySymbol>]],
+    choice: KeySeqChoice,
+    geometry: &Geometry,
+) -> Vec<(Vec<usize>, f32)> {
+    let best = || vec![0; alternatives.len()];
+    match choice {
+        KeySeqChoice::Best => vec![(best(), 1.0)],
+        KeySeqChoice::Even => {
+            let share: f32 = alternatives
+                .iter()
+                .map(|keyseqs| 1.0 / keyseqs.len() as f32)
+                .product();
+            combinations(alternatives)
+                .into_iter()
+                .map(|choices| (choices, share))
+                .collect()
+        }
+        KeySeqChoice::Context => {
+            // the first combination with the fewest SFBs, so the best sequences win the ties
+            let same_finger = |choices: &Vec<usize>| {
+                chosen_keyseq(alternatives, choices)
+                    .windows(2)
+                    .filter(|pair| {
+                        pair[0].key != pair[1].key
+                            && geometry.finger(pair[0].key) == geometry.finger(pair[1].key)
+                    })
+                    .count()
+            };
+            let chosen = combinations(alternatives)
+                .into_iter()
+                .min_by_key(same_finger)
+                .unwrap_or_else(best);
+            vec![(chosen, 1.0)]
+        }
+    }
+}
+
+/// All the choices of a key sequence for each character, from the best ones
+fn combinations(alternatives: &[&[Vec<KeySymbol>]]) -> Vec<Vec<usize>> {
+    let mut combinations: Vec<Vec<usize>> = vec![Vec::new()];
+    for keyseqs in alternatives {
+        combinations = combinations
+            .iter()
+            .flat_map(|choices| {
+                (0..keyseqs.len()).map(move |i| {
+                    let mut choices = choices.clone();
+                    choices.push(i);
+                    choices
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// Frequency of the key symbols, sharing the frequency of the characters typed
+/// in several ways by the usage of their key sequences
 pub fn keysym_freq(
     char_freq: &HashMap<char, f32>,
-    sym_to_keystrokes: &HashMap<char, Vec<KeySymbol>>,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    keyseq_usage: &HashMap<char, Vec<f32>>,
 ) -> Vec<(KeySymbol, f32)> {
-    let char_freq: HashMap<[char; 1], f32> = char_freq.iter().map(|(c, f)| ([*c], *f)).collect();
-    let res = keysym_ngram_freq(&char_freq, &sym_to_keystrokes);
-    res.iter().map(|(c, f)| (c[0].clone(), *f)).collect()
+    let mut keysym_map: HashMap<KeySymbol, f32> = HashMap::new();
+    for (c, freq) in char_freq {
+        let Some(keyseqs) = sym_to_keystrokes.get(c) else {
+            continue;
+        };
+        let shares = match keyseq_usage.get(c) {
+            Some(shares) => shares.clone(),
+            None => vec![1.0],
+        };
+        for (keyseq, share) in keyseqs.iter().zip(shares) {
+            for keysym in keyseq {
+                *keysym_map.entry(keysym.clone()).or_default() += freq * share;
+            }
+        }
+    }
+    keysym_map.into_iter().collect()
+}
+
+/// Frequency of the key symbol pairs typing the skipgrams: the last key of the first character
+/// and the first key of the second one. The frequency of characters typed in several ways
+/// is shared by the usage of their key sequences.
+pub fn keysym_skipgram_freq(
+    skipgrams_freq: &HashMap<[char; 2], f32>,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    keyseq_usage: &HashMap<char, Vec<f32>>,
+) -> Vec<([KeySymbol; 2], f32)> {
+    let used_keyseqs = |c: &char| -> Option<Vec<(&KeySymbol, &KeySymbol, f32)>> {
+        let keyseqs = sym_to_keystrokes.get(c)?;
+        let shares = match keyseq_usage.get(c) {
+            Some(shares) => shares.clone(),
+            None => vec![1.0],
+        };
+        keyseqs
+            .iter()
+            .zip(shares)
+            .map(|(keyseq, share)| Some((keyseq.first()?, keyseq.last()?, share)))
+            .collect()
+    };
+    let mut pair_map: HashMap<[KeySymbol; 2], f32> = HashMap::new();
+    for ([c1, c2], freq) in skipgrams_freq {
+        let (Some(first), Some(second)) = (used_keyseqs(c1), used_keyseqs(c2)) else {
+            continue;
+        };
+        for (_, last1, share1) in &first {
+            for (first2, _, share2) in &second {
+                *pair_map
+                    .entry([(*last1).clone(), (*first2).clone()])
+                    .or_default() += freq * share1 * share2;
+            }
+        }
+    }
+    pair_map.into_iter().collect()
 }
 
-// TODO: test
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{Mod::*, PhysicalKey::*, Symbol::Character};
+
+    #[test]
+    fn keyseq_choices() {
+        let ks_b = KeySymbol::new(Character('b'), KeyB, Base);
+        let ks_b2 = KeySymbol::new(Character('b'), KeyK, Base);
+        let ks_b_alt = KeySymbol::new(Character('b'), KeyQ, Altgr);
+        let ks_v = KeySymbol::new(Character('v'), KeyV, Base);
+        let keyseq_map = HashMap::from([
+            (
+                'b',
+                vec![vec![ks_b.clone()], vec![ks_b2.clone()], vec![ks_b_alt]],
+            ),
+            ('v', vec![vec![ks_v.clone()]]),
+        ]);
+        let digrams = HashMap::from([(['v', 'b'], 4.0), (['b', 'b'], 1.0)]);
+        let geometry = Geometry::from(BuiltinGeometry::ISO);
+        let freq = |choice| -> HashMap<[KeySymbol; 2], f32> {
+            keysym_ngram_freq(&digrams, &keyseq_map, choice, &geometry)
+                .into_iter()
+                .collect()
+        };
+
+        assert_eq!(
+            freq(KeySeqChoice::Best),
+            HashMap::from([
+                ([ks_v.clone(), ks_b.clone()], 4.0),
+                ([ks_b.clone(), ks_b.clone()], 1.0),
+            ])
+        );
+        // the AltGr alternative is never used
+        assert_eq!(
+            freq(KeySeqChoice::Even),
+            HashMap::from([
+                ([ks_v.clone(), ks_b.clone()], 2.0),
+                ([ks_v.clone(), ks_b2.clone()], 2.0),
+                ([ks_b.clone(), ks_b.clone()], 0.25),
+                ([ks_b.clone(), ks_b2.clone()], 0.25),
+                ([ks_b2.clone(), ks_b.clone()], 0.25),
+                ([ks_b2.clone(), ks_b2.clone()], 0.25),
+            ])
+        );
+        // KeyB and KeyV are typed with the left index, but a repeated key is no SFB
+        assert_eq!(
+            freq(KeySeqChoice::Context),
+            HashMap::from([
+                ([ks_v.clone(), ks_b2.clone()], 4.0),
+                ([ks_b.clone(), ks_b.clone()], 1.0),
+            ])
+        );
+
+        // the following characters count too
+        let trigrams = HashMap::from([(['b', 'v', 'v'], 1.0)]);
+        let freq = keysym_ngram_freq(&trigrams, &keyseq_map, KeySeqChoice::Context, &geometry);
+        assert_eq!(
+            freq,
+            vec![([ks_b2.clone(), ks_v.clone(), ks_v.clone()], 1.0)]
+        );
+
+        let symbols = HashMap::from([('b', 4.0), ('v', 1.0)]);
+        let usage = |choice| keyseq_usage(&symbols, &digrams, &keyseq_map, choice, &geometry);
+        // bb counts twice
+        assert_eq!(
+            usage(KeySeqChoice::Context),
+            HashMap::from([('b', vec![1.0 / 3.0, 2.0 / 3.0])])
+        );
+        assert_eq!(
+            usage(KeySeqChoice::Best),
+            HashMap::from([('b', vec![1.0, 0.0])])
+        );
+
+        let usage = HashMap::from([('b', vec![0.25, 0.75])]);
+        let freq: HashMap<KeySymbol, f32> = keysym_freq(&symbols, &keyseq_map, &usage)
+            .into_iter()
+            .collect();
+        assert_eq!(
+            freq,
+            HashMap::from([
+                (ks_b.clone(), 1.0),
+                (ks_b2.clone(), 3.0),
+                (ks_v.clone(), 1.0)
+            ])
+        );
+
+        let skipgrams = HashMap::from([(['v', 'b'], 4.0)]);
+        let freq: HashMap<[KeySymbol; 2], f32> =
+            keysym_skipgram_freq(&skipgrams, &keyseq_map, &usage)
+                .into_iter()
+                .collect();
+        assert_eq!(
+            freq,
+            HashMap::from([([ks_v.clone(), ks_b], 1.0), ([ks_v, ks_b2], 3.0)])
+        );
+    }
+
+    #[test]
+    fn keyseq_usage_without_ngrams() {
+        // "à" only appears next to spaces, so in no digram
+        let ks_a = KeySymbol::new(Character('à'), KeyA, Base);
+        let ks_a2 = KeySymbol::new(Character('à'), KeyL, Base);
+        let keyseq_map = HashMap::from([('à', vec![vec![ks_a], vec![ks_a2]])]);
+        let symbols = HashMap::from([('à', 2.0)]);
+        let digrams: HashMap<[char; 2], f32> = HashMap::new();
+        let geometry = Geometry::from(BuiltinGeometry::ISO);
+        let usage = |choice| keyseq_usage(&symbols, &digrams, &keyseq_map, choice, &geometry);
+
+        assert_eq!(
+            usage(KeySeqChoice::Even),
+            HashMap::from([('à', vec![0.5, 0.5])])
+        );
+        assert_eq!(
+            usage(KeySeqChoice::Best),
+            HashMap::from([('à', vec![1.0, 0.0])])
+        );
+    }
+}
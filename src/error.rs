@@ -0,0 +1,47 @@
+use std::{fmt, io};
+
+use crate::kalamine::PhysicalKey;
+
+/// Errors of the loaders and of the analysis
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidLayout(String),
+    InvalidCorpus(String),
+    InvalidGeometry(String),
+    /// Settings of the optimization that cannot be used
+    InvalidConfig(String),
+    /// Key of the layout missing from the geometry
+    UnsupportedKey(PhysicalKey),
+    /// Frequency of the given n-gram is not a number
+    NanFrequency(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::InvalidLayout(msg) => write!(f, "invalid layout: {msg}"),
+            Error::InvalidCorpus(msg) => write!(f, "invalid corpus: {msg}"),
+            Error::InvalidGeometry(msg) => write!(f, "invalid geometry: {msg}"),
+            Error::InvalidConfig(msg) => write!(f, "invalid optimization settings: {msg}"),
+            Error::UnsupportedKey(key) => write!(f, "key {key:?} is not in the geometry"),
+            Error::NanFrequency(ngram) => write!(f, "frequency of {ngram:?} is not a number"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod keylayout;
+mod keysyms;
+pub mod klc;
+pub mod xkb;
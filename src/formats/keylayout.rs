@@ -0,0 +1,393 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+use crate::{
+    kalamine::{DeadKey, Layout, Mod, ModMapping, PhysicalKey, Symbol},
+    Error,
+};
+
+/// Read a macOS `.keylayout` file
+pub fn read_keylayout(path: &Path) -> Result<Layout, Error> {
+    let content = fs::read_to_string(path)?;
+    parse_keylayout(&content).map_err(Error::InvalidLayout)
+}
+
+/// Parse a macOS `.keylayout` file: the key maps selected by the modifier map of its first
+/// layout for the base, shift, option and shift+option levels, and the state machine
+/// of its actions for the dead keys
+pub fn parse_keylayout(content: &str) -> Result<Layout, String> {
+    let content = xml_1_0(content);
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document =
+        Document::parse_with_options(&content, options).map_err(|err| err.to_string())?;
+    let keyboard = document.root_element();
+    let element = |name: &str, id: &str| {
+        keyboard
+            .descendants()
+            .find(|node| node.has_tag_name(name) && node.attribute("id") == Some(id))
+    };
+
+    let layout = keyboard
+        .descendants()
+        .find(|node| node.has_tag_name("layout"))
+        .ok_or("No layout element")?;
+    let map_set = layout.attribute("mapSet").ok_or("No mapSet in layout")?;
+    let modifiers = layout
+        .attribute("modifiers")
+        .ok_or("No modifiers in layout")?;
+    let modifier_map =
+        element("modifierMap", modifiers).ok_or(format!("No modifierMap {modifiers}"))?;
+
+    let actions: HashMap<&str, Node> = keyboard
+        .descendants()
+        .filter(|node| node.has_tag_name("action"))
+        .filter_map(|node| Some((node.attribute("id")?, node)))
+        .collect();
+    let states = DeadKeyStates::new(keyboard);
+
+    let mut keymap: HashMap<PhysicalKey, ModMapping> = HashMap::new();
+    for (level, modifier) in [Mod::Base, Mod::Shift, Mod::Altgr, Mod::AltgrShift]
+        .into_iter()
+        .enumerate()
+    {
+        let Some(index) = map_index(modifier_map, modifier) else {
+            continue;
+        };
+        for (code, key) in key_map(keyboard, map_set, index, 0)? {
+            let Some(physical_key) = physical_key(code) else {
+                continue;
+            };
+            let symbol = match (key.attribute("output"), key.attribute("action")) {
+                (Some(output), _) => single_char(output).map(Symbol::Character),
+                (None, Some(action)) => actions
+                    .get(action)
+                    .and_then(|action| action_symbol(*action, &states)),
+                (None, None) => key
+                    .children()
+                    .find(|node| node.has_tag_name("action"))
+                    .and_then(|action| action_symbol(action, &states)),
+            };
+            let mapping = keymap.entry(physical_key).or_insert(ModMapping {
+                map: [
+                    (Mod::Base, None),
+                    (Mod::Shift, None),
+                    (Mod::Altgr, None),
+                    (Mod::AltgrShift, None),
+                ],
+            });
+            mapping.map[level].1 = symbol;
+        }
+    }
+    if keymap.is_empty() {
+        return Err(format!("No keys in keyMapSet {map_set}"));
+    }
+
+    // The actions typed in a dead key state give its table, from the symbol they type
+    // without dead key
+    let mut deadkeys: HashMap<DeadKey, HashMap<Symbol, Symbol>> = HashMap::new();
+    let inline_actions = keyboard
+        .descendants()
+        .filter(|node| node.has_tag_name("key"))
+        .flat_map(|key| key.children().filter(|node| node.has_tag_name("action")));
+    for action in actions.values().copied().chain(inline_actions) {
+        let Some(input) = action_symbol(action, &states) else {
+            continue;
+        };
+        for when in action.children().filter(|node| node.has_tag_name("when")) {
+            let Some(dead_key) = when.attribute("state").and_then(|state| states.get(state)) else {
+                continue;
+            };
+            if when.has_attribute("through") {
+                continue;
+            }
+            if let Some(output) = when_symbol(when, &states) {
+                deadkeys
+                    .entry(DeadKey { name: dead_key })
+                    .or_default()
+                    .insert(input, output);
+            }
+        }
+    }
+    Ok(Layout { keymap, deadkeys })
+}
+
+/// roxmltree only reads XML 1.0, which has no control characters, while keylayout files
+/// are often XML 1.1 and map some keys to control characters
+fn xml_1_0(content: &str) -> String {
+    let mut content = content.replacen("version=\"1.1\"", "version=\"1.0\"", 1);
+    for c in (0..0x20).filter(|c| ![0x09, 0x0a, 0x0d].contains(c)) {
+        for reference in [
+            format!("&#x{c:04X};"),
+            format!("&#x{c:04x};"),
+            format!("&#{c};"),
+        ] {
+            content = content.replace(&reference, "");
+        }
+    }
+    content
+}
+
+/// Dead keys of the states of the keylayout, named after their terminator
+/// (or a private use character if it isn't a single character, or is already used)
+struct DeadKeyStates(HashMap<String, char>);
+
+impl DeadKeyStates {
+    fn new(keyboard: Node) -> Self {
+        let mut states: Vec<&str> = keyboard
+            .descendants()
+            .filter(|node| node.has_tag_name("when"))
+            .filter_map(|when| when.attribute("next"))
+            .collect();
+        states.sort();
+        states.dedup();
+        let terminators: HashMap<&str, &str> = keyboard
+            .descendants()
+            .filter(|node| node.has_tag_name("terminators"))
+            .flat_map(|node| node.children())
+            .filter_map(|when| Some((when.attribute("state")?, when.attribute("output")?)))
+            .collect();
+
+        let mut names: HashMap<String, char> = HashMap::new();
+        let mut private_use = '\u{e000}'..;
+        for state in states {
+            let name = terminators
+                .get(state)
+                .and_then(|output| single_char(output))
+                .filter(|c| !names.values().any(|name| name == c))
+                .or_else(|| private_use.next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            names.insert(state.to_string(), name);
+        }
+        Self(names)
+    }
+
+    fn get(&self, state: &str) -> Option<char> {
+        self.0.get(state).copied()
+    }
+}
+
+/// Symbol typed by an action outside of dead keys
+fn action_symbol(action: Node, states: &DeadKeyStates) -> Option<Symbol> {
+    action
+        .children()
+        .find(|node| node.has_tag_name("when") && node.attribute("state") == Some("none"))
+        .and_then(|when| when_symbol(when, states))
+}
+
+fn when_symbol(when: Node, states: &DeadKeyStates) -> Option<Symbol> {
+    match (when.attribute("output"), when.attribute("next")) {
+        (Some(output), _) => single_char(output).map(Symbol::Character),
+        (None, Some(next)) => states.get(next).map(Symbol::DeadKey),
+        (None, None) => None,
+    }
+}
+
+fn single_char(output: &str) -> Option<char> {
+    let mut chars = output.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Index of the key map selected by a modifier level: the first `keyMapSelect` whose
+/// modifiers are exactly shift and/or option, ignoring the optional (`?`) modifiers
+fn map_index<'a>(modifier_map: Node<'a, '_>, modifier: Mod) -> Option<&'a str> {
+    let (shift, option) = match modifier {
+        Mod::Base => (false, false),
+        Mod::Shift => (true, false),
+        Mod::Altgr => (false, true),
+        Mod::AltgrShift => (true, true),
+    };
+    let matches = |keys: &str| {
+        let keys: Vec<&str> = keys
+            .split_whitespace()
+            .filter(|key| !key.ends_with('?'))
+            .collect();
+        let is_shift = |key: &&str| key.to_lowercase().ends_with("shift");
+        let is_option = |key: &&str| key.to_lowercase().ends_with("option");
+        keys.iter().any(is_shift) == shift
+            && keys.iter().any(is_option) == option
+            && keys.iter().all(|key| is_shift(key) || is_option(key))
+    };
+    modifier_map
+        .children()
+        .filter(|node| node.has_tag_name("keyMapSelect"))
+        .find(|select| {
+            select
+                .children()
+                .filter(|node| node.has_tag_name("modifier"))
+                .any(|node| matches(node.attribute("keys").unwrap_or_default()))
+        })
+        .and_then(|select| select.attribute("mapIndex"))
+        .or(match modifier {
+            Mod::Base => modifier_map.attribute("defaultIndex"),
+            _ => None,
+        })
+}
+
+/// Keys of a key map by key code, including the keys of the key map it is based on
+fn key_map<'a, 'input>(
+    keyboard: Node<'a, 'input>,
+    map_set: &str,
+    index: &str,
+    depth: usize,
+) -> Result<HashMap<u16, Node<'a, 'input>>, String> {
+    if depth > 10 {
+        return Err(format!("Too many nested key maps in keyMapSet {map_set}"));
+    }
+    let Some(map) = keyboard
+        .descendants()
+        .find(|node| node.has_tag_name("keyMapSet") && node.attribute("id") == Some(map_set))
+        .and_then(|set| {
+            set.children()
+                .find(|node| node.has_tag_name("keyMap") && node.attribute("index") == Some(index))
+        })
+    else {
+        return Err(format!("No keyMap {index} in keyMapSet {map_set}"));
+    };
+    let mut keys = match (map.attribute("baseMapSet"), map.attribute("baseIndex")) {
+        (Some(base_set), Some(base_index)) => key_map(keyboard, base_set, base_index, depth + 1)?,
+        _ => HashMap::new(),
+    };
+    for key in map.children().filter(|node| node.has_tag_name("key")) {
+        if let Some(code) = key.attribute("code").and_then(|code| code.parse().ok()) {
+            keys.insert(code, key);
+        }
+    }
+    Ok(keys)
+}
+
+/// Physical key of a macOS virtual key code. The key codes of `§` and `` ` `` are swapped
+/// on ISO keyboards, they are read with the ANSI meaning.
+fn physical_key(code: u16) -> Option<PhysicalKey> {
+    use PhysicalKey::*;
+    #[rustfmt::skip]
+    const KEYS: [Option<PhysicalKey>; 51] = [
+        Some(KeyA), Some(KeyS), Some(KeyD), Some(KeyF), Some(KeyH), Some(KeyG), Some(KeyZ), Some(KeyX),
+        Some(KeyC), Some(KeyV), Some(IntlBackslash), Some(KeyB), Some(KeyQ), Some(KeyW), Some(KeyE), Some(KeyR),
+        Some(KeyY), Some(KeyT), Some(Digit1), Some(Digit2), Some(Digit3), Some(Digit4), Some(Digit6), Some(Digit5),
+        Some(Equal), Some(Digit9), Some(Digit7), Some(Minus), Some(Digit8), Some(Digit0), Some(BracketRight), Some(KeyO),
+        Some(KeyU), Some(BracketLeft), Some(KeyI), Some(KeyP), None, Some(KeyL), Some(KeyJ), Some(Quote),
+        Some(KeyK), Some(Semicolon), Some(Backslash), Some(Comma), Some(Slash), Some(KeyN), Some(KeyM), Some(Period),
+        None, Some(Space), Some(Backquote),
+    ];
+    KEYS.get(code as usize).copied().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use PhysicalKey::*;
+
+    const KEYLAYOUT: &str = r#"<?xml version="1.1" encoding="UTF-8"?>
+<!DOCTYPE keyboard SYSTEM "file://localhost/System/Library/DTDs/KeyboardLayout.dtd">
+<keyboard group="0" id="-1234" name="Test" maxout="1">
+    <layouts>
+        <layout first="0" last="17" mapSet="ANSI" modifiers="Modifiers"/>
+    </layouts>
+    <modifierMap id="Modifiers" defaultIndex="0">
+        <keyMapSelect mapIndex="0">
+            <modifier keys=""/>
+        </keyMapSelect>
+        <keyMapSelect mapIndex="1">
+            <modifier keys="anyShift caps?"/>
+        </keyMapSelect>
+        <keyMapSelect mapIndex="2">
+            <modifier keys="caps"/>
+        </keyMapSelect>
+        <keyMapSelect mapIndex="3">
+            <modifier keys="anyOption"/>
+        </keyMapSelect>
+        <keyMapSelect mapIndex="4">
+            <modifier keys="anyShift anyOption"/>
+        </keyMapSelect>
+        <keyMapSelect mapIndex="5">
+            <modifier keys="command"/>
+        </keyMapSelect>
+    </modifierMap>
+    <keyMapSet id="ANSI">
+        <keyMap index="0">
+            <key code="0" action="a"/>
+            <key code="33" action="circumflex"/>
+            <key code="36" output="&#x000D;"/>
+            <key code="49" action="space"/>
+            <key code="51" output="&#x0008;"/>
+        </keyMap>
+        <keyMap index="1">
+            <key code="0" output="A"/>
+            <key code="33" action="diaeresis"/>
+            <key code="49" output=" "/>
+        </keyMap>
+        <keyMap index="2" baseMapSet="ANSI" baseIndex="1">
+        </keyMap>
+        <keyMap index="3" baseMapSet="ANSI" baseIndex="0">
+            <key code="0" output="æ"/>
+            <key code="33" output="&lt;"/>
+        </keyMap>
+        <keyMap index="4">
+            <key code="0" output="Æ"/>
+        </keyMap>
+        <keyMap index="5">
+            <key code="0" output="a"/>
+        </keyMap>
+    </keyMapSet>
+    <actions>
+        <action id="a">
+            <when state="none" output="a"/>
+            <when state="circumflex" output="â"/>
+            <when state="diaeresis" output="ä"/>
+        </action>
+        <action id="circumflex">
+            <when state="none" next="circumflex"/>
+            <when state="circumflex" output="^"/>
+            <when state="diaeresis" next="circumflex"/>
+        </action>
+        <action id="diaeresis">
+            <when state="none" next="diaeresis"/>
+        </action>
+        <action id="space">
+            <when state="none" output=" "/>
+            <when state="circumflex" output="^"/>
+            <when state="diaeresis" output="¨"/>
+        </action>
+    </actions>
+    <terminators>
+        <when state="circumflex" output="^"/>
+        <when state="diaeresis" output="¨"/>
+    </terminators>
+</keyboard>
+"#;
+
+    #[test]
+    fn keylayout() {
+        let layout = parse_keylayout(KEYLAYOUT).unwrap();
+        let keymap = &layout.keymap;
+        assert_eq!(keymap[&KeyA], ModMapping::from(vec!["a", "A", "æ", "Æ"]));
+        assert_eq!(
+            keymap[&BracketLeft],
+            ModMapping::from(vec!["*^", "*¨", "<"])
+        );
+        assert_eq!(keymap[&Space], ModMapping::from(vec![" ", " ", " "]));
+        assert_eq!(keymap.len(), 3);
+
+        let c = Symbol::Character;
+        let circumflex = &layout.deadkeys[&DeadKey { name: '^' }];
+        assert_eq!(circumflex[&c('a')], c('â'));
+        assert_eq!(circumflex[&c(' ')], c('^'));
+        assert_eq!(circumflex[&Symbol::DeadKey('^')], c('^'));
+        let diaeresis = &layout.deadkeys[&DeadKey { name: '¨' }];
+        assert_eq!(diaeresis[&c('a')], c('ä'));
+        // nested dead keys
+        assert_eq!(diaeresis[&Symbol::DeadKey('^')], Symbol::DeadKey('^'));
+        assert_eq!(layout.deadkeys.len(), 2);
+
+        assert!(parse_keylayout("<keyboard/>").is_err());
+        assert!(parse_keylayout("<keyboard").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::kalamine::Symbol;
+
+/// Names of the Latin-1 keysyms from 0x20 to 0x7e, whose values are their code points
+#[rustfmt::skip]
+const ASCII: [&str; 95] = [
+    "space", "exclam", "quotedbl", "numbersign", "dollar", "percent", "ampersand", "apostrophe",
+    "parenleft", "parenright", "asterisk", "plus", "comma", "minus", "period", "slash",
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "colon", "semicolon", "less", "equal", "greater", "question", "at",
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "bracketleft", "backslash", "bracketright", "asciicircum", "underscore", "grave",
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m",
+    "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+    "braceleft", "bar", "braceright", "asciitilde",
+];
+
+/// Names of the Latin-1 keysyms from 0xa0 to 0xff
+#[rustfmt::skip]
+const LATIN1: [&str; 96] = [
+    "nobreakspace", "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section",
+    "diaeresis", "copyright", "ordfeminine", "guillemetleft", "notsign", "hyphen", "registered", "macron",
+    "degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph", "periodcentered",
+    "cedilla", "onesuperior", "ordmasculine", "guillemetright", "onequarter", "onehalf", "threequarters", "questiondown",
+    "Agrave", "Aacute", "Acircumflex", "Atilde", "Adiaeresis", "Aring", "AE", "Ccedilla",
+    "Egrave", "Eacute", "Ecircumflex", "Ediaeresis", "Igrave", "Iacute", "Icircumflex", "Idiaeresis",
+    "ETH", "Ntilde", "Ograve", "Oacute", "Ocircumflex", "Otilde", "Odiaeresis", "multiply",
+    "Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udiaeresis", "Yacute", "THORN", "ssharp",
+    "agrave", "aacute", "acircumflex", "atilde", "adiaeresis", "aring", "ae", "ccedilla",
+    "egrave", "eacute", "ecircumflex", "ediaeresis", "igrave", "iacute", "icircumflex", "idiaeresis",
+    "eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde", "odiaeresis", "division",
+    "oslash", "ugrave", "uacute", "ucircumflex", "udiaeresis", "yacute", "thorn", "ydiaeresis",
+];
+
+/// Other common keysyms, including the legacy names of some Latin-1 keysyms
+const OTHERS: [(&str, char); 44] = [
+    ("guillemotleft", '«'),
+    ("guillemotright", '»'),
+    ("masculine", 'º'),
+    ("Eth", 'Ð'),
+    ("Thorn", 'Þ'),
+    ("Ooblique", 'Ø'),
+    ("ooblique", 'ø'),
+    ("OE", 'Œ'),
+    ("oe", 'œ'),
+    ("Ydiaeresis", 'Ÿ'),
+    ("Scaron", 'Š'),
+    ("scaron", 'š'),
+    ("Zcaron", 'Ž'),
+    ("zcaron", 'ž'),
+    ("Ccaron", 'Č'),
+    ("ccaron", 'č'),
+    ("Lstroke", 'Ł'),
+    ("lstroke", 'ł'),
+    ("idotless", 'ı'),
+    ("EuroSign", '€'),
+    ("endash", '–'),
+    ("emdash", '—'),
+    ("ellipsis", '…'),
+    ("leftsinglequotemark", '‘'),
+    ("rightsinglequotemark", '’'),
+    ("singlelowquotemark", '‚'),
+    ("leftdoublequotemark", '“'),
+    ("rightdoublequotemark", '”'),
+    ("doublelowquotemark", '„'),
+    ("dagger", '†'),
+    ("doubledagger", '‡'),
+    ("permille", '‰'),
+    ("trademark", '™'),
+    ("oneeighth", '⅛'),
+    ("threeeighths", '⅜'),
+    ("fiveeighths", '⅝'),
+    ("seveneighths", '⅞'),
+    ("leftarrow", '←'),
+    ("uparrow", '↑'),
+    ("rightarrow", '→'),
+    ("downarrow", '↓'),
+    ("notequal", '≠'),
+    ("lessthanequal", '≤'),
+    ("greaterthanequal", '≥'),
+];
+
+/// Dead keys, named after the symbol kalamine uses for them
+const DEAD_KEYS: [(&str, char); 18] = [
+    ("grave", '`'),
+    ("acute", '´'),
+    ("circumflex", '^'),
+    ("tilde", '~'),
+    ("macron", '¯'),
+    ("breve", '˘'),
+    ("abovedot", '˙'),
+    ("diaeresis", '¨'),
+    ("abovering", '˚'),
+    ("doubleacute", '”'),
+    ("caron", 'ˇ'),
+    ("cedilla", '¸'),
+    ("ogonek", '˛'),
+    ("belowcomma", ','),
+    ("belowdot", '.'),
+    ("stroke", '/'),
+    ("currency", '¤'),
+    ("greek", 'µ'),
+];
+
+/// Dead keysyms that no kalamine dead key stands for, given to the dead keys
+/// without keysym (e.g. the kalamine 1dk) when exporting to XKB
+pub const SPARE_DEAD_KEYS: [&str; 12] = [
+    "dead_hook",
+    "dead_horn",
+    "dead_belowring",
+    "dead_belowmacron",
+    "dead_belowcircumflex",
+    "dead_belowtilde",
+    "dead_belowbreve",
+    "dead_belowdiaeresis",
+    "dead_invertedbreve",
+    "dead_doublegrave",
+    "dead_lowline",
+    "dead_iota",
+];
+
+/// Symbol of an X11 keysym name, e.g. `eacute`, `U00E9`, `0x10000e9` or `dead_acute`.
+/// Keysyms without a symbol (e.g. modifiers, `NoSymbol`) give `None`.
+pub fn keysym_symbol(name: &str) -> Option<Symbol> {
+    if let Some(dead_key) = name.strip_prefix("dead_") {
+        return DEAD_KEYS
+            .iter()
+            .find(|(n, _)| *n == dead_key)
+            .map(|(_, c)| Symbol::DeadKey(*c));
+    }
+    keysym_char(name).map(Symbol::Character)
+}
+
+/// X11 keysym name of a symbol, the inverse of `keysym_symbol`.
+/// Dead keys without keysym (e.g. the kalamine 1dk) give `None`.
+pub fn symbol_keysym(symbol: Symbol) -> Option<String> {
+    match symbol {
+        Symbol::DeadKey(c) => DEAD_KEYS
+            .iter()
+            .find(|(_, name)| *name == c)
+            .map(|(dead_key, _)| format!("dead_{dead_key}")),
+        Symbol::Character(c) => Some(char_keysym(c)),
+    }
+}
+
+fn char_keysym(c: char) -> String {
+    let name = match c as u32 {
+        code @ 0x20..=0x7e => Some(ASCII[code as usize - 0x20]),
+        code @ 0xa0..=0xff => Some(LATIN1[code as usize - 0xa0]),
+        _ => OTHERS
+            .iter()
+            .find(|(_, other)| *other == c)
+            .map(|(name, _)| *name),
+    };
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("U{:04X}", c as u32),
+    }
+}
+
+fn keysym_char(name: &str) -> Option<char> {
+    if let Some(i) = ASCII.iter().position(|n| *n == name) {
+        return char::from_u32(0x20 + i as u32);
+    }
+    if let Some(i) = LATIN1.iter().position(|n| *n == name) {
+        return char::from_u32(0xa0 + i as u32);
+    }
+    if let Some((_, c)) = OTHERS.iter().find(|(n, _)| *n == name) {
+        return Some(*c);
+    }
+    if let Some(hex) = name.strip_prefix('U').filter(|hex| hex.len() >= 4) {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    if let Some(hex) = name.strip_prefix("0x") {
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        return match value {
+            0x20..=0x7e | 0xa0..=0xff => char::from_u32(value),
+            0x0100_0000.. => char::from_u32(value - 0x0100_0000),
+            _ => None,
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn keysyms() {
+        assert_eq!(keysym_symbol("a"), Some(Symbol::Character('a')));
+        assert_eq!(keysym_symbol("asciitilde"), Some(Symbol::Character('~')));
+        assert_eq!(keysym_symbol("eacute"), Some(Symbol::Character('é')));
+        assert_eq!(keysym_symbol("ydiaeresis"), Some(Symbol::Character('ÿ')));
+        assert_eq!(keysym_symbol("guillemotleft"), Some(Symbol::Character('«')));
+        assert_eq!(keysym_symbol("U2022"), Some(Symbol::Character('•')));
+        assert_eq!(keysym_symbol("0x10000e9"), Some(Symbol::Character('é')));
+        assert_eq!(keysym_symbol("dead_circumflex"), Some(Symbol::DeadKey('^')));
+        assert_eq!(keysym_symbol("ISO_Level3_Shift"), None);
+        assert_eq!(keysym_symbol("NoSymbol"), None);
+        assert_eq!(keysym_symbol("Uxyz0"), None);
+
+        let keysym = |c| symbol_keysym(Symbol::Character(c)).unwrap();
+        assert_eq!(keysym('a'), "a");
+        assert_eq!(keysym(' '), "space");
+        assert_eq!(keysym('é'), "eacute");
+        assert_eq!(keysym('œ'), "oe");
+        assert_eq!(keysym('•'), "U2022");
+        assert_eq!(
+            symbol_keysym(Symbol::DeadKey('^')).as_deref(),
+            Some("dead_circumflex")
+        );
+        assert_eq!(symbol_keysym(Symbol::DeadKey('*')), None);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    kalamine::{DeadKey, Layout, Mod, ModMapping, PhysicalKey, Symbol},
+    Error,
+};
+
+/// Read a Windows keyboard layout file, as written by MSKLC (usually in UTF-16)
+pub fn read_klc(path: &Path) -> Result<Layout, Error> {
+    let bytes = fs::read(path)?;
+    decode(&bytes)
+        .and_then(|content| parse_klc(&content))
+        .map_err(Error::InvalidLayout)
+}
+
+/// Text of a KLC file: UTF-16 with a byte order mark, or UTF-8
+fn decode(bytes: &[u8]) -> Result<String, String> {
+    let utf16 = |to_u16: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| to_u16([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|err| err.to_string())
+    };
+    match bytes {
+        [0xff, 0xfe, ..] => utf16(u16::from_le_bytes),
+        [0xfe, 0xff, ..] => utf16(u16::from_be_bytes),
+        [0xef, 0xbb, 0xbf, rest @ ..] => {
+            String::from_utf8(rest.to_vec()).map_err(|err| err.to_string())
+        }
+        _ => String::from_utf8(bytes.to_vec()).map_err(|err| err.to_string()),
+    }
+}
+
+/// Parse the content of a KLC file: the keys of the `LAYOUT` table, with the levels
+/// of the `SHIFTSTATE` list (0, 1, 6 and 7 for base, shift, altgr and altgr+shift),
+/// and the `DEADKEY` tables
+pub fn parse_klc(content: &str) -> Result<Layout, String> {
+    let mut shift_states: Vec<u32> = Vec::new();
+    let mut keymap: HashMap<PhysicalKey, ModMapping> = HashMap::new();
+    let mut deadkeys: HashMap<DeadKey, HashMap<Symbol, Symbol>> = HashMap::new();
+
+    enum Section {
+        Other,
+        ShiftState,
+        Layout,
+        DeadKey(DeadKey),
+    }
+    let mut section = Section::Other;
+
+    for (n, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line
+            .split_whitespace()
+            .take_while(|field| !field.starts_with("//") && !field.starts_with(';'))
+            .collect();
+        let error = |message: &str| format!("Line {}: {message}", n + 1);
+        match fields.as_slice() {
+            [] => continue,
+            ["SHIFTSTATE"] => section = Section::ShiftState,
+            ["LAYOUT"] => section = Section::Layout,
+            ["DEADKEY", name] => {
+                let name = klc_char(name).ok_or_else(|| error("invalid dead key"))?;
+                section = Section::DeadKey(DeadKey { name });
+            }
+            [keyword, ..] if keyword.chars().all(|c| c.is_ascii_uppercase() || c == '_') => {
+                section = Section::Other;
+            }
+            fields => match section {
+                Section::Other => {}
+                Section::ShiftState => {
+                    let state = fields[0]
+                        .parse()
+                        .map_err(|_| error("invalid shift state"))?;
+                    shift_states.push(state);
+                }
+                Section::Layout => {
+                    // scan code, virtual key, caps lock, then one column per shift state
+                    let Some(key) = u8::from_str_radix(fields[0], 16)
+                        .ok()
+                        .and_then(physical_key)
+                    else {
+                        continue;
+                    };
+                    let mut levels: [Option<Symbol>; 4] = [None; 4];
+                    for (state, value) in shift_states.iter().zip(fields.iter().skip(3)) {
+                        let level = match state {
+                            0 => 0,
+                            1 => 1,
+                            6 => 2,
+                            7 => 3,
+                            _ => continue,
+                        };
+                        levels[level] = klc_symbol(value);
+                    }
+                    let [base, shift, altgr, altgr_shift] = levels;
+                    let map = [
+                        (Mod::Base, base),
+                        (Mod::Shift, shift),
+                        (Mod::Altgr, altgr),
+                        (Mod::AltgrShift, altgr_shift),
+                    ];
+                    keymap.insert(key, ModMapping { map });
+                }
+                Section::DeadKey(dead_key) => {
+                    let [input, output, ..] = fields else {
+                        return Err(error("expected a character and its output"));
+                    };
+                    let (Some(Symbol::Character(input)), Some(output)) =
+                        (klc_symbol(input), klc_symbol(output))
+                    else {
+                        return Err(error("invalid dead key output"));
+                    };
+                    deadkeys
+                        .entry(dead_key)
+                        .or_default()
+                        .insert(Symbol::Character(input), output);
+                }
+            },
+        }
+    }
+
+    if keymap.is_empty() {
+        return Err(String::from("No LAYOUT table"));
+    }
+    // The inputs of the dead key tables are characters, which stand for the dead keys
+    // too when typing a dead key after another (e.g. ^^)
+    let dead_key_names: Vec<char> = deadkeys.keys().map(|dead_key| dead_key.name).collect();
+    for table in deadkeys.values_mut() {
+        for name in dead_key_names.iter() {
+            if let Some(output) = table.get(&Symbol::Character(*name)).copied() {
+                table.insert(Symbol::DeadKey(*name), output);
+            }
+        }
+    }
+    Ok(Layout { keymap, deadkeys })
+}
+
+/// Write a layout to a KLC file, in UTF-16 as MSKLC expects
+pub fn write_klc(path: &Path, layout: &Layout, name: &str) -> Result<(), Error> {
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend(
+        format_klc(layout, name)
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes),
+    );
+    Ok(fs::write(path, bytes)?)
+}
+
+/// KLC source of a layout, with AltGr for the third and fourth levels.
+/// Characters outside of the Basic Multilingual Plane are left out.
+pub fn format_klc(layout: &Layout, name: &str) -> String {
+    let name = name.replace('"', "'");
+    let id: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(8)
+        .collect();
+    let id = if id.is_empty() {
+        String::from("klayan")
+    } else {
+        id
+    };
+
+    let mut klc = format!(
+        "KBD\t{id}\t\"{name}\"\r\n\r\n\
+         COPYRIGHT\t\"\"\r\n\r\n\
+         COMPANY\t\"\"\r\n\r\n\
+         LOCALENAME\t\"en-US\"\r\n\r\n\
+         LOCALEID\t\"00000409\"\r\n\r\n\
+         VERSION\t1.0\r\n\r\n\
+         SHIFTSTATE\r\n\r\n\
+         0\t//Column 4\r\n\
+         1\t//Column 5 : Shft\r\n\
+         2\t//Column 6 :       Ctrl\r\n\
+         6\t//Column 7 :       Ctrl Alt\r\n\
+         7\t//Column 8 : Shft  Ctrl Alt\r\n\r\n\
+         LAYOUT\t\t;an extra '@' at the end is a dead key\r\n\r\n\
+         //SC\tVK_\t\tCap\t0\t1\t2\t6\t7\r\n\
+         //--\t----\t\t----\t----\t----\t----\t----\t----\r\n\r\n"
+    );
+    for scan_code in 0..=0x7f {
+        let Some(key) = physical_key(scan_code) else {
+            continue;
+        };
+        let Some(mapping) = layout.keymap.get(&key) else {
+            continue;
+        };
+        let [base, shift, altgr, altgr_shift] = mapping.map.map(|(_, symbol)| symbol);
+        // Caps Lock acts as Shift for letters
+        let caps = match (base, shift) {
+            (Some(Symbol::Character(base)), Some(Symbol::Character(shift))) => {
+                base.is_alphabetic() && base.to_uppercase().eq([shift])
+            }
+            _ => false,
+        };
+        let ctrl = (key == PhysicalKey::Space).then_some(Symbol::Character(' '));
+        let columns: Vec<String> = [base, shift, ctrl, altgr, altgr_shift]
+            .into_iter()
+            .map(klc_value)
+            .collect();
+        klc += &format!(
+            "{scan_code:02x}\t{}\t\t{}\t{}\r\n",
+            virtual_key(key),
+            caps as u8,
+            columns.join("\t")
+        );
+    }
+
+    let mut dead_keys: Vec<&DeadKey> = layout.deadkeys.keys().collect();
+    dead_keys.sort();
+    let dead_keys: Vec<&DeadKey> = dead_keys
+        .into_iter()
+        .filter(|dead_key| (dead_key.name as u32) <= 0xffff)
+        .collect();
+    for dead_key in dead_keys.iter() {
+        klc += &format!("\r\nDEADKEY\t{:04x}\r\n\r\n", dead_key.name as u32);
+        let mut entries: Vec<(&Symbol, &Symbol)> = layout.deadkeys[dead_key].iter().collect();
+        entries.sort();
+        // a character typed after the dead key comes before a dead key with the same name
+        let mut entries: Vec<(char, Symbol)> = entries
+            .into_iter()
+            .map(|(input, output)| match input {
+                Symbol::Character(c) | Symbol::DeadKey(c) => (*c, *output),
+            })
+            .collect();
+        entries.dedup_by_key(|(input, _)| *input);
+        for (input, output) in entries {
+            let output = klc_value(Some(output));
+            if (input as u32) <= 0xffff && output != "-1" {
+                klc += &format!("{:04x}\t{output}\r\n", input as u32);
+            }
+        }
+    }
+    if !dead_keys.is_empty() {
+        klc += "\r\nKEYNAME_DEAD\r\n\r\n";
+        for dead_key in dead_keys {
+            klc += &format!("{:04x}\t\"{}\"\r\n", dead_key.name as u32, dead_key.name);
+        }
+    }
+    klc += &format!(
+        "\r\nDESCRIPTIONS\r\n\r\n0409\t{name}\r\n\r\n\
+         LANGUAGENAMES\r\n\r\n0409\tEnglish (United States)\r\n\r\n\
+         ENDKBD\r\n"
+    );
+    klc
+}
+
+/// KLC value of a symbol: its hexadecimal code, with `@` for dead keys
+fn klc_value(symbol: Option<Symbol>) -> String {
+    match symbol {
+        Some(Symbol::Character(c)) if (c as u32) <= 0xffff => format!("{:04x}", c as u32),
+        Some(Symbol::DeadKey(c)) if (c as u32) <= 0xffff => format!("{:04x}@", c as u32),
+        _ => String::from("-1"),
+    }
+}
+
+/// Windows virtual key of a physical key, as on the US layout
+fn virtual_key(key: PhysicalKey) -> String {
+    use PhysicalKey::*;
+    let name = match key {
+        Backquote => "OEM_3",
+        Minus => "OEM_MINUS",
+        Equal => "OEM_PLUS",
+        BracketLeft => "OEM_4",
+        BracketRight => "OEM_6",
+        Semicolon => "OEM_1",
+        Quote => "OEM_7",
+        Backslash => "OEM_5",
+        IntlBackslash => "OEM_102",
+        Comma => "OEM_COMMA",
+        Period => "OEM_PERIOD",
+        Slash => "OEM_2",
+        Space => "SPACE",
+        _ => {
+            let name = format!("{key:?}");
+            return match name.strip_prefix("Key").or(name.strip_prefix("Digit")) {
+                Some(name) => name.to_string(),
+                None => name,
+            };
+        }
+    };
+    name.to_string()
+}
+
+/// Symbol of a KLC value: a character or its hexadecimal code,
+/// followed by `@` for dead keys. `-1` means no symbol, `%%` a ligature (not supported).
+fn klc_symbol(value: &str) -> Option<Symbol> {
+    match value.strip_suffix('@') {
+        Some(name) => klc_char(name).map(Symbol::DeadKey),
+        None => klc_char(value).map(Symbol::Character),
+    }
+}
+
+fn klc_char(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ if value.len() >= 4 => u32::from_str_radix(value, 16).ok().and_then(char::from_u32),
+        _ => None,
+    }
+}
+
+/// Physical key of a scan code (set 1)
+fn physical_key(scan_code: u8) -> Option<PhysicalKey> {
+    use PhysicalKey::*;
+    #[rustfmt::skip]
+    const ROWS: [(u8, &[PhysicalKey]); 4] = [
+        (0x02, &[Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0, Minus, Equal]),
+        (0x10, &[KeyQ, KeyW, KeyE, KeyR, KeyT, KeyY, KeyU, KeyI, KeyO, KeyP, BracketLeft, BracketRight]),
+        (0x1e, &[KeyA, KeyS, KeyD, KeyF, KeyG, KeyH, KeyJ, KeyK, KeyL, Semicolon, Quote, Backquote]),
+        (0x2b, &[Backslash, KeyZ, KeyX, KeyC, KeyV, KeyB, KeyN, KeyM, Comma, Period, Slash]),
+    ];
+    match scan_code {
+        0x39 => Some(Space),
+        0x56 => Some(IntlBackslash),
+        _ => ROWS
+            .iter()
+            .find_map(|(first, keys)| keys.get(scan_code.checked_sub(*first)? as usize).copied()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use PhysicalKey::*;
+
+    const KLC: &str = "KBD\ttest\t\"Test layout\"
+
+COPYRIGHT\t\"(c) test\"
+LOCALEID\t\"0000040c\"
+
+SHIFTSTATE
+
+0\t//Column 4
+1\t//Column 5 : Shft
+2\t//Column 6 :       Ctrl
+6\t//Column 7 :       Ctrl Alt
+7\t//Column 8 : Shft  Ctrl Alt
+
+LAYOUT\t\t;an extra '@' at the end is a dead key
+
+//SC\tVK_\t\tCap\t0\t1\t2\t6\t7
+//--\t----\t\t----\t----\t----\t----\t----\t----
+
+02\t1\t\t0\t0022\t0031\t-1\t2014\t201e\t// QUOTATION MARK, DIGIT ONE, <none>, EM DASH, DOUBLE LOW-9 QUOTATION MARK
+10\tB\t\t1\tb\tB\t-1\t007c\t%%
+1a\tOEM_4\t\t0\t005e@\t00a8@\t-1\t-1\t-1
+56\tOEM_102\t\t0\t00ea\t00ca\t-1\t-1\t-1
+39\tSPACE\t\t0\t0020\t0020\t0020\t00a0\t202f
+53\tDECIMAL\t\t0\t002c\t002c\t-1\t-1\t-1
+
+DEADKEY\t005e
+
+0061\t00e2\t// a -> â
+0041\t00c2\t// A -> Â
+005e\t005e\t// ^ -> ^
+00a8\t00a8@\t// ¨ -> ¨, still dead
+0020\t005e\t//   -> ^
+
+DEADKEY\t00a8
+
+0061\t00e4\t// a -> ä
+
+KEYNAME
+
+01\tEsc
+0e\tBackspace
+
+ENDKBD
+";
+
+    #[test]
+    fn klc_layout() {
+        let layout = parse_klc(KLC).unwrap();
+        let keymap = &layout.keymap;
+        assert_eq!(
+            keymap[&Digit1],
+            ModMapping::from(vec!["\\\"", "1", "—", "„"])
+        );
+        assert_eq!(keymap[&KeyQ], ModMapping::from(vec!["b", "B", "|"]));
+        assert_eq!(keymap[&BracketLeft], ModMapping::from(vec!["*^", "*¨"]));
+        assert_eq!(keymap[&IntlBackslash], ModMapping::from(vec!["ê", "Ê"]));
+        assert_eq!(
+            keymap[&Space],
+            ModMapping::from(vec![" ", " ", "\u{a0}", "\u{202f}"])
+        );
+        assert_eq!(keymap.len(), 5);
+
+        let c = Symbol::Character;
+        let circumflex = &layout.deadkeys[&DeadKey { name: '^' }];
+        assert_eq!(circumflex[&c('a')], c('â'));
+        assert_eq!(circumflex[&c(' ')], c('^'));
+        assert_eq!(circumflex[&Symbol::DeadKey('^')], c('^'));
+        // nested dead keys
+        assert_eq!(circumflex[&Symbol::DeadKey('¨')], Symbol::DeadKey('¨'));
+        assert_eq!(layout.deadkeys[&DeadKey { name: '¨' }][&c('a')], c('ä'));
+    }
+
+    #[test]
+    fn klc_encoding() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(KLC.encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode(&bytes).unwrap(), KLC);
+        assert_eq!(decode(KLC.as_bytes()).unwrap(), KLC);
+        assert!(parse_klc("KBD\ttest\n").is_err());
+    }
+
+    #[test]
+    fn klc_export() {
+        let layout = parse_klc(KLC).unwrap();
+        let klc = format_klc(&layout, "Test");
+        assert!(klc.starts_with("KBD\tTest\t\"Test\"\r\n"));
+        assert!(klc.contains("\r\n10\tQ\t\t1\t0062\t0042\t-1\t007c\t-1\r\n"));
+        assert!(klc.contains("\r\n1a\tOEM_4\t\t0\t005e@\t00a8@\t-1\t-1\t-1\r\n"));
+        assert!(klc.contains("\r\n39\tSPACE\t\t0\t0020\t0020\t0020\t00a0\t202f\r\n"));
+        assert!(klc.contains("\r\n00a8\t00a8@\r\n"));
+        assert!(klc.ends_with("ENDKBD\r\n"));
+
+        // the written file is read back as the same layout
+        let exported = parse_klc(&klc).unwrap();
+        assert_eq!(exported, layout);
+    }
+}
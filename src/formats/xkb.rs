@@ -0,0 +1,837 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use super::keysyms::{keysym_symbol, symbol_keysym, SPARE_DEAD_KEYS};
+use crate::{
+    kalamine::{diacritic_table, DeadKey, Layout, Mod, ModMapping, PhysicalKey, Symbol},
+    Error,
+};
+
+/// Maximum depth of nested includes, in symbols and Compose files
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Compose file of the system, used for `include "%L"`
+const SYSTEM_COMPOSE: &str = "/usr/share/X11/locale/en_US.UTF-8/Compose";
+
+type Levels = [Option<Symbol>; 4];
+type DeadKeys = HashMap<DeadKey, HashMap<Symbol, Symbol>>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    KeyName(String),
+    Punct(char),
+}
+
+/// How the symbols of a key are merged with the ones already defined
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Merge {
+    Override,
+    Augment,
+}
+
+/// Read a section of an XKB symbols file (the default one if no variant is given).
+/// Included files are looked up in the directory of the file,
+/// and the dead keys are read from the given Compose file.
+pub fn read_symbols(
+    path: &Path,
+    variant: Option<&str>,
+    compose: Option<&Path>,
+) -> Result<Layout, Error> {
+    let content = fs::read_to_string(path)?;
+    let keys = section_keys(&content, variant, path.parent(), 0).map_err(Error::InvalidLayout)?;
+    let deadkeys = compose
+        .map(|path| read_compose(path, 0))
+        .transpose()
+        .map_err(Error::InvalidLayout)?;
+    Ok(layout(keys, deadkeys))
+}
+
+/// Parse a section of XKB symbols, with the dead keys from the content of a Compose file.
+/// Includes cannot be resolved and are skipped.
+/// Without Compose file, the dead keys combine their diacritic with the characters of the layout.
+pub fn parse_symbols(
+    content: &str,
+    variant: Option<&str>,
+    compose: Option<&str>,
+) -> Result<Layout, String> {
+    let keys = section_keys(content, variant, None, 0)?;
+    let deadkeys = compose
+        .map(|content| parse_compose(content, None, 0))
+        .transpose()?;
+    Ok(layout(keys, deadkeys))
+}
+
+/// Compose file used by X11 applications: `$XCOMPOSEFILE`, `~/.XCompose`,
+/// or the one of the system
+pub fn compose_file() -> Option<PathBuf> {
+    let candidates = [
+        env::var_os("XCOMPOSEFILE").map(PathBuf::from),
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".XCompose")),
+        Some(PathBuf::from(SYSTEM_COMPOSE)),
+    ];
+    candidates.into_iter().flatten().find(|path| path.is_file())
+}
+
+/// XKB symbols of a layout, in a section with the given name using AltGr for the third
+/// and fourth levels. XKB has no dead key tables: they are written in a Compose file
+/// with `format_compose`, and the dead keys without keysym get a spare one.
+pub fn format_symbols(layout: &Layout, name: &str) -> Result<String, Error> {
+    let dead_keys = dead_key_keysyms(layout)?;
+    let mut symbols = format!(
+        "// Generated by klayan\n\
+         default partial alphanumeric_keys modifier_keys\n\
+         xkb_symbols \"basic\" {{\n    \
+         name[Group1] = \"{}\";\n\n",
+        name.replace('"', "'")
+    );
+    for (key_name, key) in key_names() {
+        let Some(mapping) = layout.keymap.get(&key) else {
+            continue;
+        };
+        let mut keysyms: Vec<String> = mapping
+            .map
+            .iter()
+            .map(|(_, symbol)| {
+                symbol
+                    .and_then(|symbol| keysym(symbol, &dead_keys))
+                    .unwrap_or_else(|| String::from("NoSymbol"))
+            })
+            .collect();
+        while keysyms.last().is_some_and(|keysym| keysym == "NoSymbol") {
+            keysyms.pop();
+        }
+        if !keysyms.is_empty() {
+            symbols += &format!("    key <{key_name}> {{ [ {} ] }};\n", keysyms.join(", "));
+        }
+    }
+    symbols += "\n    include \"level3(ralt_switch)\"\n};\n";
+    Ok(symbols)
+}
+
+/// Compose file with the dead key tables of a layout, to include in `~/.XCompose`.
+/// It includes the Compose file of the system first, so that the tables of the layout
+/// replace its sequences. A dead key typed with another one gives a sequence of three keys.
+pub fn format_compose(layout: &Layout) -> Result<String, Error> {
+    let dead_keys = dead_key_keysyms(layout)?;
+    let mut compose = String::from("# Generated by klayan\ninclude \"%L\"\n");
+
+    let mut tables: Vec<(&DeadKey, &HashMap<Symbol, Symbol>)> = layout.deadkeys.iter().collect();
+    tables.sort_by_key(|(dead_key, _)| **dead_key);
+    for (dead_key, table) in tables {
+        let dead_keysym = &dead_keys[dead_key];
+        compose.push('\n');
+        let mut entries: Vec<(&Symbol, &Symbol)> = table.iter().collect();
+        entries.sort();
+        for (input, output) in entries {
+            let Some(input) = keysym(*input, &dead_keys) else {
+                continue;
+            };
+            match output {
+                Symbol::Character(c) => {
+                    compose += &compose_line(&[dead_keysym, &input], *c);
+                }
+                Symbol::DeadKey(name) => {
+                    let Some(chained) = layout.deadkeys.get(&DeadKey { name: *name }) else {
+                        continue;
+                    };
+                    let mut entries: Vec<(&Symbol, &Symbol)> = chained.iter().collect();
+                    entries.sort();
+                    for (chained_input, chained_output) in entries {
+                        if let (Some(chained_input), Symbol::Character(c)) =
+                            (keysym(*chained_input, &dead_keys), chained_output)
+                        {
+                            compose += &compose_line(&[dead_keysym, &input, &chained_input], *c);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(compose)
+}
+
+/// Keysym of each dead key of the layout, with a spare dead keysym
+/// for the ones that have none
+fn dead_key_keysyms(layout: &Layout) -> Result<HashMap<DeadKey, String>, Error> {
+    let on_keys = layout
+        .keymap
+        .values()
+        .flat_map(|mapping| mapping.map.iter().filter_map(|(_, symbol)| *symbol))
+        .filter_map(|symbol| match symbol {
+            Symbol::DeadKey(name) => Some(DeadKey { name }),
+            Symbol::Character(_) => None,
+        });
+    let mut dead_keys: Vec<DeadKey> = layout.deadkeys.keys().copied().chain(on_keys).collect();
+    dead_keys.sort();
+    dead_keys.dedup();
+
+    let mut spare = SPARE_DEAD_KEYS.iter();
+    dead_keys
+        .into_iter()
+        .map(|dead_key| {
+            let keysym = match symbol_keysym(Symbol::DeadKey(dead_key.name)) {
+                Some(keysym) => keysym,
+                None => spare
+                    .next()
+                    .map(|keysym| keysym.to_string())
+                    .ok_or_else(|| {
+                        Error::InvalidLayout(format!(
+                            "too many dead keys without XKB keysym, up to {} are supported",
+                            SPARE_DEAD_KEYS.len()
+                        ))
+                    })?,
+            };
+            Ok((dead_key, keysym))
+        })
+        .collect()
+}
+
+fn keysym(symbol: Symbol, dead_keys: &HashMap<DeadKey, String>) -> Option<String> {
+    match symbol {
+        Symbol::DeadKey(name) => dead_keys.get(&DeadKey { name }).cloned(),
+        Symbol::Character(_) => symbol_keysym(symbol),
+    }
+}
+
+/// Compose sequence giving a character, e.g. `<dead_circumflex> <a> : "â" acircumflex`
+fn compose_line(keysyms: &[&str], output: char) -> String {
+    let sequence: Vec<String> = keysyms.iter().map(|keysym| format!("<{keysym}>")).collect();
+    let escaped = match output {
+        '"' | '\\' => format!("\\{output}"),
+        c => c.to_string(),
+    };
+    format!(
+        "{} : \"{escaped}\" {}\n",
+        sequence.join(" "),
+        symbol_keysym(Symbol::Character(output)).unwrap_or_default()
+    )
+}
+
+fn layout(mut keys: HashMap<PhysicalKey, Levels>, compose: Option<DeadKeys>) -> Layout {
+    // the space bar is usually defined with the other non-alphanumeric keys (in `pc`)
+    keys.entry(PhysicalKey::Space)
+        .or_insert([Some(Symbol::Character(' ')), None, None, None]);
+
+    let keymap: HashMap<PhysicalKey, ModMapping> = keys
+        .into_iter()
+        .filter(|(_, levels)| levels.iter().any(Option::is_some))
+        .map(|(key, [base, shift, altgr, altgr_shift])| {
+            let map = [
+                (Mod::Base, base),
+                (Mod::Shift, shift),
+                (Mod::Altgr, altgr),
+                (Mod::AltgrShift, altgr_shift),
+            ];
+            (key, ModMapping { map })
+        })
+        .collect();
+
+    let symbols = || {
+        keymap
+            .values()
+            .flat_map(|mapping| mapping.map.iter().filter_map(|(_, symbol)| *symbol))
+    };
+    let mut dead_keys: Vec<char> = symbols()
+        .filter_map(|symbol| match symbol {
+            Symbol::DeadKey(name) => Some(name),
+            Symbol::Character(_) => None,
+        })
+        .collect();
+    dead_keys.sort();
+    dead_keys.dedup();
+
+    let mut compose = compose;
+    let deadkeys = dead_keys
+        .into_iter()
+        .map(|name| {
+            let dead_key = DeadKey { name };
+            let table = match compose.as_mut() {
+                Some(compose) => compose.remove(&dead_key).unwrap_or_default(),
+                None => diacritic_table(name, symbols()),
+            };
+            (dead_key, table)
+        })
+        .collect();
+
+    Layout { keymap, deadkeys }
+}
+
+fn section_keys(
+    content: &str,
+    variant: Option<&str>,
+    dir: Option<&Path>,
+    depth: usize,
+) -> Result<HashMap<PhysicalKey, Levels>, String> {
+    let tokens = tokenize(content);
+
+    // [flags] xkb_symbols "name" { body };
+    let mut sections: Vec<(String, bool, &[Token])> = Vec::new();
+    let mut default = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1), tokens.get(i + 2)) {
+            (Token::Ident(word), Some(Token::Str(name)), Some(Token::Punct('{')))
+                if word == "xkb_symbols" =>
+            {
+                let end = closing(&tokens, i + 2);
+                sections.push((name.clone(), default, &tokens[i + 3..end]));
+                default = false;
+                i = end + 1;
+            }
+            (Token::Ident(word), _, _) => {
+                default |= word == "default";
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    let section = match variant {
+        Some(variant) => sections.iter().find(|(name, _, _)| name == variant),
+        None => sections
+            .iter()
+            .find(|(_, default, _)| *default)
+            .or(sections.first()),
+    };
+    match section {
+        Some((_, _, body)) => parse_body(body, dir, depth),
+        None => Err(match variant {
+            Some(variant) => format!("No xkb_symbols section named '{variant}'"),
+            None => String::from("No xkb_symbols section"),
+        }),
+    }
+}
+
+fn parse_body(
+    body: &[Token],
+    dir: Option<&Path>,
+    depth: usize,
+) -> Result<HashMap<PhysicalKey, Levels>, String> {
+    let mut keys = HashMap::new();
+    let mut merge = Merge::Override;
+    let mut i = 0;
+    while i < body.len() {
+        let word = match &body[i] {
+            Token::Ident(word) => word.as_str(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        match (word, body.get(i + 1)) {
+            ("include" | "override" | "replace" | "augment", Some(Token::Str(spec))) => {
+                let merge = if word == "augment" {
+                    Merge::Augment
+                } else {
+                    Merge::Override
+                };
+                include(&mut keys, spec, merge, dir, depth)?;
+                i += 2;
+            }
+            ("override" | "replace", Some(Token::Ident(_))) => {
+                merge = Merge::Override;
+                i += 1;
+            }
+            ("augment", Some(Token::Ident(_))) => {
+                merge = Merge::Augment;
+                i += 1;
+            }
+            ("key", Some(Token::KeyName(name))) if body.get(i + 2) == Some(&Token::Punct('{')) => {
+                let end = closing(body, i + 2);
+                if let (Some(key), Some(levels)) =
+                    (physical_key(name), key_levels(&body[i + 3..end]))
+                {
+                    merge_levels(&mut keys, key, levels, merge);
+                }
+                merge = Merge::Override;
+                i = end + 1;
+            }
+            _ => {
+                // other statements (name, modifier_map…) are not needed
+                while i < body.len() && body[i] != Token::Punct(';') {
+                    i = match body[i] {
+                        Token::Punct('{') => closing(body, i) + 1,
+                        _ => i + 1,
+                    };
+                }
+                merge = Merge::Override;
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Include sections of other files, e.g. `latin+level3(ralt_switch)`
+fn include(
+    keys: &mut HashMap<PhysicalKey, Levels>,
+    spec: &str,
+    merge: Merge,
+    dir: Option<&Path>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(format!("Too many nested includes at '{spec}'"));
+    }
+    let Some(dir) = dir else {
+        log::warn!("Cannot resolve include \"{spec}\", skipping it");
+        return Ok(());
+    };
+    // `a+b` overrides a with b, `a|b` augments it
+    let mut parts = Vec::new();
+    let (mut part_merge, mut start) = (merge, 0);
+    for (i, c) in spec.char_indices() {
+        if c == '+' || c == '|' {
+            parts.push((&spec[start..i], part_merge));
+            part_merge = if c == '|' {
+                Merge::Augment
+            } else {
+                Merge::Override
+            };
+            start = i + 1;
+        }
+    }
+    parts.push((&spec[start..], part_merge));
+
+    for (part, merge) in parts {
+        let (file, section) = match part.split_once('(') {
+            Some((file, section)) => (file, Some(section.trim_end_matches(')'))),
+            None => (part, None),
+        };
+        let content = match fs::read_to_string(dir.join(file)) {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!("Cannot read included symbols '{file}', skipping it ({err})");
+                continue;
+            }
+        };
+        let included = section_keys(&content, section, Some(dir), depth + 1)?;
+        for (key, levels) in included {
+            merge_levels(keys, key, levels, merge);
+        }
+    }
+    Ok(())
+}
+
+/// Merge the levels of a key, level by level: undefined levels keep their previous symbols
+fn merge_levels(
+    keys: &mut HashMap<PhysicalKey, Levels>,
+    key: PhysicalKey,
+    levels: Levels,
+    merge: Merge,
+) {
+    let current = keys.entry(key).or_default();
+    for (current, new) in current.iter_mut().zip(levels) {
+        if new.is_some() && (merge == Merge::Override || current.is_none()) {
+            *current = new;
+        }
+    }
+}
+
+/// Symbols of the first group of a key, e.g. `{ type = "…", [ a, A, ae, AE ] }`
+/// or `{ symbols[Group1] = [ a, A ] }`
+fn key_levels(tokens: &[Token]) -> Option<Levels> {
+    let mut bare_groups = 0;
+    for item in split_top_level(tokens) {
+        let list_start = match item {
+            [Token::Punct('['), ..] => {
+                bare_groups += 1;
+                if bare_groups > 1 {
+                    continue;
+                }
+                0
+            }
+            [Token::Ident(field), Token::Punct('['), Token::Ident(group), Token::Punct(']'), Token::Punct('='), Token::Punct('['), ..]
+                if field == "symbols" && group.eq_ignore_ascii_case("group1") =>
+            {
+                5
+            }
+            _ => continue,
+        };
+        let list = &item[list_start + 1..closing(item, list_start)];
+        let mut levels: Levels = [None; 4];
+        for (level, keysym) in split_top_level(list).into_iter().take(4).enumerate() {
+            if let [Token::Ident(name)] = keysym {
+                levels[level] = keysym_symbol(name);
+                if levels[level].is_none() && !matches!(name.as_str(), "NoSymbol" | "VoidSymbol") {
+                    log::debug!("Keysym {name} has no symbol");
+                }
+            }
+        }
+        return Some(levels);
+    }
+    None
+}
+
+/// Items separated by commas, outside of brackets and braces
+fn split_top_level(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct('[' | '{' | '(') => depth += 1,
+            Token::Punct(']' | '}' | ')') => depth -= 1,
+            Token::Punct(',') if depth == 0 => {
+                items.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        items.push(&tokens[start..]);
+    }
+    items
+}
+
+/// Index of the bracket or brace closing the one at `open`
+fn closing(tokens: &[Token], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Punct('[' | '{' | '(') => depth += 1,
+            Token::Punct(']' | '}' | ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+fn tokenize(content: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' => tokens.push(Token::Str(
+                chars.by_ref().take_while(|c| *c != '"').collect(),
+            )),
+            '<' => tokens.push(Token::KeyName(
+                chars.by_ref().take_while(|c| *c != '>').collect(),
+            )),
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    ident.push(c);
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+/// Alphanumeric rows of XKB key names, e.g. `<AE01>` for Digit1
+#[rustfmt::skip]
+const ROWS: [(&str, &[PhysicalKey]); 4] = {
+    use PhysicalKey::*;
+    [
+        ("AE", &[Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0, Minus, Equal]),
+        ("AD", &[KeyQ, KeyW, KeyE, KeyR, KeyT, KeyY, KeyU, KeyI, KeyO, KeyP, BracketLeft, BracketRight]),
+        ("AC", &[KeyA, KeyS, KeyD, KeyF, KeyG, KeyH, KeyJ, KeyK, KeyL, Semicolon, Quote, Backslash]),
+        ("AB", &[KeyZ, KeyX, KeyC, KeyV, KeyB, KeyN, KeyM, Comma, Period, Slash]),
+    ]
+};
+
+/// Physical key of an XKB key name, e.g. `<AD01>` for `KeyQ`
+fn physical_key(name: &str) -> Option<PhysicalKey> {
+    use PhysicalKey::*;
+    match name {
+        "TLDE" => Some(Backquote),
+        "BKSL" => Some(Backslash),
+        "LSGT" => Some(IntlBackslash),
+        "SPCE" => Some(Space),
+        _ => {
+            let (row, column) = name.split_at_checked(2)?;
+            let (_, keys) = ROWS.iter().find(|(r, _)| *r == row)?;
+            let column: usize = column.parse().ok()?;
+            keys.get(column.checked_sub(1)?).copied()
+        }
+    }
+}
+
+/// XKB key names of the physical keys, in the order of the keyboard
+fn key_names() -> Vec<(String, PhysicalKey)> {
+    use PhysicalKey::*;
+    let mut names = vec![(String::from("TLDE"), Backquote)];
+    for (row, keys) in ROWS {
+        names.extend(
+            keys.iter()
+                .enumerate()
+                .filter(|(_, key)| **key != Backslash)
+                .map(|(i, key)| (format!("{row}{:02}", i + 1), *key)),
+        );
+        if row == "AC" {
+            names.push((String::from("BKSL"), Backslash));
+            names.push((String::from("LSGT"), IntlBackslash));
+        }
+    }
+    names.push((String::from("SPCE"), Space));
+    names
+}
+
+fn read_compose(path: &Path, depth: usize) -> Result<DeadKeys, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read Compose file {}: {err}", path.display()))?;
+    parse_compose(&content, Some(path), depth)
+}
+
+/// Dead key tables of a Compose file: the sequences of a dead key followed by one key.
+/// Longer sequences and the ones starting with the compose key are ignored.
+fn parse_compose(content: &str, path: Option<&Path>, depth: usize) -> Result<DeadKeys, String> {
+    let mut deadkeys: DeadKeys = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(spec) = line.strip_prefix("include") {
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(String::from("Too many nested includes in Compose files"));
+            }
+            let included = compose_include(spec.trim().trim_matches('"'), path);
+            match read_compose(&included, depth + 1) {
+                Ok(included) => {
+                    for (dead_key, table) in included {
+                        deadkeys.entry(dead_key).or_default().extend(table);
+                    }
+                }
+                Err(err) => log::warn!("{err}"),
+            }
+            continue;
+        }
+        let Some((sequence, output)) = line.split_once(':') else {
+            continue;
+        };
+        let keysyms: Vec<&str> = sequence
+            .split('>')
+            .filter_map(|keysym| keysym.trim().strip_prefix('<'))
+            .collect();
+        let (Some(Symbol::DeadKey(name)), Some(input)) = (
+            keysyms.first().and_then(|k| keysym_symbol(k)),
+            keysyms.get(1).and_then(|k| keysym_symbol(k)),
+        ) else {
+            continue;
+        };
+        if keysyms.len() != 2 {
+            continue;
+        }
+        if let Some(output) = compose_output(output) {
+            deadkeys
+                .entry(DeadKey { name })
+                .or_default()
+                .insert(input, output);
+        }
+    }
+    Ok(deadkeys)
+}
+
+/// Single character output of a Compose sequence, e.g. `"â" acircumflex # …`
+fn compose_output(output: &str) -> Option<Symbol> {
+    let output = output.trim_start().strip_prefix('"')?;
+    let mut chars = output.chars();
+    let c = match chars.next()? {
+        '\\' => chars.next()?,
+        c => c,
+    };
+    (chars.next() == Some('"')).then_some(Symbol::Character(c))
+}
+
+/// Path of an included Compose file, with `%L` the Compose file of the system,
+/// `%H` the home directory and `%S` the system directory of Compose files
+fn compose_include(spec: &str, path: Option<&Path>) -> PathBuf {
+    let home = env::var("HOME").unwrap_or_default();
+    let spec = spec
+        .replace("%L", SYSTEM_COMPOSE)
+        .replace("%H", &home)
+        .replace("%S", "/usr/share/X11/locale");
+    match path.and_then(Path::parent) {
+        Some(dir) => dir.join(spec),
+        None => PathBuf::from(spec),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use PhysicalKey::*;
+
+    const SYMBOLS: &str = r#"
+        default partial alphanumeric_keys
+        xkb_symbols "basic" {
+            include "latin"
+            name[Group1]="Test";
+
+            key <AE01> { [ ampersand, 1, onesuperior, exclamdown ] };
+            key <AD01> { type[group1]="FOUR_LEVEL_SEMIALPHABETIC", [ a, A, ae, AE ] };
+            key <AD11> { [ dead_circumflex, dead_diaeresis ] }; // comment
+            key <AC01> { symbols[Group1] = [ q, Q ], actions[Group1] = [ NoAction() ] };
+            key <LSGT> { [ less, greater, NoSymbol, U2264 ] };
+            key <RALT> { [ ISO_Level3_Shift ] };
+            modifier_map Mod5 { <LVL3> };
+        };
+
+        partial alphanumeric_keys
+        xkb_symbols "variant" {
+            include "test(basic)"
+            key <AD01> { [ b, B ] };
+        };
+    "#;
+
+    const COMPOSE: &str = r#"
+        # comment
+        <dead_circumflex> <a>  : "â" acircumflex # LATIN SMALL LETTER A WITH CIRCUMFLEX
+        <dead_circumflex> <space> : "^" asciicircum
+        <dead_circumflex> <dead_circumflex> : "^"
+        <dead_acute> <dead_circumflex> <a> : "ấ" U1EA5
+        <Multi_key> <a> <e> : "æ" ae
+        <dead_diaeresis> <quotedbl> : "\"" quotedbl
+        <dead_tilde> <a> : "ã" atilde
+    "#;
+
+    #[test]
+    fn xkb_symbols() {
+        let layout = parse_symbols(SYMBOLS, None, Some(COMPOSE)).unwrap();
+        let keymap = &layout.keymap;
+        assert_eq!(keymap[&Digit1], ModMapping::from(vec!["&", "1", "¹", "¡"]));
+        assert_eq!(keymap[&KeyQ], ModMapping::from(vec!["a", "A", "æ", "Æ"]));
+        assert_eq!(keymap[&KeyA], ModMapping::from(vec!["q", "Q"]));
+        assert_eq!(keymap[&BracketLeft], ModMapping::from(vec!["*^", "*¨"]));
+        assert_eq!(
+            keymap[&IntlBackslash],
+            ModMapping::from(vec!["<", ">", "", "≤"])
+        );
+        assert_eq!(keymap[&Space], ModMapping::from(vec![" "]));
+        assert!(!keymap.contains_key(&AltRight));
+
+        let c = Symbol::Character;
+        let circumflex = &layout.deadkeys[&DeadKey { name: '^' }];
+        assert_eq!(circumflex[&c('a')], c('â'));
+        assert_eq!(circumflex[&c(' ')], c('^'));
+        assert_eq!(circumflex[&Symbol::DeadKey('^')], c('^'));
+        assert_eq!(circumflex.len(), 3);
+        assert_eq!(layout.deadkeys[&DeadKey { name: '¨' }][&c('"')], c('"'));
+        // only the dead keys of the layout
+        assert!(!layout.deadkeys.contains_key(&DeadKey { name: '~' }));
+
+        // without Compose file, the diacritic is combined with the layout's characters
+        let layout = parse_symbols(SYMBOLS, None, None).unwrap();
+        let circumflex = &layout.deadkeys[&DeadKey { name: '^' }];
+        assert_eq!(circumflex[&c('a')], c('â'));
+        assert!(!circumflex.contains_key(&c('q')));
+
+        // includes cannot be resolved without a directory
+        let layout = parse_symbols(SYMBOLS, Some("variant"), None).unwrap();
+        assert_eq!(layout.keymap[&KeyQ], ModMapping::from(vec!["b", "B"]));
+        assert!(parse_symbols(SYMBOLS, Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn xkb_includes() {
+        let dir = env::temp_dir().join(format!("klayan-xkb-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("test"), SYMBOLS).unwrap();
+        fs::write(
+            dir.join("latin"),
+            r#"default xkb_symbols "basic" {
+                key <AD01> { [ x, X, NoSymbol, NoSymbol ] };
+                key <AD02> { [ w, W, lstroke, Lstroke ] };
+            };"#,
+        )
+        .unwrap();
+
+        let layout = read_symbols(&dir.join("test"), Some("variant"), None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // the variant overrides the first levels only
+        assert_eq!(
+            layout.keymap[&KeyQ],
+            ModMapping::from(vec!["b", "B", "æ", "Æ"])
+        );
+        assert_eq!(
+            layout.keymap[&KeyW],
+            ModMapping::from(vec!["w", "W", "ł", "Ł"])
+        );
+        assert_eq!(layout.keymap[&KeyA], ModMapping::from(vec!["q", "Q"]));
+    }
+
+    #[test]
+    fn xkb_export() {
+        let mut layout = parse_symbols(SYMBOLS, None, Some(COMPOSE)).unwrap();
+        layout
+            .keymap
+            .insert(Backslash, ModMapping::from(vec!["\\\\", "*µ", "•", "**"]));
+        let symbols = format_symbols(&layout, "Test \"layout\"").unwrap();
+        assert!(symbols.contains("name[Group1] = \"Test 'layout'\";"));
+        assert!(symbols.contains("key <AE01> { [ ampersand, 1, onesuperior, exclamdown ] };"));
+        assert!(symbols.contains("key <AD11> { [ dead_circumflex, dead_diaeresis ] };"));
+        // the 1dk has no keysym, it gets a spare one
+        assert!(symbols.contains("key <BKSL> { [ backslash, dead_greek, U2022, dead_hook ] };"));
+        assert!(symbols.contains("key <LSGT> { [ less, greater, NoSymbol, lessthanequal ] };"));
+        assert!(symbols.find("<TLDE>").is_none());
+        assert!(symbols.find("<AC01>") < symbols.find("<BKSL>"));
+        assert!(symbols.find("<LSGT>") < symbols.find("<SPCE>"));
+
+        // the written symbols are read back as the same layout, but the spare dead keysym
+        let exported = parse_symbols(&symbols, None, Some(COMPOSE)).unwrap();
+        layout.keymap.get_mut(&Backslash).unwrap().map[3].1 = None;
+        assert_eq!(exported.keymap, layout.keymap);
+    }
+
+    #[test]
+    fn xkb_export_dead_keys() {
+        let layout = Layout::from_file(Path::new("files/ergol.json")).unwrap();
+        let one_dk = DeadKey { name: '*' };
+        let c = Symbol::Character;
+        assert_eq!(layout.deadkeys[&one_dk][&c('c')], c('ç'));
+
+        let symbols = format_symbols(&layout, "Ergo-L").unwrap();
+        assert!(symbols.contains("dead_hook"));
+
+        let compose = format_compose(&layout).unwrap();
+        assert!(compose.starts_with("# Generated by klayan\ninclude \"%L\"\n"));
+        // the layer of the 1dk, with its spare keysym
+        assert!(compose.contains("<dead_hook> <c> : \"ç\" ccedilla\n"));
+        assert!(compose.contains("<dead_hook> <space> : \"’\" rightsinglequotemark\n"));
+        // a dead key typed after the 1dk
+        assert!(compose.contains("<dead_hook> <dead_hook> <e> : \"ë\" ediaeresis\n"));
+        assert!(compose.contains("<dead_diaeresis> <space> : \"\\\"\" quotedbl\n"));
+
+        // the tables of the other dead keys are read back as they are on the layout
+        let compose = compose.replace("include \"%L\"", "");
+        let exported = parse_symbols(&symbols, None, Some(&compose)).unwrap();
+        let circumflex = DeadKey { name: '^' };
+        assert_eq!(exported.deadkeys[&circumflex], layout.deadkeys[&circumflex]);
+
+        let mut too_many = layout.clone();
+        for name in ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b'] {
+            too_many.deadkeys.insert(DeadKey { name }, HashMap::new());
+        }
+        assert!(matches!(
+            format_compose(&too_many),
+            Err(Error::InvalidLayout(_))
+        ));
+    }
+}
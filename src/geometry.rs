@@ -1,10 +1,148 @@
-use crate::kalamine::PhysicalKey;
+use std::{collections::HashMap, fs, path::Path};
 
-/// Keyboard geometry.
+use strum::IntoEnumIterator;
+
+use crate::{
+    hands::{Finger, Hand},
+    kalamine::PhysicalKey,
+    Error,
+};
+
+/// Keyboard geometry: position, finger and row of each key.
+/// Either one of the built-in geometries, or read from a JSON or TOML file
+/// (see files/corne.toml).
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Geometry {
+    pub keys: HashMap<PhysicalKey, KeyGeometry>,
+}
+
+/// Position of a key, in key units (x from left to right, y from top to bottom),
+/// and the finger pressing it.
+/// The position can be left out for keys that are not compared by distance (e.g. thumb keys).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyGeometry {
+    #[serde(default)]
+    pub x: Option<f32>,
+    #[serde(default)]
+    pub y: Option<f32>,
+    pub finger: Finger,
+    pub row: Row,
+}
+
+impl Geometry {
+    /// Read a geometry file, in TOML if the extension is `.toml`, in JSON otherwise
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let geometry = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&content).map_err(|err| err.to_string())
+        } else {
+            serde_json::from_str(&content).map_err(|err| err.to_string())
+        };
+        geometry.map_err(Error::InvalidGeometry)
+    }
+
+    /// Geometry of a key, which must be on this keyboard
+    pub fn key(&self, key: PhysicalKey) -> Result<&KeyGeometry, Error> {
+        self.keys.get(&key).ok_or(Error::UnsupportedKey(key))
+    }
+
+    /// Whether the key exists on this keyboard
+    pub fn contains(&self, key: PhysicalKey) -> bool {
+        self.keys.contains_key(&key)
+    }
+
+    /// Finger pressing the key.
+    /// Keys missing from the geometry use the standard touch typing fingers.
+    pub fn finger(&self, key: PhysicalKey) -> Finger {
+        match self.keys.get(&key) {
+            Some(k) => k.finger,
+            None => key.finger(),
+        }
+    }
+
+    pub fn hand(&self, key: PhysicalKey) -> Hand {
+        self.finger(key).hand()
+    }
+
+    /// Row of the key, the standard one for keys missing from the geometry
+    pub fn row(&self, key: PhysicalKey) -> Row {
+        match self.keys.get(&key) {
+            Some(k) => k.row,
+            None => key.row(),
+        }
+    }
+
+    /// Change the finger pressing a key, e.g. for the modifiers
+    pub fn set_finger(&mut self, key: PhysicalKey, finger: Finger) {
+        match self.keys.get_mut(&key) {
+            Some(k) => k.finger = finger,
+            None => {
+                self.keys.insert(
+                    key,
+                    KeyGeometry {
+                        x: None,
+                        y: None,
+                        finger,
+                        row: key.row(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Horizontal distance between two keys, in key units
+    pub fn horizontal_distance(&self, key1: PhysicalKey, key2: PhysicalKey) -> Option<f32> {
+        let x1 = self.keys.get(&key1)?.x?;
+        let x2 = self.keys.get(&key2)?.x?;
+        Some((x1 - x2).abs())
+    }
+
+    /// Euclidean distance between two keys, in key units.
+    /// Keys without vertical position are compared by row.
+    pub fn distance(&self, key1: PhysicalKey, key2: PhysicalKey) -> Option<f32> {
+        if key1 == key2 {
+            return Some(0.0);
+        }
+        let dx = self.horizontal_distance(key1, key2)?;
+        let dy = match (self.keys[&key1].y, self.keys[&key2].y) {
+            (Some(y1), Some(y2)) => (y1 - y2).abs(),
+            _ => Row::distance(self.row(key1), self.row(key2)) as f32,
+        };
+        Some(dx.hypot(dy))
+    }
+
+    /// Column of the key, by its finger. Keys beyond the column of the home key
+    /// are in the inner column for the index fingers, and in the outer column for the pinkies.
+    pub fn column(&self, key: PhysicalKey) -> Column {
+        let finger = self.finger(key);
+        let home_column = self.home_key(finger).and_then(standard_column);
+        let extra = match (standard_column(key), home_column) {
+            (Some(column), Some(home)) => match finger {
+                Finger::LeftIndex | Finger::RightPinky => column > home,
+                Finger::RightIndex | Finger::LeftPinky => column < home,
+                _ => false,
+            },
+            _ => false,
+        };
+        Column::new(finger, extra)
+    }
+
+    /// Key where the finger rests: the standard home row key pressed by this finger
+    pub fn home_key(&self, finger: Finger) -> Option<PhysicalKey> {
+        use PhysicalKey::*;
+        [KeyA, KeyS, KeyD, KeyF, KeyJ, KeyK, KeyL, Semicolon, Space]
+            .into_iter()
+            .find(|&key| self.contains(key) && self.finger(key) == finger)
+    }
+}
+
+/// Built-in keyboard geometries.
 /// Opti versions: optimized finger placement with angle mod,
 /// and later shift on num layer.
 #[derive(Debug, Clone, Copy)]
-pub enum Geometry {
+pub enum BuiltinGeometry {
     ISO,
     ISOOpti,
     ANSI,
@@ -12,16 +150,16 @@ pub enum Geometry {
     Ortho,
 }
 
-impl std::str::FromStr for Geometry {
+impl std::str::FromStr for BuiltinGeometry {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use Geometry::*;
+        use BuiltinGeometry::*;
         match s.to_lowercase().as_str() {
             "iso" => Ok(ISO),
             "iso-angle-mod" => Ok(ISOOpti),
             "ansi" => Ok(ANSI),
-            "ansi-angle-mod" => Ok(ISOOpti),
+            "ansi-angle-mod" => Ok(ANSIOpti),
             "ortho" => Ok(Ortho),
             _ => Err(format!("Invalid geometry: {}", s)),
         }
@@ -30,18 +168,50 @@ impl std::str::FromStr for Geometry {
 
 // Key size unit.
 // It's 4 to be able to divide by 4 for staggered layout without having to use floats
-pub const U: u32 = 4;
+const U: u32 = 4;
 
-impl Geometry {
-    pub fn horizontal_distance(&self, key1: PhysicalKey, key2: PhysicalKey) -> Option<u32> {
-        let offset1 = self.key_horizontal_position(key1)?;
-        let offset2 = self.key_horizontal_position(key2)?;
-        return Some(offset1.abs_diff(offset2));
+impl From<BuiltinGeometry> for Geometry {
+    fn from(builtin: BuiltinGeometry) -> Self {
+        let keys = PhysicalKey::iter()
+            .filter(|key| builtin.contains(*key))
+            .map(|key| {
+                let x = builtin.key_horizontal_position(key);
+                let row = key.row();
+                let y = (row != Row::Spacebar).then_some(Row::Digits as u32 - row as u32);
+                let key_geometry = KeyGeometry {
+                    x: x.map(|x| x as f32 / U as f32),
+                    y: y.map(|y| y as f32),
+                    finger: builtin.finger(key),
+                    row,
+                };
+                (key, key_geometry)
+            })
+            .collect();
+        Geometry { keys }
+    }
+}
+
+impl BuiltinGeometry {
+    /// Standard touch typing fingers, with the left lower row shifted
+    /// one column to the left under angle mod
+    fn finger(&self, key: PhysicalKey) -> Finger {
+        use BuiltinGeometry::*;
+        use PhysicalKey::*;
+        match (self, key) {
+            (ISOOpti | ANSIOpti, KeyZ) => Finger::LeftRing,
+            (ISOOpti | ANSIOpti, KeyX) => Finger::LeftMiddle,
+            (ISOOpti | ANSIOpti, KeyC) => Finger::LeftIndex,
+            _ => key.finger(),
+        }
+    }
+
+    fn contains(&self, key: PhysicalKey) -> bool {
+        use BuiltinGeometry::*;
+        !matches!((self, key), (ANSI | ANSIOpti, PhysicalKey::IntlBackslash))
     }
 
     fn key_horizontal_position(&self, key: PhysicalKey) -> Option<u32> {
-        // TODO: is Option really needed here?
-        use Geometry::*;
+        use BuiltinGeometry::*;
         use PhysicalKey::*;
         match self {
             Ortho => self.key_horizontal_position_ortho(key),
@@ -52,14 +222,14 @@ impl Geometry {
                 match key {
                     Backslash => match self {
                         ISO | ISOOpti => Some(CAPS + 11 * U),
-                        ANSI | ANSIOpti => Some(TAB + 12 * U),
-                        _ => panic!(),
+                        _ => Some(TAB + 12 * U),
                     },
                     IntlBackslash => match self {
-                        ISO | ISOOpti => Some(SHIFT),
-                        ANSI | ANSIOpti => None,
-                        _ => panic!(),
+                        ISO | ISOOpti => Some(U + U / 4), // after a 1.25U shift
+                        _ => None,
                     },
+                    ShiftLeft => Some(0),
+                    ShiftRight => Some(SHIFT + 10 * U),
                     _ => Some(match key.row() {
                         Row::Upper => self.key_horizontal_position_ortho(key)? + TAB - U,
                         Row::Middle => self.key_horizontal_position_ortho(key)? + CAPS - U,
@@ -74,7 +244,7 @@ impl Geometry {
     fn key_horizontal_position_ortho(&self, key: PhysicalKey) -> Option<u32> {
         use PhysicalKey::*;
         match key {
-            Backquote => Some(0),
+            Backquote | ShiftLeft => Some(0),
             Digit1 | KeyQ | KeyA | KeyZ => Some(U),
             Digit2 | KeyW | KeyS | KeyX => Some(2 * U),
             Digit3 | KeyE | KeyD | KeyC => Some(3 * U),
@@ -85,14 +255,30 @@ impl Geometry {
             Digit8 | KeyI | KeyK | Comma => Some(8 * U),
             Digit9 | KeyO | KeyL | Period => Some(9 * U),
             Digit0 | KeyP | Semicolon | Slash => Some(10 * U),
-            Minus | BracketLeft | Quote | Backslash => Some(11 * U),
+            Minus | BracketLeft | Quote | Backslash | ShiftRight => Some(11 * U),
             Equal | BracketRight => Some(12 * U),
-            Space | IntlBackslash => None,
+            Space | IntlBackslash | AltRight => None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialOrd,
+    Ord,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::Display,
+    strum::EnumIter,
+    strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum Row {
     Spacebar = 0,
     Lower = 1,
@@ -106,3 +292,192 @@ impl Row {
         (r1 as u32).abs_diff(r2 as u32)
     }
 }
+
+/// Column of a key: the finger pressing it, with the inner columns of the
+/// index fingers and the outer columns of the pinkies apart
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialOrd,
+    Ord,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    strum::Display,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Column {
+    LeftPinkyOuter,
+    LeftPinky,
+    LeftRing,
+    LeftMiddle,
+    LeftIndex,
+    LeftIndexInner,
+    RightIndexInner,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightPinky,
+    RightPinkyOuter,
+    Thumb,
+}
+
+impl Column {
+    fn new(finger: Finger, extra: bool) -> Self {
+        use Column::*;
+        match (finger, extra) {
+            (Finger::LeftPinky, true) => LeftPinkyOuter,
+            (Finger::LeftPinky, false) => LeftPinky,
+            (Finger::LeftRing, _) => LeftRing,
+            (Finger::LeftMiddle, _) => LeftMiddle,
+            (Finger::LeftIndex, false) => LeftIndex,
+            (Finger::LeftIndex, true) => LeftIndexInner,
+            (Finger::RightIndex, true) => RightIndexInner,
+            (Finger::RightIndex, false) => RightIndex,
+            (Finger::RightMiddle, _) => RightMiddle,
+            (Finger::RightRing, _) => RightRing,
+            (Finger::RightPinky, false) => RightPinky,
+            (Finger::RightPinky, true) => RightPinkyOuter,
+            (Finger::Thumb, _) => Thumb,
+        }
+    }
+}
+
+/// Column of the key on a standard keyboard, from 0 (Backquote) to 12 (Equal)
+fn standard_column(key: PhysicalKey) -> Option<u32> {
+    match key {
+        PhysicalKey::IntlBackslash => Some(0),
+        _ => Some(BuiltinGeometry::Ortho.key_horizontal_position_ortho(key)? / U),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use BuiltinGeometry::*;
+    use PhysicalKey::*;
+
+    #[test]
+    fn builtin_positions() {
+        let ansi = Geometry::from(ANSI);
+        assert_eq!(ansi.horizontal_distance(KeyQ, KeyA), Some(0.25));
+        assert_eq!(ansi.horizontal_distance(KeyA, KeyZ), Some(0.5));
+        assert_eq!(ansi.keys[&KeyQ].y, Some(1.0));
+        assert_eq!(ansi.horizontal_distance(KeyQ, Space), None);
+        assert!(!ansi.contains(IntlBackslash));
+        assert!(Geometry::from(ISO).contains(IntlBackslash));
+        assert_eq!(
+            Geometry::from(Ortho).horizontal_distance(KeyQ, KeyZ),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn angle_mod_fingers() {
+        for builtin in [ISOOpti, ANSIOpti] {
+            let geometry = Geometry::from(builtin);
+            assert_eq!(geometry.finger(KeyZ), Finger::LeftRing);
+            assert_eq!(geometry.finger(KeyX), Finger::LeftMiddle);
+            assert_eq!(geometry.finger(KeyC), Finger::LeftIndex);
+            assert_eq!(geometry.finger(KeyV), Finger::LeftIndex);
+            assert_eq!(geometry.finger(KeyB), Finger::LeftIndex);
+            assert_eq!(geometry.finger(KeyA), Finger::LeftPinky);
+        }
+        assert_eq!(
+            Geometry::from(ISOOpti).finger(IntlBackslash),
+            Finger::LeftPinky
+        );
+        assert_eq!(Geometry::from(ISO).finger(KeyZ), Finger::LeftPinky);
+        assert!(matches!("ansi-angle-mod".parse(), Ok(ANSIOpti)));
+        assert!(matches!("iso-angle-mod".parse(), Ok(ISOOpti)));
+    }
+
+    #[test]
+    fn key_distance() {
+        let mut iso = Geometry::from(ISO);
+        assert_eq!(iso.distance(KeyF, KeyF), Some(0.0));
+        assert_eq!(iso.distance(KeyR, KeyF), Some(0.25f32.hypot(1.0)));
+        assert_eq!(iso.distance(Digit4, KeyV), Some(3.25));
+        assert_eq!(iso.distance(KeyF, KeyG), Some(1.0));
+        assert_eq!(iso.distance(KeyF, Space), None);
+        assert_eq!(iso.home_key(Finger::LeftIndex), Some(KeyF));
+        assert_eq!(iso.home_key(Finger::Thumb), Some(Space));
+
+        // rows are compared without vertical position
+        iso.keys.get_mut(&KeyV).unwrap().y = None;
+        assert_eq!(iso.distance(Digit4, KeyV), Some(3.25));
+
+        let corne = Geometry::from_file(Path::new("files/corne.toml")).unwrap();
+        assert_eq!(corne.distance(KeyQ, KeyZ), Some(2.0));
+        assert_eq!(corne.home_key(Finger::RightPinky), Some(Semicolon));
+    }
+
+    #[test]
+    fn key_columns() {
+        let iso = Geometry::from(ISO);
+        assert_eq!(iso.column(KeyF), Column::LeftIndex);
+        assert_eq!(iso.column(KeyV), Column::LeftIndex);
+        assert_eq!(iso.column(KeyB), Column::LeftIndexInner);
+        assert_eq!(iso.column(KeyH), Column::RightIndexInner);
+        assert_eq!(iso.column(KeyM), Column::RightIndex);
+        assert_eq!(iso.column(KeyA), Column::LeftPinky);
+        assert_eq!(iso.column(IntlBackslash), Column::LeftPinkyOuter);
+        assert_eq!(iso.column(Quote), Column::RightPinkyOuter);
+        assert_eq!(iso.column(Slash), Column::RightPinky);
+        assert_eq!(iso.column(KeyE), Column::LeftMiddle);
+        assert_eq!(iso.column(Space), Column::Thumb);
+        // with angle mod, the index reaches C outward
+        assert_eq!(Geometry::from(ISOOpti).column(KeyC), Column::LeftIndex);
+    }
+
+    #[test]
+    fn deserialize_geometry() {
+        let toml = r#"
+            [keys.KeyQ]
+            x = 0.0
+            y = 0.25
+            finger = "left_pinky"
+            row = "upper"
+
+            [keys.Space]
+            finger = "thumb"
+            row = "spacebar"
+        "#;
+        let geometry: Geometry = toml::from_str(toml).unwrap();
+        let json = r#"{ "keys": {
+            "KeyQ": { "x": 0.0, "y": 0.25, "finger": "left_pinky", "row": "upper" },
+            "Space": { "finger": "thumb", "row": "spacebar" }
+        } }"#;
+        assert_eq!(geometry, serde_json::from_str(json).unwrap());
+
+        assert_eq!(geometry.finger(KeyQ), Finger::LeftPinky);
+        assert_eq!(geometry.horizontal_distance(KeyQ, Space), None);
+        assert_eq!(geometry.horizontal_distance(KeyQ, KeyW), None);
+        // missing keys use the standard fingers
+        assert_eq!(geometry.finger(KeyJ), Finger::RightIndex);
+    }
+
+    #[test]
+    fn corne_geometry_file() {
+        let geometry = Geometry::from_file(Path::new("files/corne.toml")).unwrap();
+        assert_eq!(geometry.finger(KeyB), Finger::LeftIndex);
+        assert_eq!(geometry.hand(Space), Hand::Thumbs);
+        assert_eq!(geometry.horizontal_distance(KeyQ, KeyZ), Some(0.0));
+        assert!(!geometry.contains(Digit1));
+        assert!(matches!(
+            geometry.key(Digit1),
+            Err(Error::UnsupportedKey(Digit1))
+        ));
+        // missing keys use the standard rows
+        assert_eq!(geometry.row(Digit1), Row::Digits);
+        assert!(matches!(
+            Geometry::from_file(Path::new("files/missing.toml")),
+            Err(Error::Io(_))
+        ));
+    }
+}
@@ -1,7 +1,22 @@
-use strum;
-
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, strum::EnumIter)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialOrd,
+    Ord,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::Display,
+    strum::EnumIter,
+    strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "kebab-case")]
 pub enum Finger {
+    #[serde(rename = "left_pinkie", alias = "left_pinky")]
     LeftPinky = 1,
     LeftRing = 2,
     LeftMiddle = 3,
@@ -9,6 +24,7 @@ pub enum Finger {
     RightIndex = 5,
     RightMiddle = 6,
     RightRing = 7,
+    #[serde(rename = "right_pinkie", alias = "right_pinky")]
     RightPinky = 8,
     Thumb = 0,
 }
@@ -35,7 +51,7 @@ impl Finger {
         if f1.hand() != f2.hand() {
             return None;
         }
-        return Some((f1 as u32).abs_diff(f2 as u32));
+        Some((f1 as u32).abs_diff(f2 as u32))
     }
 
     pub fn roll_direction(&self, second_finger: Finger) -> RollDirection {
@@ -74,7 +90,10 @@ impl Finger {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, strum::EnumIter)]
+#[derive(
+    Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, serde::Serialize, strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
 pub enum Hand {
     Left,
     Right,
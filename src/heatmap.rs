@@ -0,0 +1,448 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    geometry::{Geometry, Row},
+    kalamine::{Layout, PhysicalKey, Symbol},
+    modifiers::{ALTGR, SHIFT},
+};
+
+/// A key of the heatmap, with its position in key units (x from left to right,
+/// y from top to bottom) and its usage
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeatmapKey {
+    pub key: PhysicalKey,
+    pub x: f32,
+    pub y: f32,
+    pub row: Row,
+    /// Symbols of the key, for each level (base, shift, altgr, shift+altgr)
+    pub symbols: [Option<Symbol>; 4],
+    pub usage: f32,
+    /// Usage relative to the most used key, between 0 and 1
+    pub intensity: f32,
+}
+
+impl HeatmapKey {
+    /// Symbol to display on the key: its base symbol, or the modifier it stands for
+    pub fn label(&self) -> String {
+        match (self.key, self.symbols[0]) {
+            (_, Some(Symbol::Character(c))) if c.is_whitespace() => String::from("␣"),
+            (_, Some(symbol)) => symbol.to_string(),
+            (PhysicalKey::ShiftLeft | PhysicalKey::ShiftRight, None) => SHIFT.to_string(),
+            (PhysicalKey::AltRight, None) => ALTGR.to_string(),
+            (_, None) => String::new(),
+        }
+    }
+}
+
+/// Usage of each key of the geometry that has one: the modifiers are only drawn
+/// when their presses are counted.
+/// Keys without a position (e.g. the thumb keys of the built-in geometries)
+/// are put on an extra row, centered under the keyboard.
+pub fn heatmap(
+    layout: &Layout,
+    geometry: &Geometry,
+    key_usage: &HashMap<PhysicalKey, f32>,
+) -> Vec<HeatmapKey> {
+    let mut keys: Vec<PhysicalKey> = geometry
+        .keys
+        .keys()
+        .filter(|key| key_usage.contains_key(key))
+        .copied()
+        .collect();
+    keys.sort();
+
+    let max_usage = keys
+        .iter()
+        .filter_map(|key| key_usage.get(key))
+        .fold(0.0, |max: f32, usage| max.max(*usage));
+    let positioned = keys.iter().filter_map(|key| {
+        let key_geometry = geometry.keys[key];
+        Some((key_geometry.x?, key_geometry.y?))
+    });
+    let (min_x, max_x, max_y) = positioned.fold(
+        (f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        |(min_x, max_x, max_y), (x, y)| (min_x.min(x), max_x.max(x), max_y.max(y)),
+    );
+    let unpositioned: Vec<PhysicalKey> = keys
+        .iter()
+        .copied()
+        .filter(|key| geometry.keys[key].x.is_none() || geometry.keys[key].y.is_none())
+        .collect();
+    let (min_x, max_x, max_y) = if min_x.is_finite() {
+        (min_x, max_x, max_y)
+    } else {
+        (0.0, unpositioned.len() as f32 - 1.0, -1.0)
+    };
+    let first_unpositioned_x = (min_x + max_x - unpositioned.len() as f32 + 1.0) / 2.0;
+
+    keys.iter()
+        .map(|key| {
+            let key_geometry = geometry.keys[key];
+            let (x, y) = match (key_geometry.x, key_geometry.y) {
+                (Some(x), Some(y)) => (x, y),
+                _ => {
+                    let i = unpositioned.iter().position(|k| k == key).unwrap_or(0);
+                    (first_unpositioned_x + i as f32, max_y + 1.0)
+                }
+            };
+            let symbols = match layout.keymap.get(key) {
+                Some(mapping) => mapping.map.map(|(_, symbol)| Symbol::filter_empty(symbol)),
+                None => [None; 4],
+            };
+            let usage = key_usage.get(key).copied().unwrap_or(0.0);
+            HeatmapKey {
+                key: *key,
+                x,
+                y,
+                row: geometry.row(*key),
+                symbols,
+                usage,
+                intensity: if max_usage > 0.0 {
+                    usage / max_usage
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect()
+}
+
+/// Color of a key in the heatmap, from pale yellow (unused) to red (most used)
+pub fn color(intensity: f32) -> [u8; 3] {
+    const GRADIENT: [[f32; 3]; 3] = [
+        [255.0, 255.0, 204.0],
+        [253.0, 141.0, 60.0],
+        [189.0, 0.0, 38.0],
+    ];
+    let intensity = intensity.clamp(0.0, 1.0) * (GRADIENT.len() - 1) as f32;
+    let i = (intensity.floor() as usize).min(GRADIENT.len() - 2);
+    let t = intensity - i as f32;
+    let (from, to) = (GRADIENT[i], GRADIENT[i + 1]);
+    [0, 1, 2].map(|c| (from[c] + (to[c] - from[c]) * t).round() as u8)
+}
+
+/// Width of a key unit in the text diagram, in characters
+const TEXT_KEY_WIDTH: f32 = 6.0;
+
+/// Keyboard diagram in text, with the label and usage of each key on two lines.
+/// Keys are grouped by row rather than placed by their vertical position,
+/// so the keys of columns with a different stagger never overlap.
+/// With `colored`, each key has a true color background for its usage.
+pub fn text(keys: &[HeatmapKey], colored: bool) -> String {
+    let min_x = keys.iter().fold(f32::INFINITY, |min, key| min.min(key.x));
+    let mut rows: Vec<Row> = keys.iter().map(|key| key.row).collect();
+    rows.sort_by(|r1, r2| r2.cmp(r1));
+    rows.dedup();
+
+    let mut text = String::new();
+    for row in rows {
+        let mut labels = Vec::new();
+        let mut usages = Vec::new();
+        for key in keys.iter().filter(|key| key.row == row) {
+            let column = ((key.x - min_x) * TEXT_KEY_WIDTH).round() as usize;
+            let (color, reset) = if colored {
+                let [r, g, b] = color(key.intensity);
+                (format!("\x1b[48;2;{r};{g};{b}m\x1b[38;2;0;0;0m"), "\x1b[0m")
+            } else {
+                (String::new(), "")
+            };
+            let label: String = key.label().chars().take(4).collect();
+            labels.push((column, format!("{color} {label:<4}{reset}")));
+            usages.push((column, format!("{color}{:>4.1} {reset}", key.usage)));
+        }
+        for mut cells in [labels, usages] {
+            cells.sort_by_key(|(column, _)| *column);
+            let mut line = String::new();
+            let mut width = 0;
+            for (column, cell) in cells {
+                line.push_str(&" ".repeat(column.saturating_sub(width)));
+                line.push_str(&cell);
+                width = column.max(width) + 5;
+            }
+            let _ = writeln!(text, "{}", line.trim_end());
+        }
+    }
+    text
+}
+
+/// Size of a key unit in the SVG, in pixels
+const SVG_KEY_SIZE: f32 = 60.0;
+const SVG_MARGIN: f32 = 10.0;
+
+/// Keys of the most frequent same finger bigrams, at most `count` of them.
+/// Bigrams with a key that is not on the heatmap are skipped.
+pub fn sfb_arrows(
+    keys: &[HeatmapKey],
+    list_sfb_keys: &[([PhysicalKey; 2], f32)],
+    count: usize,
+) -> Vec<([PhysicalKey; 2], f32)> {
+    let on_heatmap = |key: &PhysicalKey| keys.iter().any(|k| k.key == *key);
+    list_sfb_keys
+        .iter()
+        .filter(|(pair, _)| pair.iter().all(on_heatmap))
+        .take(count)
+        .copied()
+        .collect()
+}
+
+/// Keyboard diagram in SVG: each key shows its four levels (shift and altgr+shift
+/// on top, base and altgr at the bottom) and its usage, and is colored by usage.
+/// Arrows go from the first to the second key of each bigram,
+/// thicker for the more frequent ones.
+pub fn svg(keys: &[HeatmapKey], arrows: &[([PhysicalKey; 2], f32)]) -> String {
+    let min_x = keys.iter().fold(f32::INFINITY, |min, key| min.min(key.x));
+    let min_y = keys.iter().fold(f32::INFINITY, |min, key| min.min(key.y));
+    let max_x = keys
+        .iter()
+        .fold(f32::NEG_INFINITY, |max, key| max.max(key.x));
+    let max_y = keys
+        .iter()
+        .fold(f32::NEG_INFINITY, |max, key| max.max(key.y));
+    let (min_x, min_y, max_x, max_y) = if keys.is_empty() {
+        (0.0, 0.0, -1.0, -1.0)
+    } else {
+        (min_x, min_y, max_x, max_y)
+    };
+    let position = |key: &HeatmapKey| {
+        (
+            SVG_MARGIN + (key.x - min_x) * SVG_KEY_SIZE,
+            SVG_MARGIN + (key.y - min_y) * SVG_KEY_SIZE,
+        )
+    };
+    let width = (max_x - min_x + 1.0) * SVG_KEY_SIZE + 2.0 * SVG_MARGIN;
+    let height = (max_y - min_y + 1.0) * SVG_KEY_SIZE + 2.0 * SVG_MARGIN;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif">"#
+    );
+    let _ = writeln!(
+        svg,
+        r##"<defs><marker id="arrow" viewBox="0 0 10 10" refX="9" refY="5" markerWidth="4" markerHeight="4" orient="auto-start-reverse"><path d="M 0 0 L 10 5 L 0 10 z" fill="#1f5fa8"/></marker></defs>"##
+    );
+
+    let size = SVG_KEY_SIZE - 4.0;
+    for key in keys {
+        let (x, y) = position(key);
+        let [r, g, b] = color(key.intensity);
+        let _ = writeln!(svg, r#"<g class="key" id="{:?}">"#, key.key);
+        let _ = writeln!(
+            svg,
+            r##"<rect x="{}" y="{}" width="{size}" height="{size}" rx="5" fill="rgb({r},{g},{b})" stroke="#444"/>"##,
+            x + 2.0,
+            y + 2.0,
+        );
+        // base, shift, altgr, altgr+shift
+        let corners = [
+            (x + 8.0, y + size - 4.0, "start"),
+            (x + 8.0, y + 20.0, "start"),
+            (x + size - 4.0, y + size - 4.0, "end"),
+            (x + size - 4.0, y + 20.0, "end"),
+        ];
+        for (level, (tx, ty, anchor)) in corners.into_iter().enumerate() {
+            let label = match (level, key.symbols[level]) {
+                (0, _) => key.label(),
+                (_, Some(symbol)) => symbol.to_string(),
+                (_, None) => continue,
+            };
+            let (font_size, fill) = if level < 2 {
+                (16, "#000")
+            } else {
+                (12, "#1f5fa8")
+            };
+            let _ = writeln!(
+                svg,
+                r#"<text x="{tx}" y="{ty}" font-size="{font_size}" fill="{fill}" text-anchor="{anchor}">{}</text>"#,
+                xml_escape(&label)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r##"<text x="{}" y="{}" font-size="9" fill="#444" text-anchor="middle">{:.1}%</text>"##,
+            x + SVG_KEY_SIZE / 2.0,
+            y + SVG_KEY_SIZE / 2.0 + 4.0,
+            key.usage
+        );
+        svg.push_str("</g>\n");
+    }
+
+    let max_freq = arrows
+        .iter()
+        .fold(0.0, |max: f32, (_, freq)| max.max(*freq));
+    for ([key1, key2], freq) in arrows {
+        let (Some(k1), Some(k2)) = (
+            keys.iter().find(|k| k.key == *key1),
+            keys.iter().find(|k| k.key == *key2),
+        ) else {
+            continue;
+        };
+        let ((x1, y1), (x2, y2)) = (position(k1), position(k2));
+        let center = SVG_KEY_SIZE / 2.0;
+        let stroke_width = 1.0 + 4.0 * freq / max_freq;
+        let _ = writeln!(
+            svg,
+            r##"<line class="sfb" x1="{}" y1="{}" x2="{}" y2="{}" stroke="#1f5fa8" stroke-opacity="0.8" stroke-width="{stroke_width:.1}" marker-end="url(#arrow)"><title>{:.2}%</title></line>"##,
+            x1 + center,
+            y1 + center,
+            x2 + center,
+            y2 + center,
+            freq
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{ModMapping, PhysicalKey::*};
+
+    #[test]
+    fn heatmap_keys() {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyQ, ModMapping::from(vec!["a", "A"])),
+                (KeyW, ModMapping::from(vec!["*^"])),
+                (Space, ModMapping::from(vec![" "])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let mut key_usage = HashMap::from([
+            (KeyQ, 4.0),
+            (KeyW, 1.0),
+            (KeyE, 0.0),
+            (Space, 2.0),
+            (ShiftLeft, 0.5),
+            (AltRight, 0.5),
+        ]);
+        let keys = heatmap(&layout, &Geometry::from(BuiltinGeometry::ANSI), &key_usage);
+        let get = |key| keys.iter().find(|k| k.key == key).unwrap();
+
+        assert_eq!(get(KeyQ).intensity, 1.0);
+        assert_eq!(get(KeyW).intensity, 0.25);
+        assert_eq!(get(KeyE).intensity, 0.0);
+        assert_eq!((get(KeyQ).x, get(KeyQ).y), (1.5, 1.0));
+        assert_eq!(get(KeyQ).symbols[1], Some(Symbol::Character('A')));
+        assert_eq!(get(KeyQ).label(), "a");
+        assert_eq!(get(KeyW).label(), "*^");
+        assert_eq!(get(Space).label(), "␣");
+        assert_eq!(get(ShiftLeft).label(), "⇧");
+        // space and altgr are put under the lower row
+        assert_eq!(get(Space).y, 4.0);
+        assert_eq!(get(AltRight).x, get(Space).x + 1.0);
+        assert!(!keys.iter().any(|k| k.key == IntlBackslash));
+
+        // without a usage, e.g. modifiers that are not counted
+        key_usage.remove(&ShiftLeft);
+        let keys = heatmap(&layout, &Geometry::from(BuiltinGeometry::ANSI), &key_usage);
+        assert!(!keys.iter().any(|k| k.key == ShiftLeft));
+        assert_eq!(keys.len(), 5);
+    }
+
+    #[test]
+    fn heatmap_colors() {
+        assert_eq!(color(0.0), [255, 255, 204]);
+        assert_eq!(color(0.5), [253, 141, 60]);
+        assert_eq!(color(1.0), [189, 0, 38]);
+    }
+
+    #[test]
+    fn text_staggered_columns() {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyQ, ModMapping::from(vec!["q"])),
+                (KeyW, ModMapping::from(vec!["w"])),
+                (KeyA, ModMapping::from(vec!["a"])),
+                (KeyS, ModMapping::from(vec!["s"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        // the ring finger column is half a unit above the pinky one
+        let geometry: Geometry = toml::from_str(
+            r#"
+            [keys.KeyQ]
+            x = 0.0
+            y = 0.5
+            finger = "left_pinky"
+            row = "upper"
+
+            [keys.KeyW]
+            x = 1.0
+            y = 0.0
+            finger = "left_ring"
+            row = "upper"
+
+            [keys.KeyA]
+            x = 0.0
+            y = 1.5
+            finger = "left_pinky"
+            row = "middle"
+
+            [keys.KeyS]
+            x = 1.0
+            y = 1.0
+            finger = "left_ring"
+            row = "middle"
+            "#,
+        )
+        .unwrap();
+        let key_usage = HashMap::from([(KeyQ, 1.0), (KeyW, 2.0), (KeyA, 3.0), (KeyS, 4.0)]);
+        let keys = heatmap(&layout, &geometry, &key_usage);
+
+        assert_eq!(
+            text(&keys, false),
+            " q     w\n 1.0   2.0\n a     s\n 3.0   4.0\n"
+        );
+        assert!(text(&keys, true).contains("\x1b[48;2;189;0;38m"));
+    }
+
+    #[test]
+    fn svg_export() {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyQ, ModMapping::from(vec!["a", "A", "<", "*^"])),
+                (KeyA, ModMapping::from(vec!["b", "B"])),
+                (KeyW, ModMapping::from(vec!["c"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let key_usage = HashMap::from([(KeyQ, 3.0), (KeyA, 1.0)]);
+        let keys = heatmap(&layout, &Geometry::from(BuiltinGeometry::ANSI), &key_usage);
+
+        let list_sfb_keys = [
+            ([KeyA, KeyQ], 1.0),
+            ([KeyA, IntlBackslash], 0.5), // not on ANSI
+            ([KeyQ, KeyA], 0.25),
+        ];
+        let arrows = sfb_arrows(&keys, &list_sfb_keys, 1);
+        assert_eq!(arrows, vec![([KeyA, KeyQ], 1.0)]);
+
+        let svg = svg(&keys, &arrows);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), keys.len());
+        assert!(svg.contains(">&lt;</text>"));
+        assert!(svg.contains(">*^</text>"));
+        assert!(svg.contains(r#"fill="rgb(189,0,38)""#));
+        assert_eq!(svg.matches(r#"class="sfb""#).count(), 1);
+    }
+}
@@ -1,7 +1,10 @@
 mod corpus;
 mod layout;
+mod source;
 mod symbols;
 
 pub use corpus::Corpus;
 pub use layout::{Geometry, Layout, PhysicalKey};
 pub use symbols::{DeadKey, Mod, ModMapping, Symbol};
+
+pub(crate) use source::diacritic_table;
@@ -1,4 +1,17 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::Error;
+
+/// Largest distance of the skipgrams counted in texts
+pub const MAX_SKIPGRAM_DISTANCE: usize = 3;
 
 #[derive(Debug, PartialEq)]
 pub struct Corpus {
@@ -6,6 +19,241 @@ pub struct Corpus {
     pub symbols: HashMap<char, f32>,
     pub digrams: HashMap<[char; 2], f32>,
     pub trigrams: HashMap<[char; 3], f32>,
+    /// Pairs of characters with other characters between them, by distance
+    /// (the first table has one character between them, e.g. "ac" in "abc")
+    pub skipgrams: Vec<HashMap<[char; 2], f32>>,
+}
+
+impl Corpus {
+    /// Count the symbols, bigrams, trigrams and skipgrams of the texts, in percentages.
+    /// As in kalamine corpora, whitespace is left out. N-grams containing whitespace
+    /// are still counted in the total, so their percentages don't add up to 100.
+    /// Skipgrams are only left out if whitespace is at one of their ends.
+    /// N-grams don't span over two texts.
+    pub fn from_texts<'a>(path: &str, texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut symbols = NgramCount::default();
+        let mut digrams = NgramCount::default();
+        let mut trigrams = NgramCount::default();
+        let mut skipgrams: Vec<NgramCount<2>> = (0..MAX_SKIPGRAM_DISTANCE)
+            .map(|_| NgramCount::default())
+            .collect();
+
+        for text in texts {
+            let chars: Vec<char> = text.chars().collect();
+            symbols.add(&chars);
+            digrams.add(&chars);
+            trigrams.add(&chars);
+            for (i, count) in skipgrams.iter_mut().enumerate() {
+                count.add_skipgrams(&chars, i + 1);
+            }
+        }
+
+        Corpus {
+            path: String::from(path),
+            symbols: ngram_to_char(symbols.percentages()),
+            digrams: ngram_to_char_array(digrams.percentages()),
+            trigrams: ngram_to_char_array(trigrams.percentages()),
+            skipgrams: skipgrams
+                .into_iter()
+                .map(|count| ngram_to_char_array(count.percentages()))
+                .collect(),
+        }
+    }
+
+    /// Skipgrams of the corpus. Corpora without skipgrams (e.g. the kalamine ones)
+    /// get the ones of distance 1 from their trigrams, so without whitespace between them.
+    pub fn skipgrams_or_from_trigrams(&self) -> Vec<HashMap<[char; 2], f32>> {
+        if !self.skipgrams.is_empty() {
+            return self.skipgrams.clone();
+        }
+        let mut skipgrams: HashMap<[char; 2], f32> = HashMap::new();
+        for ([c1, _, c3], freq) in &self.trigrams {
+            *skipgrams.entry([*c1, *c3]).or_insert(0.0) += freq;
+        }
+        vec![skipgrams]
+    }
+
+    /// Build a corpus from UTF-8 text files. Directories are read recursively,
+    /// skipping the files that are not valid UTF-8.
+    pub fn from_paths(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut texts = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                read_dir_texts(path, &mut texts)?;
+            } else {
+                texts.push(fs::read_to_string(path).map_err(|err| with_path(err, path))?);
+            }
+        }
+        let name: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        Ok(Corpus::from_texts(
+            &name.join(" "),
+            texts.iter().map(String::as_str),
+        ))
+    }
+
+    /// Read a corpus generated by kalamine or by the `corpus` subcommand
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        let corpus: Corpus = serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|err| Error::InvalidCorpus(err.to_string()))?;
+        corpus.validate()?;
+        Ok(corpus)
+    }
+
+    /// Check that the frequencies are numbers, finite and not negative
+    pub fn validate(&self) -> Result<(), Error> {
+        let symbols = self.symbols.iter().map(|(c, f)| (String::from(*c), *f));
+        let digrams = self.digrams.iter().map(|(d, f)| (d.iter().collect(), *f));
+        let trigrams = self.trigrams.iter().map(|(t, f)| (t.iter().collect(), *f));
+        let skipgrams = self
+            .skipgrams
+            .iter()
+            .flatten()
+            .map(|(s, f)| (s.iter().collect(), *f));
+        for (ngram, freq) in symbols.chain(digrams).chain(trigrams).chain(skipgrams) {
+            if freq.is_nan() {
+                return Err(Error::NanFrequency(ngram));
+            }
+            if !freq.is_finite() || freq < 0.0 {
+                return Err(Error::InvalidCorpus(format!(
+                    "frequency of {ngram:?} is {freq}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Mix several corpora, e.g. to analyse a layout for a bilingual use.
+    /// The weights are normalized so they add up to 1, and must not be negative.
+    pub fn merge(corpora: &[(Corpus, f32)]) -> Result<Corpus, Error> {
+        if corpora
+            .iter()
+            .any(|(_, weight)| weight.is_nan() || *weight < 0.0)
+        {
+            return Err(Error::InvalidCorpus(String::from(
+                "the weights must not be negative",
+            )));
+        }
+        let total_weight: f32 = corpora.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 || total_weight.is_infinite() {
+            return Err(Error::InvalidCorpus(String::from(
+                "at least one weight must be positive",
+            )));
+        }
+        let weights: Vec<f32> = corpora
+            .iter()
+            .map(|(_, weight)| weight / total_weight)
+            .collect();
+
+        let path: Vec<String> = corpora
+            .iter()
+            .zip(weights.iter())
+            .map(|((corpus, _), weight)| format!("{}:{weight}", corpus.path))
+            .collect();
+        let mut merged = Corpus {
+            path: path.join(" "),
+            symbols: HashMap::new(),
+            digrams: HashMap::new(),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+
+        for ((corpus, _), weight) in corpora.iter().zip(weights) {
+            merge_freq(&mut merged.symbols, &corpus.symbols, weight);
+            merge_freq(&mut merged.digrams, &corpus.digrams, weight);
+            merge_freq(&mut merged.trigrams, &corpus.trigrams, weight);
+            // a corpus without skipgrams still has its share of the distance 1 ones
+            let skipgrams = corpus.skipgrams_or_from_trigrams();
+            if merged.skipgrams.len() < skipgrams.len() {
+                merged.skipgrams.resize(skipgrams.len(), HashMap::new());
+            }
+            for (merged, skipgrams) in merged.skipgrams.iter_mut().zip(&skipgrams) {
+                merge_freq(merged, skipgrams, weight);
+            }
+        }
+        Ok(merged)
+    }
+}
+
+fn merge_freq<K: Copy + Eq + Hash>(
+    merged: &mut HashMap<K, f32>,
+    freq: &HashMap<K, f32>,
+    weight: f32,
+) {
+    for (ngram, freq) in freq {
+        *merged.entry(*ngram).or_insert(0.0) += freq * weight;
+    }
+}
+
+fn read_dir_texts(dir: &Path, texts: &mut Vec<String>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|err| with_path(err, dir))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort(); // for reproducible results
+
+    for path in entries {
+        if path.is_dir() {
+            read_dir_texts(&path, texts)?;
+        } else {
+            match fs::read_to_string(&path) {
+                Ok(text) => texts.push(text),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Skipping {}: not UTF-8 text", path.display())
+                }
+                Err(err) => return Err(with_path(err, &path)),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+#[derive(Default)]
+struct NgramCount<const N: usize> {
+    counts: HashMap<Ngram<N>, usize>,
+    total: usize,
+}
+
+impl<const N: usize> NgramCount<N> {
+    fn add(&mut self, chars: &[char]) {
+        for window in chars.windows(N) {
+            let whitespace = window.iter().any(|c| c.is_whitespace());
+            if N > 1 || !whitespace {
+                self.total += 1;
+            }
+            if whitespace {
+                continue;
+            }
+            let ngram = Ngram(window.try_into().unwrap());
+            *self.counts.entry(ngram).or_insert(0) += 1;
+        }
+    }
+
+    fn percentages(self) -> HashMap<Ngram<N>, f32> {
+        let total = self.total as f64;
+        self.counts
+            .into_iter()
+            .map(|(ngram, count)| (ngram, (count as f64 * 100.0 / total) as f32))
+            .collect()
+    }
+}
+
+impl NgramCount<2> {
+    /// Count the pairs of characters with `distance` characters between them
+    fn add_skipgrams(&mut self, chars: &[char], distance: usize) {
+        for window in chars.windows(distance + 2) {
+            self.total += 1;
+            let pair = [window[0], window[distance + 1]];
+            if pair.iter().any(|c| c.is_whitespace()) {
+                continue;
+            }
+            *self.counts.entry(Ngram(pair)).or_insert(0) += 1;
+        }
+    }
 }
 
 fn ngram_to_char(map: HashMap<Ngram<1>, f32>) -> HashMap<char, f32> {
@@ -29,10 +277,70 @@ impl<'de> serde::Deserialize<'de> for Corpus {
             symbols: ngram_to_char(corpus.symbols),
             digrams: ngram_to_char_array(corpus.bigrams),
             trigrams: ngram_to_char_array(corpus.trigrams),
+            skipgrams: skipgrams_by_distance(corpus.skipgrams).map_err(serde::de::Error::custom)?,
         })
     }
 }
 
+/// Tables of skipgrams, from the ones of distance 1. Missing distances have an empty table.
+fn skipgrams_by_distance(
+    tables: BTreeMap<usize, HashMap<Ngram<2>, f32>>,
+) -> Result<Vec<HashMap<[char; 2], f32>>, String> {
+    let mut skipgrams: Vec<HashMap<[char; 2], f32>> = Vec::new();
+    for (distance, table) in tables {
+        if distance == 0 {
+            return Err(String::from("skipgram distances start at 1"));
+        }
+        skipgrams.resize(distance, HashMap::new());
+        skipgrams[distance - 1] = ngram_to_char_array(table);
+    }
+    Ok(skipgrams)
+}
+
+impl serde::Serialize for Corpus {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut corpus = s.serialize_struct("Corpus", 5)?;
+        corpus.serialize_field("corpus", &self.path)?;
+        corpus.serialize_field("symbols", &by_frequency(&self.symbols, |c| c.to_string()))?;
+        corpus.serialize_field(
+            "digrams",
+            &by_frequency(&self.digrams, |ngram| ngram.iter().collect()),
+        )?;
+        corpus.serialize_field(
+            "trigrams",
+            &by_frequency(&self.trigrams, |ngram| ngram.iter().collect()),
+        )?;
+        if self.skipgrams.is_empty() {
+            corpus.skip_field("skipgrams")?;
+        } else {
+            let skipgrams: BTreeMap<usize, ByFrequency> = self
+                .skipgrams
+                .iter()
+                .enumerate()
+                .map(|(i, table)| (i + 1, by_frequency(table, |ngram| ngram.iter().collect())))
+                .collect();
+            corpus.serialize_field("skipgrams", &skipgrams)?;
+        }
+        corpus.end()
+    }
+}
+
+/// N-grams serialized as a JSON object, most frequent first
+struct ByFrequency(Vec<(String, f32)>);
+
+fn by_frequency<K: Ord>(map: &HashMap<K, f32>, to_string: impl Fn(&K) -> String) -> ByFrequency {
+    let mut list: Vec<(&K, &f32)> = map.iter().collect();
+    list.sort_by(|(k1, f1), (k2, f2)| f2.total_cmp(f1).then(k1.cmp(k2)));
+    ByFrequency(list.into_iter().map(|(k, f)| (to_string(k), *f)).collect())
+}
+
+impl serde::Serialize for ByFrequency {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
 // The point of having an struct with wrapper for data is to do the validation
 // during deserialization, to have helpful error messages with the line/column number
 #[derive(Debug, serde::Deserialize)]
@@ -42,6 +350,8 @@ struct CorpusJSON {
     #[serde(alias = "digrams")]
     bigrams: HashMap<Ngram<2>, f32>,
     trigrams: HashMap<Ngram<3>, f32>,
+    #[serde(default)]
+    skipgrams: BTreeMap<usize, HashMap<Ngram<2>, f32>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -92,6 +402,7 @@ mod tests {
             symbols: HashMap::from([('a', 9.0), ('b', 8.0), ('c', 7.0)]),
             digrams: HashMap::from([(['a', 'b'], 6.0), (['b', 'c'], 5.0)]),
             trigrams: HashMap::from([(['a', 'b', 'c'], 4.0)]),
+            skipgrams: Vec::new(),
         };
         assert_eq!(corpus, expected);
     }
@@ -110,10 +421,155 @@ mod tests {
             symbols: HashMap::new(),
             digrams: HashMap::new(),
             trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
         };
         assert_eq!(corpus, expected);
     }
 
+    #[test]
+    fn corpus_from_texts() {
+        let corpus = Corpus::from_texts("text", ["abab a", "ba"]);
+        // 7 symbols, not counting whitespace
+        assert_eq!(
+            corpus.symbols,
+            HashMap::from([('a', 400.0 / 7.0), ('b', 300.0 / 7.0)])
+        );
+        // 6 bigrams, "b " and " a" left out
+        assert_eq!(
+            corpus.digrams,
+            HashMap::from([(['a', 'b'], 200.0 / 6.0), (['b', 'a'], 200.0 / 6.0)])
+        );
+        assert_eq!(
+            corpus.trigrams,
+            HashMap::from([(['a', 'b', 'a'], 25.0), (['b', 'a', 'b'], 25.0)])
+        );
+        // whitespace between the characters is kept: "b a"
+        assert_eq!(
+            corpus.skipgrams,
+            vec![
+                HashMap::from([(['a', 'a'], 25.0), (['b', 'b'], 25.0), (['b', 'a'], 25.0)]),
+                HashMap::from([(['a', 'b'], 100.0 / 3.0), (['a', 'a'], 100.0 / 3.0)]),
+                HashMap::from([(['b', 'a'], 50.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn corpus_skipgrams() {
+        let json = r#"{
+            "corpus": "text.txt",
+            "symbols": {},
+            "digrams": {},
+            "trigrams": { "abc": 4.0, "adc": 1.0 },
+            "skipgrams": { "2": { "ad": 3.0 } }
+        }"#;
+        let corpus: Corpus = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            corpus.skipgrams,
+            vec![HashMap::new(), HashMap::from([(['a', 'd'], 3.0)])]
+        );
+
+        let json = json.replace(r#""2":"#, r#""0":"#);
+        assert!(serde_json::from_str::<Corpus>(&json).is_err());
+
+        let corpus = Corpus {
+            skipgrams: Vec::new(),
+            ..corpus
+        };
+        assert_eq!(
+            corpus.skipgrams_or_from_trigrams(),
+            vec![HashMap::from([(['a', 'c'], 5.0)])]
+        );
+    }
+
+    #[test]
+    fn corpus_roundtrip() {
+        let corpus = Corpus::from_texts("text", ["hello world"]);
+        let json = serde_json::to_string(&corpus).unwrap();
+        assert!(json.starts_with(r#"{"corpus":"text","symbols":{"l":"#));
+        let deserialized: Corpus = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, corpus);
+    }
+
+    #[test]
+    fn merge_corpora() {
+        let en = Corpus {
+            path: String::from("en"),
+            symbols: HashMap::from([('a', 60.0), ('b', 40.0)]),
+            digrams: HashMap::from([(['a', 'b'], 10.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let fr = Corpus {
+            path: String::from("fr"),
+            symbols: HashMap::from([('a', 20.0), ('é', 80.0)]),
+            digrams: HashMap::new(),
+            trigrams: HashMap::from([(['a', 'é', 'a'], 5.0)]),
+            skipgrams: Vec::new(),
+        };
+        let merged = Corpus::merge(&[(en, 1.0), (fr, 3.0)]).unwrap();
+        let expected = Corpus {
+            path: String::from("en:0.25 fr:0.75"),
+            symbols: HashMap::from([('a', 30.0), ('b', 10.0), ('é', 60.0)]),
+            digrams: HashMap::from([(['a', 'b'], 2.5)]),
+            trigrams: HashMap::from([(['a', 'é', 'a'], 3.75)]),
+            skipgrams: vec![HashMap::from([(['a', 'a'], 3.75)])],
+        };
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn merge_corpora_with_and_without_skipgrams() {
+        let with_skipgrams = Corpus {
+            path: String::from("text"),
+            symbols: HashMap::new(),
+            digrams: HashMap::new(),
+            trigrams: HashMap::from([(['a', 'b', 'c'], 4.0)]),
+            skipgrams: vec![
+                HashMap::from([(['a', 'c'], 4.0)]),
+                HashMap::from([(['a', 'd'], 2.0)]),
+            ],
+        };
+        let kalamine = Corpus {
+            path: String::from("kalamine"),
+            symbols: HashMap::new(),
+            digrams: HashMap::new(),
+            trigrams: HashMap::from([(['x', 'b', 'c'], 8.0)]),
+            skipgrams: Vec::new(),
+        };
+        let merged = Corpus::merge(&[(with_skipgrams, 1.0), (kalamine, 1.0)]).unwrap();
+        assert_eq!(
+            merged.skipgrams,
+            vec![
+                HashMap::from([(['a', 'c'], 2.0), (['x', 'c'], 4.0)]),
+                HashMap::from([(['a', 'd'], 1.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_corpus() {
+        let corpus = |freq| Corpus {
+            path: String::from("text"),
+            symbols: HashMap::from([('a', 60.0)]),
+            digrams: HashMap::from([(['a', 'b'], freq)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        assert!(corpus(10.0).validate().is_ok());
+        assert!(
+            matches!(corpus(f32::NAN).validate(), Err(Error::NanFrequency(ngram)) if ngram == "ab")
+        );
+        assert!(matches!(
+            corpus(-1.0).validate(),
+            Err(Error::InvalidCorpus(_))
+        ));
+        assert!(matches!(
+            Corpus::merge(&[(corpus(1.0), 0.0), (corpus(2.0), 0.0)]),
+            Err(Error::InvalidCorpus(_))
+        ));
+    }
+
     #[test]
     fn deserialize_corpus_invalid_ngram() {
         let json = r#"{
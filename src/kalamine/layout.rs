@@ -1,20 +1,79 @@
 use crate::{
+    formats::{keylayout, klc, xkb},
     geometry::Row,
     hands::{Finger, Hand},
     kalamine::symbols::{DeadKey, ModMapping, Symbol},
+    Error,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
 };
-use std::collections::HashMap;
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Layout {
     // name: String,
     // description: String,
     // geometry: Geometry,
+    #[serde(serialize_with = "serialize_sorted")]
     pub keymap: HashMap<PhysicalKey, ModMapping>,
+    #[serde(serialize_with = "serialize_deadkeys")]
     pub deadkeys: HashMap<DeadKey, HashMap<Symbol, Symbol>>,
     // altgr: bool,
 }
 
+/// Sort the keys, so the written layouts are stable and readable
+fn serialize_sorted<S: serde::Serializer, K: Ord + Serialize, V: Serialize>(
+    map: &HashMap<K, V>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
+
+fn serialize_deadkeys<S: serde::Serializer>(
+    deadkeys: &HashMap<DeadKey, HashMap<Symbol, Symbol>>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<_, BTreeMap<_, _>> = deadkeys
+        .iter()
+        .map(|(dk, layer)| (dk, layer.iter().collect()))
+        .collect();
+    s.collect_map(sorted)
+}
+
+impl Layout {
+    /// Read a layout file: a kalamine source if the extension is `.toml`,
+    /// the JSON generated by kalamine, a Windows `.klc` file, a macOS `.keylayout`
+    /// file or an XKB symbols file.
+    /// The section of an XKB file can be given after its path, e.g. `symbols/fr(bepo)`.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            let name = path.to_string_lossy();
+            if let Some((file, variant)) = name.strip_suffix(')').and_then(|n| n.rsplit_once('(')) {
+                let compose = xkb::compose_file();
+                return xkb::read_symbols(Path::new(file), Some(variant), compose.as_deref());
+            }
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("klc") => return klc::read_klc(path),
+            Some("keylayout") => return keylayout::read_keylayout(path),
+            _ => {}
+        }
+        let content = fs::read_to_string(path)?;
+        let json = |content: &str| {
+            serde_json::from_str(content).map_err(|err| Error::InvalidLayout(err.to_string()))
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_kalamine_toml(&content).map_err(Error::InvalidLayout),
+            Some("json") => json(&content),
+            _ if content.trim_start().starts_with('{') => json(&content),
+            _ => xkb::read_symbols(path, None, xkb::compose_file().as_deref()),
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Geometry {
@@ -24,7 +83,18 @@ pub enum Geometry {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Hash, serde::Deserialize, strum::EnumIter,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    PartialOrd,
+    Eq,
+    Ord,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+    strum::EnumIter,
+    strum::EnumString,
 )]
 pub enum PhysicalKey {
     KeyQ,
@@ -76,15 +146,18 @@ pub enum PhysicalKey {
     Backquote,
     Backslash,
     IntlBackslash,
+    ShiftLeft,
+    ShiftRight,
+    AltRight,
 }
 
 impl PhysicalKey {
+    /// Standard touch typing finger, see `Geometry::finger` for other geometries
     pub fn finger(&self) -> Finger {
-        // TODO: depend on geometry (opti) / angle mod
         use PhysicalKey::*;
         match self {
-            Space => Finger::Thumb,
-            Digit1 | KeyQ | KeyA | KeyZ | IntlBackslash => Finger::LeftPinky,
+            Space | AltRight => Finger::Thumb,
+            Digit1 | KeyQ | KeyA | KeyZ | IntlBackslash | ShiftLeft => Finger::LeftPinky,
             Digit2 | KeyW | KeyS | KeyX => Finger::LeftRing,
             Digit3 | KeyE | KeyD | KeyC => Finger::LeftMiddle,
             Digit4 | KeyR | KeyF | KeyV | Digit5 | KeyT | KeyG | KeyB => Finger::LeftIndex,
@@ -92,7 +165,7 @@ impl PhysicalKey {
             Digit8 | KeyI | KeyK | Comma => Finger::RightMiddle,
             Digit9 | KeyO | KeyL | Period => Finger::RightRing,
             Digit0 | KeyP | Semicolon | Slash | Minus | Equal | BracketLeft | BracketRight
-            | Quote | Backquote | Backslash => Finger::RightPinky,
+            | Quote | Backquote | Backslash | ShiftRight => Finger::RightPinky,
         }
     }
 
@@ -106,13 +179,48 @@ impl PhysicalKey {
             | KeyP | BracketLeft | BracketRight | Backslash => Row::Upper,
             KeyA | KeyS | KeyD | KeyF | KeyG | KeyH | KeyJ
             | KeyK | KeyL | Semicolon | Quote => Row::Middle,
-            IntlBackslash | KeyZ | KeyX | KeyC | KeyV | KeyB
-            | KeyN | KeyM | Comma | Period | Slash => Row::Lower,
-            Space => Row::Spacebar,
+            ShiftLeft | IntlBackslash | KeyZ | KeyX | KeyC | KeyV | KeyB
+            | KeyN | KeyM | Comma | Period | Slash | ShiftRight => Row::Lower,
+            Space | AltRight => Row::Spacebar,
         }
     }
 
     pub fn hand(&self) -> Hand {
         self.finger().hand()
     }
+
+    /// Shift and AltGr, only pressed when the modifiers are counted
+    pub fn is_modifier(&self) -> bool {
+        use PhysicalKey::*;
+        matches!(self, ShiftLeft | ShiftRight | AltRight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn serialize_layout_roundtrip() {
+        let json = r#"{
+            "keymap": {
+                "KeyQ": ["q", "Q", "", "*^"],
+                "KeyW": ["*^", "w"],
+                "Space": [" "]
+            },
+            "deadkeys": {
+                "*^": { "q": "ĝ", "*^": "^", " ": "^" }
+            }
+        }"#;
+        let layout: Layout = serde_json::from_str(json).unwrap();
+        let written = serde_json::to_string(&layout).unwrap();
+        assert_eq!(
+            written,
+            r#"{"keymap":{"KeyQ":["q","Q","","*^"],"KeyW":["*^","w"],"Space":[" "]},"#.to_owned()
+                + r#""deadkeys":{"*^":{" ":"^","q":"ĝ","*^":"^"}}}"#
+        );
+        let read_back: Layout = serde_json::from_str(&written).unwrap();
+        assert_eq!(read_back, layout);
+    }
 }
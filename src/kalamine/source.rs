@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use crate::kalamine::{
+    layout::{Layout, PhysicalKey},
+    symbols::{DeadKey, Mod, ModMapping, Symbol},
+};
+
+/// The "one dead key" of kalamine layouts, written `**`
+const ODK: DeadKey = DeadKey { name: '*' };
+
+/// Symbols drawn in the key grids for keys that are not part of the layout
+const SPECIAL_KEYS: [char; 5] = ['⌫', '↹', '⇬', '⏎', '⇧'];
+
+/// A kalamine `.toml` layout source.
+/// Only the fields needed for the analysis are read, the metadata is ignored.
+#[derive(Debug, serde::Deserialize)]
+struct Source {
+    geometry: Option<String>,
+    base: Option<String>,
+    full: Option<String>,
+    altgr: Option<String>,
+    #[serde(default)]
+    spacebar: HashMap<String, String>,
+}
+
+/// Levels of a key read from a grid: the main symbols on the left of the key,
+/// and the secondary ones (1dk or altgr) on the right
+#[derive(Debug, Default, Clone, Copy)]
+struct GridKey {
+    base: Option<Symbol>,
+    shift: Option<Symbol>,
+    second_base: Option<Symbol>,
+    second_shift: Option<Symbol>,
+}
+
+impl Layout {
+    /// Read a layout from the source of a kalamine layout, in TOML.
+    /// The 1dk table comes from the `base` grid, the other dead keys
+    /// are built by combining their diacritic with the characters of the layout.
+    pub fn from_kalamine_toml(content: &str) -> Result<Self, String> {
+        let source: Source = toml::from_str(content).map_err(|err| err.to_string())?;
+        let rows = grid_rows(source.geometry.as_deref().unwrap_or("ISO"))?;
+
+        let mut levels: HashMap<PhysicalKey, [Option<Symbol>; 4]> = HashMap::new();
+        let mut odk_levels: HashMap<PhysicalKey, [Option<Symbol>; 2]> = HashMap::new();
+        match (&source.base, &source.full) {
+            (Some(base), None) => {
+                for (key, grid_key) in parse_grid(base, &rows) {
+                    let level = levels.entry(key).or_default();
+                    (level[0], level[1]) = (grid_key.base, grid_key.shift);
+                    if grid_key.second_base.is_some() || grid_key.second_shift.is_some() {
+                        odk_levels.insert(key, [grid_key.second_base, grid_key.second_shift]);
+                    }
+                }
+            }
+            (None, Some(full)) => {
+                for (key, grid_key) in parse_grid(full, &rows) {
+                    levels.insert(
+                        key,
+                        [
+                            grid_key.base,
+                            grid_key.shift,
+                            grid_key.second_base,
+                            grid_key.second_shift,
+                        ],
+                    );
+                }
+            }
+            (Some(_), Some(_)) => return Err(String::from("Both 'base' and 'full' are defined")),
+            (None, None) => return Err(String::from("Missing 'base' or 'full' key grid")),
+        }
+        if let Some(altgr) = &source.altgr {
+            for (key, grid_key) in parse_grid(altgr, &rows) {
+                let level = levels.entry(key).or_default();
+                (level[2], level[3]) = (grid_key.second_base, grid_key.second_shift);
+            }
+        }
+        // in the main layer, a key with only a shift symbol has its lowercase on base
+        for level in levels.values_mut() {
+            if let (None, Some(shift)) = (level[0], level[1]) {
+                level[0] = Some(match shift {
+                    Symbol::Character(c) => Symbol::Character(lowercase(c)),
+                    dead_key => dead_key,
+                });
+            }
+        }
+
+        let has_altgr = source.full.is_some() || source.altgr.is_some();
+        let spacebar = |name: &str, default: Option<char>| -> Result<Option<Symbol>, String> {
+            let mut chars = match source.spacebar.get(name) {
+                Some(value) => value.chars(),
+                None => return Ok(default.map(Symbol::Character)),
+            };
+            match (chars.next(), chars.next()) {
+                (None, _) => Ok(None),
+                (Some(c), None) => Ok(Some(Symbol::Character(c))),
+                _ => Err(format!("Invalid spacebar symbol for '{name}'")),
+            }
+        };
+        let altgr_default = has_altgr.then_some(' ');
+        levels.insert(
+            PhysicalKey::Space,
+            [
+                Some(Symbol::Character(' ')),
+                spacebar("shift", Some(' '))?,
+                spacebar("altgr", altgr_default)?,
+                spacebar("altgr_shift", altgr_default)?,
+            ],
+        );
+        let odk_space = spacebar("1dk", Some('\''))?;
+        let odk_shift_space = match source.spacebar.contains_key("shift_1dk") {
+            true => spacebar("shift_1dk", None)?,
+            false => spacebar("1dk_shift", None)?.or(odk_space),
+        };
+        odk_levels.insert(PhysicalKey::Space, [odk_space, odk_shift_space]);
+
+        let keymap: HashMap<PhysicalKey, ModMapping> = levels
+            .into_iter()
+            .filter(|(_, level)| level.iter().any(Option::is_some))
+            .map(|(key, [base, shift, altgr, altgr_shift])| {
+                let map = [
+                    (Mod::Base, base),
+                    (Mod::Shift, shift),
+                    (Mod::Altgr, altgr),
+                    (Mod::AltgrShift, altgr_shift),
+                ];
+                (key, ModMapping { map })
+            })
+            .collect();
+
+        let mut deadkeys = HashMap::new();
+        let symbols = || {
+            keymap
+                .values()
+                .flat_map(|mapping| mapping.map.iter().filter_map(|(_, symbol)| *symbol))
+        };
+        if symbols().any(|symbol| symbol == Symbol::DeadKey(ODK.name)) {
+            let mut table = HashMap::new();
+            for (key, odk_level) in odk_levels.iter() {
+                let Some(mapping) = keymap.get(key) else {
+                    continue;
+                };
+                for (level, odk_symbol) in odk_level.iter().enumerate() {
+                    if let (Some(symbol), Some(odk_symbol)) = (mapping.map[level].1, odk_symbol) {
+                        table.insert(symbol, *odk_symbol);
+                    }
+                }
+            }
+            deadkeys.insert(ODK, table);
+        }
+        let mut dead_symbols: Vec<Symbol> = symbols()
+            .chain(deadkeys.values().flat_map(|table| table.values().copied()))
+            .collect();
+        dead_symbols.sort();
+        dead_symbols.dedup();
+        for symbol in dead_symbols {
+            let Symbol::DeadKey(name) = symbol else {
+                continue;
+            };
+            if name != ODK.name {
+                let table = diacritic_table(name, symbols());
+                deadkeys.insert(DeadKey { name }, table);
+            }
+        }
+
+        Ok(Layout { keymap, deadkeys })
+    }
+}
+
+/// Keys of each row of the grid, for a kalamine geometry
+fn grid_rows(geometry: &str) -> Result<[Vec<PhysicalKey>; 4], String> {
+    use PhysicalKey::*;
+    let ansi = match geometry.to_uppercase().as_str() {
+        "ANSI" => true,
+        "ISO" | "ERGO" => false,
+        _ => return Err(format!("Unsupported kalamine geometry: {geometry}")),
+    };
+    #[rustfmt::skip]
+    let mut rows = [
+        vec![Backquote, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6,
+             Digit7, Digit8, Digit9, Digit0, Minus, Equal],
+        vec![KeyQ, KeyW, KeyE, KeyR, KeyT, KeyY, KeyU, KeyI, KeyO, KeyP,
+             BracketLeft, BracketRight],
+        vec![KeyA, KeyS, KeyD, KeyF, KeyG, KeyH, KeyJ, KeyK, KeyL, Semicolon, Quote],
+        vec![KeyZ, KeyX, KeyC, KeyV, KeyB, KeyN, KeyM, Comma, Period, Slash],
+    ];
+    // ANSI keyboards have the backslash on the upper row, and no "<>" key
+    if ansi {
+        rows[1].push(Backslash);
+    } else {
+        rows[2].push(Backslash);
+        rows[3].insert(0, IntlBackslash);
+    }
+    Ok(rows)
+}
+
+/// Symbols of the keys of a kalamine grid.
+/// Each row of keys is drawn on two lines (shift, then base), between borders.
+/// In each key, the main symbol is on the second column and the secondary one on
+/// the fourth, with a `*` just before the symbol for dead keys.
+fn parse_grid(grid: &str, rows: &[Vec<PhysicalKey>]) -> Vec<(PhysicalKey, GridKey)> {
+    let lines: Vec<Vec<char>> = grid
+        .lines()
+        .filter(|line| is_content_line(line))
+        .map(|line| line.chars().collect())
+        .collect();
+
+    let mut keys = Vec::new();
+    for (row, pair) in rows.iter().zip(lines.chunks_exact(2)) {
+        let (upper, lower) = (&pair[0], &pair[1]);
+        let separators: Vec<usize> = (0..upper.len())
+            .filter(|i| is_separator(upper[*i]))
+            .collect();
+        let cells = separators
+            .windows(2)
+            .map(|w| (w[0] + 1, w[1]))
+            .filter(|(start, end)| end - start >= 5)
+            .filter(|(start, end)| {
+                let special = |line: &Vec<char>| {
+                    line.get(*start..*end)
+                        .is_some_and(|cell| cell.iter().any(|c| SPECIAL_KEYS.contains(c)))
+                };
+                !special(upper) && !special(lower)
+            });
+        for (key, (start, _)) in row.iter().zip(cells) {
+            let grid_key = GridKey {
+                base: grid_symbol(lower, start + 1),
+                shift: grid_symbol(upper, start + 1),
+                second_base: grid_symbol(lower, start + 3),
+                second_shift: grid_symbol(upper, start + 3),
+            };
+            keys.push((*key, grid_key));
+        }
+    }
+    keys
+}
+
+fn grid_symbol(line: &[char], i: usize) -> Option<Symbol> {
+    match (line.get(i - 1), line.get(i)) {
+        (_, None | Some(' ')) => None,
+        (Some('*'), Some(c)) => Some(Symbol::DeadKey(*c)),
+        (_, Some(c)) => Some(Symbol::Character(*c)),
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '║')
+}
+
+/// Lines of the grid with the symbols of the keys, as opposed to the borders
+fn is_content_line(line: &str) -> bool {
+    line.chars().any(is_separator)
+        && !line
+            .chars()
+            .any(|c| ('\u{2500}'..='\u{257f}').contains(&c) && !is_separator(c))
+}
+
+fn lowercase(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// Combining character of the diacritic of a dead key
+fn combining_character(dead_key: char) -> Option<char> {
+    Some(match dead_key {
+        '`' => '\u{300}',
+        '´' => '\u{301}',
+        '^' => '\u{302}',
+        '~' => '\u{303}',
+        '¯' => '\u{304}',
+        '˘' => '\u{306}',
+        '˙' => '\u{307}',
+        '¨' => '\u{308}',
+        '˚' => '\u{30a}',
+        '”' => '\u{30b}',
+        'ˇ' => '\u{30c}',
+        ',' => '\u{326}',
+        '¸' => '\u{327}',
+        '˛' => '\u{328}',
+        _ => return None,
+    })
+}
+
+/// Output of a diacritic dead key for each character of the layout.
+/// The dead key itself and the spaces give the diacritic alone.
+pub(crate) fn diacritic_table(
+    name: char,
+    symbols: impl Iterator<Item = Symbol>,
+) -> HashMap<Symbol, Symbol> {
+    let combining = combining_character(name);
+    if combining.is_none() {
+        log::warn!("Unknown dead key *{name}, only its own key and the spaces are defined");
+    }
+    let mut table = HashMap::from([(Symbol::DeadKey(name), Symbol::Character(name))]);
+    for symbol in symbols {
+        let Symbol::Character(c) = symbol else {
+            continue;
+        };
+        let output = match combining {
+            _ if c.is_whitespace() => Some(name),
+            Some(combining) => unicode_normalization::char::compose(c, combining),
+            None => None,
+        };
+        if let Some(output) = output {
+            table.insert(symbol, Symbol::Character(output));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use PhysicalKey::*;
+
+    const ERGO_BASE: &str = r#"
+        geometry = "ERGO"
+        name = "test"
+        description = "test layout"
+        base = '''
+        ╭╌╌╌╌╌┰─────┬─────┬─────┬─────┬─────┰─────┬─────┬─────┬─────┬─────┰╌╌╌╌╌┬╌╌╌╌╌╮
+        ┆ ~   ┃ !   │ @   │ #   │ $   │ %   ┃ ^   │ &   │ *   │ (   │ )   ┃ _   ┆ +   ┆
+        ┆ `   ┃ 1 „ │ 2 “ │ 3 ” │ 4   │ 5   ┃ 6   │ 7   │ 8   │ 9   │ 0   ┃ -   ┆ =   ┆
+        ╰╌╌╌╌╌╂─────┼─────┼─────┼─────┼─────╂─────┼─────┼─────┼─────┼─────╂╌╌╌╌╌┼╌╌╌╌╌┤
+              ┃ Q   │ C   │ O   │ P   │ W   ┃ J   │ M   │ D   │**   │ Y   ┃ [   ┆ ]   ┆
+              ┃   â │   ç │   œ │   ô │     ┃     │     │     │  *¨ │     ┃     ┆     ┆
+              ╂─────┼─────┼─────┼─────┼─────╂─────┼─────┼─────┼─────┼─────╂╌╌╌╌╌┼╌╌╌╌╌┤
+              ┃ A Â │ S   │ E   │ N   │ F   ┃ L   │ R   │ T   │ I   │ U   ┃*¨   ┆ \   ┆
+              ┃   à │   é │   è │   ê │     ┃     │     │     │     │     ┃*^   ┆     ┆
+        '''
+
+        [spacebar]
+        shift = "\u202F"
+        1dk = "’"
+    "#;
+
+    #[test]
+    fn kalamine_toml_base() {
+        let layout = Layout::from_kalamine_toml(ERGO_BASE).unwrap();
+        let keymap = &layout.keymap;
+        let c = Symbol::Character;
+        assert_eq!(keymap[&KeyQ], ModMapping::from(vec!["q", "Q"]));
+        assert_eq!(keymap[&Digit1], ModMapping::from(vec!["1", "!"]));
+        assert_eq!(keymap[&KeyO], ModMapping::from(vec!["**", "**"]));
+        assert_eq!(keymap[&Quote], ModMapping::from(vec!["*^", "*¨"]));
+        assert_eq!(keymap[&Backslash], ModMapping::from(vec!["\\\\", "\\\\"]));
+        assert_eq!(keymap[&Space], ModMapping::from(vec![" ", "\u{202f}"]));
+        assert!(!keymap.contains_key(&IntlBackslash));
+
+        let odk = &layout.deadkeys[&ODK];
+        assert_eq!(odk[&c('q')], c('â'));
+        assert_eq!(odk[&c('a')], c('à'));
+        assert_eq!(odk[&c('A')], c('Â'));
+        assert_eq!(odk[&c('1')], c('„'));
+        assert_eq!(odk[&Symbol::DeadKey('*')], Symbol::DeadKey('¨'));
+        assert_eq!(odk[&c(' ')], c('’'));
+        assert_eq!(odk[&c('\u{202f}')], c('’'));
+
+        let circumflex = &layout.deadkeys[&DeadKey { name: '^' }];
+        assert_eq!(circumflex[&c('e')], c('ê'));
+        assert_eq!(circumflex[&c('W')], c('Ŵ'));
+        assert_eq!(circumflex[&Symbol::DeadKey('^')], c('^'));
+        assert_eq!(circumflex[&c(' ')], c('^'));
+        assert!(!circumflex.contains_key(&c('m')));
+        let diaeresis = &layout.deadkeys[&DeadKey { name: '¨' }];
+        assert_eq!(diaeresis[&c('u')], c('ü'));
+    }
+
+    #[test]
+    fn kalamine_toml_full() {
+        let toml = r#"
+            geometry = "ANSI"
+            full = '''
+            ┌─────┰─────┬─────┲━━━━━━━━━━┓
+            │ ~   ┃ !   │ @   ┃          ┃
+            │ `   ┃ 1 ¹ │ 2 ² ┃ ⌫        ┃
+            ┢━━━━━┷━┱───┴─┬───┴─┬────────┨
+            ┃       ┃ Q Ä │ W   │ E      ┃
+            ┃ ↹     ┃ q ä │ w   │ e   €  ┃
+            ┗━━━━━━━┹─────┴─────┴────────┛
+            '''
+        "#;
+        let layout = Layout::from_kalamine_toml(toml).unwrap();
+        assert_eq!(
+            layout.keymap[&Digit1],
+            ModMapping::from(vec!["1", "!", "¹"])
+        );
+        assert_eq!(
+            layout.keymap[&KeyQ],
+            ModMapping::from(vec!["q", "Q", "ä", "Ä"])
+        );
+        assert_eq!(
+            layout.keymap[&Digit2],
+            ModMapping::from(vec!["2", "@", "²"])
+        );
+        // wide keys are only read on their first columns
+        assert_eq!(layout.keymap[&KeyE], ModMapping::from(vec!["e", "E"]));
+        assert_eq!(
+            layout.keymap[&Space],
+            ModMapping::from(vec![" ", " ", " ", " "])
+        );
+        assert!(layout.deadkeys.is_empty());
+    }
+
+    #[test]
+    fn kalamine_toml_errors() {
+        assert!(Layout::from_kalamine_toml("name = 'empty'").is_err());
+        assert!(Layout::from_kalamine_toml("geometry = 'JIS'\nbase = ''").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use serde::Deserializer;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum Symbol {
     Character(char),
     DeadKey(char),
@@ -22,21 +22,35 @@ impl std::fmt::Display for Symbol {
     }
 }
 
-impl<'de> serde::Deserialize<'de> for Symbol {
-    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
-        let s = String::deserialize(d)?;
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl std::str::FromStr for Symbol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut chars = s.chars();
 
         match (chars.next(), chars.next(), chars.next()) {
             (None, _, _) => Ok(Symbol::Character('\x00')), // Sentinel value for empty string
             (Some(first), None, _) => Ok(Symbol::Character(first)),
             (Some('*'), Some(second), None) => Ok(Symbol::DeadKey(second)),
-            _ => Err(serde::de::Error::custom(format!("Invalid symbol: {s}"))),
+            _ => Err(format!("Invalid symbol: {s}")),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct DeadKey {
     pub name: char,
 }
@@ -47,6 +61,12 @@ impl std::fmt::Display for DeadKey {
     }
 }
 
+impl serde::Serialize for DeadKey {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for DeadKey {
     fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         let s = String::deserialize(d)?;
@@ -59,7 +79,7 @@ impl<'de> serde::Deserialize<'de> for DeadKey {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 pub enum Mod {
     Base,
     Shift,
@@ -78,20 +98,24 @@ impl Mod {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ModMapping {
     pub map: [(Mod, Option<Symbol>); 4],
 }
 
 impl ModMapping {
     // helper function to easily create an instance in the tests
+    #[cfg(test)]
     pub fn from<T: AsRef<str>>(vec: Vec<T>) -> Self {
         let symbols: Vec<Symbol> = vec
             .into_iter()
             .map(|s| serde_json::from_str(&format!(r#""{}""#, s.as_ref())))
             .collect::<Result<_, _>>()
             .unwrap();
+        Self::from_symbols(symbols)
+    }
 
+    fn from_symbols(symbols: Vec<Symbol>) -> Self {
         let mut iter = symbols.into_iter();
         ModMapping {
             map: [
@@ -104,6 +128,41 @@ impl ModMapping {
     }
 }
 
+/// Levels of a key from their symbols, e.g. `["a", "A", "", "*^"]`
+impl<T: AsRef<str>> TryFrom<Vec<T>> for ModMapping {
+    type Error = crate::Error;
+
+    fn try_from(levels: Vec<T>) -> Result<Self, Self::Error> {
+        if levels.len() > 4 {
+            return Err(crate::Error::InvalidLayout(format!(
+                "{} levels, at most 4 are supported",
+                levels.len()
+            )));
+        }
+        let symbols: Vec<Symbol> = levels
+            .iter()
+            .map(|s| s.as_ref().parse())
+            .collect::<Result<_, _>>()
+            .map_err(crate::Error::InvalidLayout)?;
+        Ok(Self::from_symbols(symbols))
+    }
+}
+
+impl serde::Serialize for ModMapping {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        // Empty levels are written as empty strings, except the trailing ones
+        let len = self
+            .map
+            .iter()
+            .rposition(|(_, symbol)| symbol.is_some())
+            .map_or(0, |i| i + 1);
+        s.collect_seq(self.map[..len].iter().map(|(_, symbol)| match symbol {
+            Some(symbol) => symbol.to_string(),
+            None => String::new(),
+        }))
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for ModMapping {
     fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         let vec: Vec<Symbol> = Vec::deserialize(d)?;
@@ -115,16 +174,7 @@ impl<'de> serde::Deserialize<'de> for ModMapping {
             ));
         }
 
-        let mut iter = vec.into_iter();
-
-        Ok(ModMapping {
-            map: [
-                (Mod::Base, Symbol::filter_empty(iter.next())),
-                (Mod::Shift, Symbol::filter_empty(iter.next())),
-                (Mod::Altgr, Symbol::filter_empty(iter.next())),
-                (Mod::AltgrShift, Symbol::filter_empty(iter.next())),
-            ],
-        })
+        Ok(ModMapping::from_symbols(vec))
     }
 }
 
@@ -232,6 +282,28 @@ mod tests {
         assert_eq!(symbols, expected);
     }
 
+    #[test]
+    fn layers_from_strings() {
+        let symbols = ModMapping::try_from(vec!["**", "", "a"]).unwrap();
+        assert_eq!(symbols, serde_json::from_str(r#"["**", "", "a"]"#).unwrap());
+        assert!(ModMapping::try_from(vec!["ab"]).is_err());
+        assert!(ModMapping::try_from(vec!["a", "b", "c", "d", "e"]).is_err());
+    }
+
+    #[test]
+    fn serialize_layers() {
+        let symbols = ModMapping {
+            map: [
+                (Mod::Base, Some(Symbol::DeadKey('*'))),
+                (Mod::Shift, None),
+                (Mod::Altgr, Some(Symbol::Character('a'))),
+                (Mod::AltgrShift, None),
+            ],
+        };
+        let json = serde_json::to_string(&symbols).unwrap();
+        assert_eq!(json, r#"["**","","a"]"#);
+    }
+
     #[test]
     fn deserialize_layers_too_many() {
         let json = r#"["a", "b", "c", "d", "e"]"#;
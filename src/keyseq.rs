@@ -1,10 +1,11 @@
 use log::warn;
-use std::{collections::HashMap, hash::Hash};
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
 
 use crate::{
     geometry::Row,
     hands::Finger,
     kalamine::{DeadKey, Mod, ModMapping, PhysicalKey, Symbol},
+    modifiers::{ALTGR, SHIFT},
 };
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -31,6 +32,17 @@ impl KeySymbol {
         }
     }
 
+    /// Key name, with the modifiers pressed (e.g. `⇧KeyA`)
+    pub fn key_name(&self) -> String {
+        let modifiers = match self.modifier {
+            Mod::Base => String::new(),
+            Mod::Shift => SHIFT.to_string(),
+            Mod::Altgr => ALTGR.to_string(),
+            Mod::AltgrShift => format!("{ALTGR}{SHIFT}"),
+        };
+        format!("{modifiers}{:?}", self.key)
+    }
+
     pub fn symbol(&self) -> Symbol {
         match self.dead_key {
             false => Symbol::Character(self.name),
@@ -39,12 +51,29 @@ impl KeySymbol {
     }
 }
 
+/// How the frequency of a character is shared between its equivalent key sequences
+/// (the ones with as many keys and modifiers as the best one)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, strum::EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum KeySeqChoice {
+    /// Always the best key sequence
+    #[default]
+    Best,
+    /// Split evenly between the equivalent key sequences
+    Even,
+    /// The equivalent key sequences making the fewest same finger bigrams in each n-gram,
+    /// as an experienced typist would do
+    Context,
+}
+
+/// All the key sequences typing each character, from the best to the worst
 pub fn build_keyseq_map(
     layout_keymap: &HashMap<PhysicalKey, ModMapping>,
     layout_deadkeys: &HashMap<DeadKey, HashMap<Symbol, Symbol>>,
-) -> HashMap<char, Vec<KeySymbol>> {
-    let mut base_keysym_map: HashMap<char, KeySymbol> = HashMap::new();
-    let mut deadkeys_map: HashMap<DeadKey, Vec<KeySymbol>> = HashMap::new();
+) -> HashMap<char, Vec<Vec<KeySymbol>>> {
+    // character to key-symbols sequences map:
+    let mut keyseq_map: HashMap<char, Vec<Vec<KeySymbol>>> = HashMap::new();
+    let mut base_deadkeys: HashMap<DeadKey, Vec<KeySymbol>> = HashMap::new();
 
     // One key characters
     for (&physical_key, mod_mapping) in layout_keymap.iter() {
@@ -52,146 +81,161 @@ pub fn build_keyseq_map(
             if let Some(symbol) = symbol {
                 let keysym = KeySymbol::new(symbol, physical_key, modifier);
                 match symbol {
-                    Symbol::Character(c) => {
-                        if is_bettery_keysym(&keysym, base_keysym_map.get(&c)) {
-                            base_keysym_map.insert(c, keysym);
-                        }
-                    }
-                    Symbol::DeadKey(c) => {
-                        deadkeys_map.insert(DeadKey { name: c }, vec![keysym]);
-                    }
+                    Symbol::Character(c) => keyseq_map.entry(c).or_default().push(vec![keysym]),
+                    Symbol::DeadKey(c) => base_deadkeys
+                        .entry(DeadKey { name: c })
+                        .or_default()
+                        .push(keysym),
                 }
             }
             // TODO: warn if incompatible key is used, such as IntlBackslash with ANSI
             // and don't add it to the map
         }
     }
+    let base_keysyms: HashMap<char, Vec<KeySymbol>> = keyseq_map
+        .iter()
+        .map(|(c, keyseqs)| (*c, keyseqs.iter().map(|keyseq| keyseq[0].clone()).collect()))
+        .collect();
 
-    // Dead keys layers
-    let mut dk_layer_to_parse: Vec<DeadKey> = layout_deadkeys.keys().cloned().collect();
-    // character to key-symbols sequence map:
-    let mut keyseq_map: HashMap<char, Vec<KeySymbol>> = HashMap::new();
+    // Sequences of the dead keys, including the ones typed from other dead keys.
+    // A sequence never goes through the same dead key twice.
+    let mut deadkey_seqs: HashMap<DeadKey, Vec<Vec<KeySymbol>>> = HashMap::new();
+    let mut dk_seq_to_parse: Vec<(DeadKey, Vec<KeySymbol>)> = base_deadkeys
+        .iter()
+        .flat_map(|(deadkey, keysyms)| keysyms.iter().map(|ks| (*deadkey, vec![ks.clone()])))
+        .collect();
+    while let Some((deadkey, dk_keyseq)) = dk_seq_to_parse.pop() {
+        for (trigger_sym, output_sym) in layout_deadkeys.get(&deadkey).into_iter().flatten() {
+            let Symbol::DeadKey(c) = *output_sym else {
+                continue;
+            };
+            if dk_keyseq.iter().any(|ks| ks.dead_key && ks.name == c) {
+                continue;
+            }
+            let Ok(triggers) =
+                trigger_keysyms(*trigger_sym, &dk_keyseq, &base_keysyms, &base_deadkeys)
+            else {
+                continue;
+            };
+            for trigger in triggers {
+                let mut keyseq = dk_keyseq.clone();
+                keyseq.push(KeySymbol::new(*output_sym, trigger.key, trigger.modifier));
+                dk_seq_to_parse.push((DeadKey { name: c }, keyseq));
+            }
+        }
+        deadkey_seqs.entry(deadkey).or_default().push(dk_keyseq);
+    }
 
-    while let Some(deadkey) = dk_layer_to_parse.pop() {
-        let layer = match layout_deadkeys.get(&deadkey) {
+    // Dead keys layers
+    for (deadkey, dk_keyseqs) in deadkey_seqs.iter() {
+        let layer = match layout_deadkeys.get(deadkey) {
             Some(s) => s,
             None => {
                 warn!("No layer defined for dead key '{deadkey}'");
                 continue;
             }
         };
-        let dk_keyseq = match deadkeys_map.get(&deadkey).cloned() {
-            Some(ks_sequence) => ks_sequence,
-            None => continue, // Dead key that we don't know how to trigger yet
-        };
-
         for (trigger_sym, output_sym) in layer {
-            // Get the key symbol of the trigger
-            let trigger_keysym: &KeySymbol = match trigger_sym {
-                Symbol::Character(c) => match base_keysym_map.get(&c) {
-                    Some(ks) => ks,
-                    None => {
-                        warn!("Symbol '{c}' from dead key layer '{deadkey}' is not on the base layers");
-                        continue;
-                    }
-                },
-                Symbol::DeadKey(c) => {
-                    // Double press the dead key (e.g. ** -> ¨ in Ergo-L)
-                    if c == &deadkey.name {
-                        dk_keyseq.last().unwrap()
-                    } else {
-                        warn!("Invalid trigger '{trigger_sym}' on dead key layer '{deadkey}'");
-                        continue;
-                    }
-                }
+            let Symbol::Character(c) = *output_sym else {
+                continue;
             };
-
-            // Build the key sequence to do the output symbol
-            let mut keyseq = dk_keyseq.clone();
-
-            match output_sym {
-                Symbol::Character(c) => {
-                    keyseq.push(trigger_keysym.clone());
-                    if is_better_keyseq(&keyseq, keyseq_map.get(c)) {
-                        keyseq_map.insert(*c, keyseq);
-                    }
-                }
-                Symbol::DeadKey(c) => {
-                    let dk = DeadKey { name: *c };
-                    if !dk_layer_to_parse.contains(&dk) {
-                        dk_layer_to_parse.push(dk);
-                    }
-                    let ks =
-                        KeySymbol::new(*output_sym, trigger_keysym.key, trigger_keysym.modifier);
-                    keyseq.push(ks);
-                    if is_better_keyseq(&keyseq, deadkeys_map.get(&dk)) {
-                        deadkeys_map.insert(dk, keyseq);
-                    }
+            for dk_keyseq in dk_keyseqs {
+                let triggers =
+                    match trigger_keysyms(*trigger_sym, dk_keyseq, &base_keysyms, &base_deadkeys) {
+                        Ok(triggers) => triggers,
+                        Err(err) => {
+                            warn!("{err} on dead key layer '{deadkey}'");
+                            break;
+                        }
+                    };
+                for trigger in triggers {
+                    let mut keyseq = dk_keyseq.clone();
+                    keyseq.push(trigger.clone());
+                    keyseq_map.entry(c).or_default().push(keyseq);
                 }
             }
         }
     }
 
-    keyseq_map.extend(base_keysym_map.into_iter().map(|(c, key)| (c, vec![key])));
+    for keyseqs in keyseq_map.values_mut() {
+        keyseqs.sort_by(|ks, other| compare_keyseqs(ks, other));
+        keyseqs.dedup();
+    }
     keyseq_map
 }
 
-fn is_bettery_keysym(keysym: &KeySymbol, old_keysym: Option<&KeySymbol>) -> bool {
-    match old_keysym {
-        None => true,
-        Some(old_keysym) => {
-            if keysym.modifier != old_keysym.modifier {
-                keysym.modifier < old_keysym.modifier
-            } else {
-                let dfh = Row::distance(Row::Middle, keysym.key.row()); // distance from home
-                let old_dfh = Row::distance(Row::Middle, old_keysym.key.row());
-                if dfh != old_dfh {
-                    dfh < old_dfh
-                } else {
-                    warn!("non-deterministic choice between two keys for the same character");
-                    false
-                }
-            }
-        }
+/// Key symbols typing a symbol of a dead key layer
+fn trigger_keysyms<'a>(
+    trigger_sym: Symbol,
+    dk_keyseq: &'a [KeySymbol],
+    base_keysyms: &'a HashMap<char, Vec<KeySymbol>>,
+    base_deadkeys: &'a HashMap<DeadKey, Vec<KeySymbol>>,
+) -> Result<Vec<&'a KeySymbol>, String> {
+    let dk_keysym = dk_keyseq.last().unwrap();
+    match trigger_sym {
+        Symbol::Character(c) => match base_keysyms.get(&c) {
+            Some(keysyms) => Ok(keysyms.iter().collect()),
+            None => Err(format!("Symbol '{c}' is not on the base layers")),
+        },
+        // Double press the dead key (e.g. ** -> ¨ in Ergo-L)
+        Symbol::DeadKey(c) if c == dk_keysym.name => Ok(vec![dk_keysym]),
+        Symbol::DeadKey(c) => match base_deadkeys.get(&DeadKey { name: c }) {
+            Some(keysyms) => Ok(keysyms.iter().collect()),
+            None => Err(format!("Invalid trigger '{trigger_sym}'")),
+        },
     }
 }
 
-fn is_better_keyseq(ks: &Vec<KeySymbol>, old_ks: Option<&Vec<KeySymbol>>) -> bool {
-    match old_ks {
-        None => true,
-        Some(old_ks) => {
-            if ks.len() == old_ks.len() {
-                // if same length, prefer the one using the least mods
-                let mod_count = count_mods(ks);
-                let old_mod_count = count_mods(old_ks);
-                if mod_count != old_mod_count {
-                    mod_count < old_mod_count
-                } else {
-                    // if same length and mods, prefer the one using the thumb
-                    let thumb_count = count_thumbs(ks);
-                    let old_thumb_count = count_thumbs(old_ks);
-                    if thumb_count != old_thumb_count {
-                        thumb_count > old_thumb_count
-                    } else {
-                        warn!("non-deterministic choice between two key sequences for the same character");
-                        false
-                    }
-                }
-            } else {
-                ks.len() < old_ks.len()
-            }
-        }
-    }
+/// Order of the key sequences, from the best: the shortest, using the least mods
+/// (Shift before AltGr), the thumb, and the keys closest to the home row.
+/// The remaining ties are broken by key, so the order is deterministic.
+pub fn compare_keyseqs(ks: &[KeySymbol], other: &[KeySymbol]) -> Ordering {
+    let modifiers = |ks: &[KeySymbol]| ks.iter().map(|keysym| keysym.modifier).collect::<Vec<_>>();
+    let home_distance = |ks: &[KeySymbol]| {
+        ks.iter()
+            .map(|keysym| Row::distance(Row::Middle, keysym.key.row()))
+            .sum::<u32>()
+    };
+    let keys = |ks: &[KeySymbol]| ks.iter().map(|keysym| keysym.key).collect::<Vec<_>>();
+    ks.len()
+        .cmp(&other.len())
+        .then(count_mods(ks).cmp(&count_mods(other)))
+        .then_with(|| modifiers(ks).cmp(&modifiers(other)))
+        .then(count_thumbs(other).cmp(&count_thumbs(ks)))
+        .then_with(|| home_distance(ks).cmp(&home_distance(other)))
+        .then_with(|| keys(ks).cmp(&keys(other)))
+        .then_with(|| {
+            let symbols = |ks: &[KeySymbol]| ks.iter().map(KeySymbol::symbol).collect::<Vec<_>>();
+            symbols(ks).cmp(&symbols(other))
+        })
+}
+
+/// Names of the keys of a sequence (e.g. `Minus ⇧KeyA`)
+pub fn keyseq_name(keyseq: &[KeySymbol]) -> String {
+    let names: Vec<String> = keyseq.iter().map(KeySymbol::key_name).collect();
+    names.join(" ")
 }
 
-fn count_mods(sequence: &Vec<KeySymbol>) -> u32 {
+/// The key sequences as good as the best one: as many keys and modifiers
+pub fn equivalent_keyseqs(keyseqs: &[Vec<KeySymbol>]) -> &[Vec<KeySymbol>] {
+    let Some(best) = keyseqs.first() else {
+        return keyseqs;
+    };
+    let count = keyseqs
+        .iter()
+        .take_while(|ks| ks.len() == best.len() && count_mods(ks) == count_mods(best))
+        .count();
+    &keyseqs[..count]
+}
+
+fn count_mods(sequence: &[KeySymbol]) -> u32 {
     sequence
         .iter()
         .map(|keysym| keysym.modifier.mod_count())
         .sum()
 }
 
-fn count_thumbs(sequence: &Vec<KeySymbol>) -> usize {
+fn count_thumbs(sequence: &[KeySymbol]) -> usize {
     sequence
         .iter()
         .filter(|&x| x.key.finger() == Finger::Thumb)
@@ -293,12 +337,41 @@ mod tests {
                 sym
             );
             assert_eq!(
-                keystrokes_map.get(sym).unwrap(),
+                &keystrokes_map.get(sym).unwrap()[0],
                 expected_seq,
                 "symbol: {}",
                 sym
             );
         }
-        assert_eq!(keystrokes_map, expected);
+        assert_eq!(keystrokes_map.len(), expected.len());
+
+        // All the alternatives, from the best
+        let ks_b5 = KeySymbol::new(Character('b'), Digit5, Base);
+        let ks_b_maj = KeySymbol::new(Character('b'), KeyB, Shift);
+        let ks_b5_maj = KeySymbol::new(Character('b'), Digit5, Shift);
+        assert_eq!(
+            keystrokes_map[&'b'][..4],
+            [vec![ks_b], vec![ks_b5], vec![ks_b_maj], vec![ks_b5_maj]]
+        );
+        assert_eq!(keystrokes_map[&'b'].len(), 8);
+        assert_eq!(
+            keystrokes_map[&'’'],
+            [
+                vec![ks_caret.clone(), ks_space],
+                vec![ks_caret.clone(), ks_quote]
+            ]
+        );
+        assert_eq!(
+            keystrokes_map[&'.'],
+            [
+                vec![ks_period.clone()],
+                vec![ks_caret.clone(), ks_period.clone()],
+                vec![ks_caret, ks_diae, ks_period],
+            ]
+        );
+        assert_eq!(
+            equivalent_keyseqs(&keystrokes_map[&'b']),
+            &keystrokes_map[&'b'][..2]
+        );
     }
 }
@@ -1,8 +1,17 @@
+pub mod compare;
 pub mod corpus;
+mod error;
+pub mod formats;
 pub mod geometry;
 pub mod hands;
+pub mod heatmap;
 pub mod kalamine;
 pub mod keyseq;
+pub mod lint;
+pub mod modifiers;
+pub mod optimize;
+pub mod score;
 pub mod stats;
 
+pub use error::Error;
 pub use stats::analyse;
@@ -0,0 +1,376 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use crate::{
+    geometry::Geometry,
+    kalamine::{Corpus, DeadKey, Layout, PhysicalKey, Symbol},
+    keyseq::{self, KeySymbol},
+};
+
+/// How serious a problem found in a layout is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+/// Problem found in a layout
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// Dead key typed by the layout, without layer
+    DeadKeyWithoutLayer(DeadKey),
+    /// Dead key layer that no key sequence reaches
+    UnreachableDeadKey(DeadKey),
+    /// Symbol of a dead key layer that is not on the keys, nor the dead key itself
+    MissingTrigger { dead_key: DeadKey, trigger: Symbol },
+    /// Key of the layout that is not on the keyboard of the geometry
+    KeyNotInGeometry(PhysicalKey),
+    /// Lowercase letter whose uppercase cannot be typed
+    MissingUppercase { letter: char, uppercase: char },
+    /// Character of the corpus that cannot be typed, with its frequency
+    UntypeableCharacter { character: char, frequency: f32 },
+    /// Character on several keys: the best one is used, the others are alternatives
+    DuplicateCharacter {
+        character: char,
+        used: KeySymbol,
+        others: Vec<KeySymbol>,
+    },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::DeadKeyWithoutLayer(_) => Severity::Error,
+            Diagnostic::UnreachableDeadKey(_)
+            | Diagnostic::MissingTrigger { .. }
+            | Diagnostic::KeyNotInGeometry(_)
+            | Diagnostic::MissingUppercase { .. }
+            | Diagnostic::UntypeableCharacter { .. } => Severity::Warning,
+            Diagnostic::DuplicateCharacter { .. } => Severity::Info,
+        }
+    }
+
+    /// Short name of the kind of problem
+    pub fn code(&self) -> &'static str {
+        match self {
+            Diagnostic::DeadKeyWithoutLayer(_) => "dead-key-without-layer",
+            Diagnostic::UnreachableDeadKey(_) => "unreachable-dead-key",
+            Diagnostic::MissingTrigger { .. } => "missing-trigger",
+            Diagnostic::KeyNotInGeometry(_) => "key-not-in-geometry",
+            Diagnostic::MissingUppercase { .. } => "missing-uppercase",
+            Diagnostic::UntypeableCharacter { .. } => "untypeable-character",
+            Diagnostic::DuplicateCharacter { .. } => "duplicate-character",
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::DeadKeyWithoutLayer(dead_key) => {
+                write!(f, "dead key '{dead_key}' has no layer")
+            }
+            Diagnostic::UnreachableDeadKey(dead_key) => {
+                write!(f, "dead key '{dead_key}' cannot be typed")
+            }
+            Diagnostic::MissingTrigger { dead_key, trigger } => write!(
+                f,
+                "'{trigger}' of the dead key layer '{dead_key}' is not on the layout"
+            ),
+            Diagnostic::KeyNotInGeometry(key) => {
+                write!(f, "key {key:?} is not on the keyboard of the geometry")
+            }
+            Diagnostic::MissingUppercase { letter, uppercase } => {
+                write!(f, "'{uppercase}', uppercase of '{letter}', cannot be typed")
+            }
+            Diagnostic::UntypeableCharacter {
+                character,
+                frequency,
+            } => write!(
+                f,
+                "'{character}' cannot be typed ({frequency:.2}% of the corpus)"
+            ),
+            Diagnostic::DuplicateCharacter {
+                character,
+                used,
+                others,
+            } => {
+                let others: Vec<String> = others.iter().map(KeySymbol::key_name).collect();
+                write!(
+                    f,
+                    "'{character}' is on {} and {}: {} is used",
+                    used.key_name(),
+                    others.join(", "),
+                    used.key_name()
+                )
+            }
+        }
+    }
+}
+
+impl serde::Serialize for Diagnostic {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = s.serialize_map(None)?;
+        map.serialize_entry("severity", &self.severity().to_string())?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Diagnostic::DeadKeyWithoutLayer(dead_key)
+            | Diagnostic::UnreachableDeadKey(dead_key) => {
+                map.serialize_entry("dead_key", dead_key)?;
+            }
+            Diagnostic::MissingTrigger { dead_key, trigger } => {
+                map.serialize_entry("dead_key", dead_key)?;
+                map.serialize_entry("trigger", trigger)?;
+            }
+            Diagnostic::KeyNotInGeometry(key) => map.serialize_entry("key", key)?,
+            Diagnostic::MissingUppercase { letter, uppercase } => {
+                map.serialize_entry("letter", letter)?;
+                map.serialize_entry("uppercase", uppercase)?;
+            }
+            Diagnostic::UntypeableCharacter {
+                character,
+                frequency,
+            } => {
+                map.serialize_entry("character", character)?;
+                map.serialize_entry("frequency", frequency)?;
+            }
+            Diagnostic::DuplicateCharacter {
+                character,
+                used,
+                others,
+            } => {
+                let others: Vec<String> = others.iter().map(KeySymbol::key_name).collect();
+                map.serialize_entry("character", character)?;
+                map.serialize_entry("used", &used.key_name())?;
+                map.serialize_entry("others", &others)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Problems of a layout typed on a keyboard, and of the corpus characters if one is given.
+/// The most serious ones come first.
+pub fn lint(layout: &Layout, geometry: &Geometry, corpus: Option<&Corpus>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let keyseq_map = keyseq::build_keyseq_map(&layout.keymap, &layout.deadkeys);
+
+    let key_symbols: HashSet<Symbol> = layout
+        .keymap
+        .values()
+        .flat_map(|mapping| mapping.map.iter().filter_map(|(_, symbol)| *symbol))
+        .collect();
+    // as in the key sequences, a trigger is a symbol on the keys, or the dead key itself
+    let is_trigger = |dead_key: DeadKey, symbol: Symbol| {
+        key_symbols.contains(&symbol) || symbol == Symbol::DeadKey(dead_key.name)
+    };
+
+    let mut reachable: BTreeSet<DeadKey> = key_symbols
+        .iter()
+        .filter_map(|symbol| match symbol {
+            Symbol::DeadKey(name) => Some(DeadKey { name: *name }),
+            Symbol::Character(_) => None,
+        })
+        .collect();
+    let mut to_visit: Vec<DeadKey> = reachable.iter().copied().collect();
+    while let Some(dead_key) = to_visit.pop() {
+        for (trigger, output) in layout.deadkeys.get(&dead_key).into_iter().flatten() {
+            if let Symbol::DeadKey(name) = output {
+                if is_trigger(dead_key, *trigger) && reachable.insert(DeadKey { name: *name }) {
+                    to_visit.push(DeadKey { name: *name });
+                }
+            }
+        }
+    }
+
+    for dead_key in &reachable {
+        if !layout.deadkeys.contains_key(dead_key) {
+            diagnostics.push(Diagnostic::DeadKeyWithoutLayer(*dead_key));
+        }
+    }
+    let mut dead_keys: Vec<&DeadKey> = layout.deadkeys.keys().collect();
+    dead_keys.sort();
+    for dead_key in dead_keys {
+        if !reachable.contains(dead_key) {
+            diagnostics.push(Diagnostic::UnreachableDeadKey(*dead_key));
+        }
+        let mut triggers: Vec<&Symbol> = layout.deadkeys[dead_key].keys().collect();
+        triggers.sort();
+        for trigger in triggers {
+            if !is_trigger(*dead_key, *trigger) {
+                diagnostics.push(Diagnostic::MissingTrigger {
+                    dead_key: *dead_key,
+                    trigger: *trigger,
+                });
+            }
+        }
+    }
+
+    let mut keys: Vec<&PhysicalKey> = layout
+        .keymap
+        .iter()
+        .filter(|(key, mapping)| {
+            !geometry.contains(**key) && mapping.map.iter().any(|(_, symbol)| symbol.is_some())
+        })
+        .map(|(key, _)| key)
+        .collect();
+    keys.sort();
+    diagnostics.extend(
+        keys.into_iter()
+            .map(|key| Diagnostic::KeyNotInGeometry(*key)),
+    );
+
+    let mut letters: Vec<char> = keyseq_map
+        .keys()
+        .copied()
+        .filter(|c| c.is_lowercase())
+        .collect();
+    letters.sort();
+    for letter in letters {
+        let mut uppercase = letter.to_uppercase();
+        if let (Some(upper), None) = (uppercase.next(), uppercase.next()) {
+            if upper != letter && !keyseq_map.contains_key(&upper) {
+                diagnostics.push(Diagnostic::MissingUppercase {
+                    letter,
+                    uppercase: upper,
+                });
+            }
+        }
+    }
+
+    if let Some(corpus) = corpus {
+        let mut untypeable: Vec<(&char, &f32)> = corpus
+            .symbols
+            .iter()
+            .filter(|(c, freq)| **freq > 0.0 && !keyseq_map.contains_key(c))
+            .collect();
+        untypeable.sort_by(|(c1, f1), (c2, f2)| f2.total_cmp(f1).then(c1.cmp(c2)));
+        diagnostics.extend(untypeable.into_iter().map(|(character, frequency)| {
+            Diagnostic::UntypeableCharacter {
+                character: *character,
+                frequency: *frequency,
+            }
+        }));
+    }
+
+    let mut characters: Vec<(&char, &Vec<Vec<KeySymbol>>)> = keyseq_map.iter().collect();
+    characters.sort_by_key(|(c, _)| **c);
+    for (character, keyseqs) in characters {
+        let mut keysyms = keyseqs
+            .iter()
+            .filter(|keyseq| keyseq.len() == 1)
+            .map(|ks| &ks[0]);
+        if let Some(used) = keysyms.next() {
+            let others: Vec<KeySymbol> = keysyms.cloned().collect();
+            if !others.is_empty() {
+                diagnostics.push(Diagnostic::DuplicateCharacter {
+                    character: *character,
+                    used: used.clone(),
+                    others,
+                });
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(Diagnostic::severity);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{Mod, ModMapping, PhysicalKey::*};
+
+    #[test]
+    fn lint_layout() {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyA, ModMapping::from(vec!["a", "A"])),
+                (KeyB, ModMapping::from(vec!["b", "", "é"])),
+                (KeyC, ModMapping::from(vec!["*^", "*¨"])),
+                (IntlBackslash, ModMapping::from(vec!["b"])),
+            ]),
+            deadkeys: HashMap::from([
+                (
+                    DeadKey { name: '^' },
+                    HashMap::from([
+                        (Symbol::Character('a'), Symbol::Character('â')),
+                        (Symbol::Character('z'), Symbol::Character('ẑ')),
+                        (Symbol::DeadKey('^'), Symbol::Character('^')),
+                    ]),
+                ),
+                (
+                    DeadKey { name: '~' },
+                    HashMap::from([(Symbol::Character('a'), Symbol::Character('ã'))]),
+                ),
+            ]),
+        };
+        let corpus = Corpus {
+            path: String::from("text"),
+            symbols: HashMap::from([('a', 50.0), ('c', 10.0), ('d', 20.0)]),
+            digrams: HashMap::new(),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let geometry = Geometry::from(BuiltinGeometry::ANSI);
+        let diagnostics = lint(&layout, &geometry, Some(&corpus));
+
+        let ks_b = KeySymbol::new(Symbol::Character('b'), KeyB, Mod::Base);
+        let ks_b2 = KeySymbol::new(Symbol::Character('b'), IntlBackslash, Mod::Base);
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::DeadKeyWithoutLayer(DeadKey { name: '¨' }),
+                Diagnostic::MissingTrigger {
+                    dead_key: DeadKey { name: '^' },
+                    trigger: Symbol::Character('z'),
+                },
+                Diagnostic::UnreachableDeadKey(DeadKey { name: '~' }),
+                Diagnostic::KeyNotInGeometry(IntlBackslash),
+                Diagnostic::MissingUppercase {
+                    letter: 'b',
+                    uppercase: 'B',
+                },
+                Diagnostic::MissingUppercase {
+                    letter: 'â',
+                    uppercase: 'Â',
+                },
+                Diagnostic::MissingUppercase {
+                    letter: 'é',
+                    uppercase: 'É',
+                },
+                Diagnostic::UntypeableCharacter {
+                    character: 'd',
+                    frequency: 20.0,
+                },
+                Diagnostic::UntypeableCharacter {
+                    character: 'c',
+                    frequency: 10.0,
+                },
+                Diagnostic::DuplicateCharacter {
+                    character: 'b',
+                    used: ks_b,
+                    others: vec![ks_b2],
+                },
+            ]
+        );
+
+        let json = serde_json::to_value(&diagnostics[0]).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["code"], "dead-key-without-layer");
+        assert_eq!(json["dead_key"], "*¨");
+        assert_eq!(
+            diagnostics[9].to_string(),
+            "'b' is on KeyB and IntlBackslash: KeyB is used"
+        );
+    }
+}
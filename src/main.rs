@@ -1,24 +1,286 @@
-use std::{fs::File, io::BufReader, path::PathBuf, process};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
 
-use clap::Parser;
-use env_logger;
-use klayan::kalamine;
-use klayan::{self, geometry};
+use clap::{Args, Parser, Subcommand};
+use klayan::compare;
+use klayan::formats::{klc, xkb};
+use klayan::geometry::{BuiltinGeometry, Geometry, Row};
+use klayan::hands::Finger;
+use klayan::heatmap;
+use klayan::kalamine::{self, PhysicalKey, Symbol};
+use klayan::keyseq::KeySeqChoice;
+use klayan::lint::{self, Severity};
+use klayan::modifiers::{ModifierConfig, ShiftSide};
+use klayan::optimize::{self, OptimizeConfig};
+use klayan::score::{Score, Weights};
+use klayan::stats::{Options, Stats};
+use strum::IntoEnumIterator;
 mod cli_output;
 
 /// Analyse a keyboard layout
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Layout to analyse, in json format
-    layout: PathBuf,
-    /// Corpus to use for analysis, in json format
-    corpus: PathBuf,
-    /// Keyboard geometry
-    geometry: Option<klayan::geometry::Geometry>,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    analyse: AnalyseArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search a better layout, by swapping keys with simulated annealing
+    Optimize(OptimizeArgs),
+    /// Compare the statistics of several layouts, side by side
+    Compare(CompareArgs),
+    /// Build a corpus from text files, in json format
+    Corpus(CorpusArgs),
+    /// Convert a layout to kalamine json, XKB symbols or a Windows KLC file
+    Export(ExportArgs),
+    /// Check a layout for problems, e.g. unreachable dead keys or characters that cannot be typed
+    Lint(LintArgs),
+}
+
+#[derive(Args)]
+struct AnalyseArgs {
+    /// Layout to analyse, in json format or as a kalamine toml source
+    #[arg(required = true)]
+    layout: Option<PathBuf>,
+    /// Corpora to use for analysis, in json format, with an optional weight to mix them
+    /// (e.g. en.json:0.3 fr.json:0.7)
+    #[arg(required = true)]
+    corpus: Vec<CorpusArg>,
+    /// Keyboard geometry: iso, ansi, ortho, iso-angle-mod, ansi-angle-mod,
+    /// or a geometry file in json or toml format (default: iso).
+    /// A built-in geometry after the corpora is still accepted, but deprecated
+    #[arg(short, long)]
+    geometry: Option<String>,
     /// Show the full lists for each statistics
     #[arg(short, long)]
     all: bool,
+    /// Show a heatmap of the key usage
+    #[arg(long)]
+    heatmap: bool,
+    /// Write a heatmap of the key usage to an SVG file
+    #[arg(long)]
+    svg: Option<PathBuf>,
+    /// Number of the most frequent SFBs to draw as arrows on the SVG heatmap
+    #[arg(long, default_value_t = 0, requires = "svg")]
+    svg_sfb: usize,
+    #[command(flatten)]
+    modifiers: ModifierArgs,
+    #[command(flatten)]
+    keyseq: KeySeqArgs,
+    /// Weights to compute a score of the layout, in json format
+    #[arg(short, long)]
+    weights: Option<PathBuf>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct OptimizeArgs {
+    /// Layout to start from, in json format or as a kalamine toml source
+    layout: PathBuf,
+    /// Corpora to use for analysis, in json format, with an optional weight to mix them
+    #[arg(required = true)]
+    corpus: Vec<CorpusArg>,
+    /// Where to write the best layout found, in json format
+    /// (or as XKB symbols or a KLC file, by extension: .xkb, .klc)
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Keyboard geometry: iso, ansi, ortho, iso-angle-mod, ansi-angle-mod,
+    /// or a geometry file in json or toml format
+    #[arg(short, long, default_value = "iso")]
+    geometry: String,
+    /// Weights of the score to minimize, in json format (built-in weights if omitted)
+    #[arg(short, long)]
+    weights: Option<PathBuf>,
+    /// Keys that must not move: by name (e.g. Space), by row (e.g. digits) or by symbol (e.g. **)
+    #[arg(short, long)]
+    pin: Vec<String>,
+    /// Number of key swaps to try
+    #[arg(short, long, default_value_t = 10_000)]
+    iterations: usize,
+    /// Initial temperature of the annealing, in score unit
+    #[arg(short, long, default_value_t = 1.0)]
+    temperature: f32,
+    /// Seed of the random number generator
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+    #[command(flatten)]
+    modifiers: ModifierArgs,
+    #[command(flatten)]
+    keyseq: KeySeqArgs,
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    /// Layouts to compare, in json format or as kalamine toml sources. The deltas are computed against the first one
+    #[arg(required = true)]
+    layouts: Vec<PathBuf>,
+    /// Corpus to use for analysis, in json format, with an optional weight.
+    /// Repeat it to mix several corpora
+    #[arg(short, long, required = true)]
+    corpus: Vec<CorpusArg>,
+    /// Keyboard geometry: iso, ansi, ortho, iso-angle-mod, ansi-angle-mod,
+    /// or a geometry file in json or toml format
+    #[arg(short, long, default_value = "iso")]
+    geometry: String,
+    #[command(flatten)]
+    modifiers: ModifierArgs,
+    #[command(flatten)]
+    keyseq: KeySeqArgs,
+}
+
+#[derive(Args)]
+struct CorpusArgs {
+    /// UTF-8 text files, or directories containing them
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Where to write the corpus (default: standard output)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Layout to convert, in any format klayan reads
+    layout: PathBuf,
+    /// Where to write the layout
+    output: PathBuf,
+    /// Output format (default: from the extension of the output, kalamine json otherwise)
+    #[arg(short, long, value_enum)]
+    format: Option<LayoutFormat>,
+    /// Name of the layout (default: the name of the output file)
+    #[arg(short, long)]
+    name: Option<String>,
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Layout to check, in any format klayan reads
+    layout: PathBuf,
+    /// Corpora whose characters must be typeable, in json format
+    corpus: Vec<CorpusArg>,
+    /// Keyboard geometry: iso, ansi, ortho, iso-angle-mod, ansi-angle-mod,
+    /// or a geometry file in json or toml format
+    #[arg(short, long, default_value = "iso")]
+    geometry: String,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct ModifierArgs {
+    /// Count Shift and AltGr presses as keystrokes
+    #[arg(short, long)]
+    modifiers: bool,
+    /// Shift key used for shifted symbols: left, right or opposite (to the shifted key)
+    #[arg(long, default_value = "opposite")]
+    shift_side: ShiftSide,
+    /// Finger pressing the left Shift
+    #[arg(long, default_value = "left-pinky")]
+    left_shift_finger: Finger,
+    /// Finger pressing the right Shift
+    #[arg(long, default_value = "right-pinky")]
+    right_shift_finger: Finger,
+    /// Finger pressing AltGr
+    #[arg(long, default_value = "thumb")]
+    altgr_finger: Finger,
+}
+
+impl ModifierArgs {
+    fn config(&self) -> Option<ModifierConfig> {
+        self.modifiers.then_some(ModifierConfig {
+            shift_side: self.shift_side,
+            left_shift_finger: self.left_shift_finger,
+            right_shift_finger: self.right_shift_finger,
+            altgr_finger: self.altgr_finger,
+        })
+    }
+}
+
+#[derive(Args)]
+struct KeySeqArgs {
+    /// Key sequences used for the characters typed in several ways: best (always the best
+    /// one), even (split between the equivalent ones), or context (avoiding SFBs)
+    #[arg(long, default_value = "best")]
+    keyseq: KeySeqChoice,
+}
+
+/// Options of the analysis, from the modifier and key sequence arguments
+fn options(modifiers: &ModifierArgs, keyseq: &KeySeqArgs) -> Options {
+    Options {
+        modifiers: modifiers.config(),
+        keyseq_choice: keyseq.keyseq,
+    }
+}
+
+/// Corpus file, with an optional weight: `path[:weight]`
+#[derive(Clone)]
+struct CorpusArg {
+    path: PathBuf,
+    weight: f32,
+}
+
+impl FromStr for CorpusArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, weight) = match s.rsplit_once(':') {
+            Some((path, weight)) => match weight.parse::<f32>() {
+                Ok(weight) => (path, weight),
+                Err(_) => (s, 1.0),
+            },
+            None => (s, 1.0),
+        };
+        if !(weight >= 0.0 && weight.is_finite()) {
+            return Err(format!("Invalid corpus weight: {weight}"));
+        }
+        Ok(CorpusArg {
+            path: PathBuf::from(path),
+            weight,
+        })
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    /// Human readable tables
+    Table,
+    /// Full report in json, as in files/result.json
+    Json,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum LayoutFormat {
+    /// Kalamine json
+    Json,
+    /// XKB symbols, for Linux
+    Xkb,
+    /// Microsoft Keyboard Layout Creator source, for Windows
+    Klc,
+}
+
+impl LayoutFormat {
+    /// Format given by the extension of an output file, kalamine json otherwise
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xkb") => LayoutFormat::Xkb,
+            Some("klc") => LayoutFormat::Klc,
+            _ => LayoutFormat::Json,
+        }
+    }
 }
 
 fn main() {
@@ -32,31 +294,342 @@ fn main() {
 
     let cli = Cli::parse();
 
-    let layout = File::open(cli.layout).unwrap_or_else(|err| {
-        eprintln!("Could not open layout file: {err}");
-        process::exit(1);
-    });
+    match cli.command {
+        None => analyse(cli.analyse),
+        Some(Command::Optimize(args)) => optimize(args),
+        Some(Command::Compare(args)) => compare(args),
+        Some(Command::Corpus(args)) => corpus(args),
+        Some(Command::Export(args)) => export(args),
+        Some(Command::Lint(args)) => lint(args),
+    }
+}
 
-    let corpus = File::open(cli.corpus).unwrap_or_else(|err| {
-        eprintln!("Could not open corpus file: {err}");
-        process::exit(1);
+fn analyse(mut args: AnalyseArgs) {
+    let layout = load_layout(&args.layout.unwrap());
+    if let Some(name) = positional_geometry(&mut args.corpus) {
+        if args.geometry.is_some() {
+            eprintln!("The geometry is given twice: '{name}' and --geometry");
+            process::exit(1);
+        }
+        log::warn!("A positional geometry is deprecated, use --geometry {name}");
+        args.geometry = Some(name);
+    }
+    let corpus = load_corpus(&args.corpus);
+    let geometry = load_geometry(args.geometry.as_deref().unwrap_or("iso"));
+
+    let stats = analyse_layout(
+        &layout,
+        &corpus,
+        &geometry,
+        &options(&args.modifiers, &args.keyseq),
+    );
+
+    let score = args.weights.map(|path| {
+        let weights: Weights = read_json(&path, "weights");
+        stats.score(&weights)
     });
 
-    let layout: kalamine::Layout =
-        serde_json::from_reader(BufReader::new(layout)).unwrap_or_else(|err| {
-            eprint!("Invalid layout file: {err}");
+    if let Some(path) = &args.svg {
+        let keys = heatmap::heatmap(&layout, &geometry, &stats.unigrams.key_usage);
+        let arrows = heatmap::sfb_arrows(&keys, &stats.bigrams.list_sfb_keys, args.svg_sfb);
+        fs::write(path, heatmap::svg(&keys, &arrows)).unwrap_or_else(|err| {
+            eprintln!("Could not write svg file: {err}");
             process::exit(1);
         });
+    }
 
-    let corpus: kalamine::Corpus =
-        serde_json::from_reader(BufReader::new(corpus)).unwrap_or_else(|err| {
-            eprintln!("Invalid corpus file: {err}");
-            process::exit(1);
-        });
+    match args.format {
+        Format::Table => {
+            let heatmap = args
+                .heatmap
+                .then(|| heatmap::heatmap(&layout, &geometry, &stats.unigrams.key_usage));
+            cli_output::print_output(stats, args.all);
+            if let Some(heatmap) = heatmap {
+                println!();
+                cli_output::print_heatmap(&heatmap);
+            }
+            if let Some(score) = score {
+                println!();
+                cli_output::print_score(&score);
+            }
+        }
+        Format::Json => {
+            let report = Report {
+                stats: &stats,
+                score,
+            };
+            serde_json::to_writer_pretty(io::stdout().lock(), &report).unwrap_or_else(|err| {
+                eprintln!("Could not write json output: {err}");
+                process::exit(1);
+            });
+            println!();
+        }
+    }
+}
+
+fn optimize(args: OptimizeArgs) {
+    let layout = load_layout(&args.layout);
+    let corpus = load_corpus(&args.corpus);
+    let geometry = load_geometry(&args.geometry);
+    let weights: Weights = match args.weights {
+        Some(path) => read_json(&path, "weights"),
+        None => Weights::default(),
+    };
+
+    let pinned: HashSet<PhysicalKey> = args
+        .pin
+        .iter()
+        .flat_map(|pin| {
+            pinned_keys(&layout, &geometry, pin).unwrap_or_else(|| {
+                eprintln!("Invalid pin '{pin}': not a key name, a row, nor a symbol of the layout");
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let config = OptimizeConfig {
+        iterations: args.iterations,
+        temperature: args.temperature,
+        seed: args.seed,
+        pinned,
+    };
+    let options = options(&args.modifiers, &args.keyseq);
+
+    let initial_score = analyse_layout(&layout, &corpus, &geometry, &options).score(&weights);
+    let (best, best_score) =
+        optimize::optimize(&layout, &corpus, &geometry, &options, &weights, &config)
+            .unwrap_or_else(|err| exit_with_error("Could not optimize the layout", err));
+
+    let format = LayoutFormat::from_path(&args.output);
+    write_layout(&args.output, &best, format, None);
+
+    println!(
+        "score: {:.2} -> {:.2}",
+        initial_score.total, best_score.total
+    );
+    cli_output::print_score(&best_score);
+}
+
+fn compare(args: CompareArgs) {
+    let corpus = load_corpus(&args.corpus);
+    let geometry = load_geometry(&args.geometry);
+    let options = options(&args.modifiers, &args.keyseq);
 
-    let geometry = cli.geometry.unwrap_or(geometry::Geometry::ISO);
+    let names: Vec<String> = args
+        .layouts
+        .iter()
+        .map(|path| match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        })
+        .collect();
+    let stats: Vec<Stats> = args
+        .layouts
+        .iter()
+        .map(|path| {
+            let layout = load_layout(path);
+            analyse_layout(&layout, &corpus, &geometry, &options)
+        })
+        .collect();
+
+    cli_output::print_comparison(&names, &compare::compare(&stats));
+}
 
-    let stats = klayan::analyse(&layout, &corpus, geometry);
+fn corpus(args: CorpusArgs) {
+    let corpus = kalamine::Corpus::from_paths(&args.paths).unwrap_or_else(|err| {
+        eprintln!("Could not read text: {err}");
+        process::exit(1);
+    });
+    match args.output {
+        Some(path) => write_json(&path, &corpus),
+        None => {
+            serde_json::to_writer_pretty(io::stdout().lock(), &corpus).unwrap_or_else(|err| {
+                eprintln!("Could not write json output: {err}");
+                process::exit(1);
+            });
+            println!();
+        }
+    }
+}
+
+fn export(args: ExportArgs) {
+    let layout = load_layout(&args.layout);
+    let format = args
+        .format
+        .unwrap_or_else(|| LayoutFormat::from_path(&args.output));
+    write_layout(&args.output, &layout, format, args.name.as_deref());
+}
+
+/// Print the problems of a layout, and fail if there is an error
+fn lint(args: LintArgs) {
+    let layout = load_layout(&args.layout);
+    let geometry = load_geometry(&args.geometry);
+    let corpus = (!args.corpus.is_empty()).then(|| load_corpus(&args.corpus));
+
+    let diagnostics = lint::lint(&layout, &geometry, corpus.as_ref());
+    match args.format {
+        Format::Table => cli_output::print_diagnostics(&diagnostics),
+        Format::Json => {
+            serde_json::to_writer_pretty(io::stdout().lock(), &diagnostics).unwrap_or_else(|err| {
+                eprintln!("Could not write json output: {err}");
+                process::exit(1);
+            });
+            println!();
+        }
+    }
+    if diagnostics.iter().any(|d| d.severity() == Severity::Error) {
+        process::exit(1);
+    }
+}
+
+/// Write a layout, named after the file if no name is given
+fn write_layout(path: &Path, layout: &kalamine::Layout, format: LayoutFormat, name: Option<&str>) {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+    let name = name.or(stem.as_deref()).unwrap_or("klayan");
+    let written = match format {
+        LayoutFormat::Json => return write_json(path, layout),
+        LayoutFormat::Xkb => write_xkb(path, layout, name),
+        LayoutFormat::Klc => klc::write_klc(path, layout, name),
+    };
+    written.unwrap_or_else(|err| exit_with_error("Could not write output file", err));
+}
+
+/// Write the XKB symbols, and the dead keys in a Compose file next to them
+fn write_xkb(path: &Path, layout: &kalamine::Layout, name: &str) -> Result<(), klayan::Error> {
+    fs::write(path, xkb::format_symbols(layout, name)?)?;
+    if !layout.deadkeys.is_empty() {
+        fs::write(
+            path.with_extension("XCompose"),
+            xkb::format_compose(layout)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Keys given by a key name, a row name, or a symbol they contain
+fn pinned_keys(
+    layout: &kalamine::Layout,
+    geometry: &Geometry,
+    pin: &str,
+) -> Option<Vec<PhysicalKey>> {
+    if let Ok(key) = pin.parse() {
+        return Some(vec![key]);
+    }
+    if let Ok(row) = pin.parse::<Row>() {
+        return Some(
+            PhysicalKey::iter()
+                .filter(|key| geometry.row(*key) == row)
+                .collect(),
+        );
+    }
+    let symbol: Symbol = pin.parse().ok()?;
+    let keys: Vec<PhysicalKey> = layout
+        .keymap
+        .iter()
+        .filter(|(_, mapping)| mapping.map.iter().any(|(_, s)| *s == Some(symbol)))
+        .map(|(key, _)| *key)
+        .collect();
+    (!keys.is_empty()).then_some(keys)
+}
+
+fn analyse_layout(
+    layout: &kalamine::Layout,
+    corpus: &kalamine::Corpus,
+    geometry: &Geometry,
+    options: &Options,
+) -> Stats {
+    klayan::analyse(layout, corpus, geometry, options)
+        .unwrap_or_else(|err| exit_with_error("Could not analyse the layout", err))
+}
+
+fn load_corpus(args: &[CorpusArg]) -> kalamine::Corpus {
+    let read = |arg: &CorpusArg| {
+        kalamine::Corpus::from_file(&arg.path).unwrap_or_else(|err| {
+            exit_with_error(
+                &format!("Could not read corpus {}", arg.path.display()),
+                err,
+            )
+        })
+    };
+    if let [arg] = args {
+        return read(arg);
+    }
+    let corpora: Vec<(kalamine::Corpus, f32)> =
+        args.iter().map(|arg| (read(arg), arg.weight)).collect();
+    kalamine::Corpus::merge(&corpora)
+        .unwrap_or_else(|err| exit_with_error("Could not merge the corpora", err))
+}
+
+/// Built-in geometry given after the corpora, as before `--geometry` existed.
+/// It is taken out of the corpora, unless a file of this name exists.
+fn positional_geometry(corpus: &mut Vec<CorpusArg>) -> Option<String> {
+    if corpus.len() < 2 {
+        return None;
+    }
+    let last = corpus.last()?;
+    let name = last.path.to_str()?;
+    if last.path.exists() || name.parse::<BuiltinGeometry>().is_err() {
+        return None;
+    }
+    let name = name.to_string();
+    corpus.pop();
+    Some(name)
+}
+
+/// Built-in geometry by name, or geometry file
+fn load_geometry(name: &str) -> Geometry {
+    if let Ok(builtin) = name.parse::<BuiltinGeometry>() {
+        return Geometry::from(builtin);
+    }
+    Geometry::from_file(Path::new(name)).unwrap_or_else(|err| {
+        eprintln!(
+            "Invalid geometry '{name}': not a built-in geometry, nor a valid geometry file ({err})"
+        );
+        process::exit(1);
+    })
+}
+
+fn load_layout(path: &Path) -> kalamine::Layout {
+    kalamine::Layout::from_file(path).unwrap_or_else(|err| {
+        exit_with_error(&format!("Could not read layout {}", path.display()), err)
+    })
+}
+
+fn exit_with_error(context: &str, err: klayan::Error) -> ! {
+    eprintln!("{context}: {err}");
+    process::exit(1);
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path, name: &str) -> T {
+    let file = File::open(path).unwrap_or_else(|err| {
+        eprintln!("Could not open {name} file: {err}");
+        process::exit(1);
+    });
+    serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|err| {
+        eprintln!("Invalid {name} file: {err}");
+        process::exit(1);
+    })
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) {
+    let file = File::create(path).unwrap_or_else(|err| {
+        eprintln!("Could not create output file: {err}");
+        process::exit(1);
+    });
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, value)
+        .map_err(io::Error::from)
+        .and_then(|_| writeln!(writer))
+        .unwrap_or_else(|err| {
+            eprintln!("Could not write output file: {err}");
+            process::exit(1);
+        });
+}
 
-    cli_output::print_output(stats, cli.all);
+#[derive(serde::Serialize)]
+struct Report<'a> {
+    #[serde(flatten)]
+    stats: &'a Stats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<Score>,
 }
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use crate::{
+    geometry::Geometry,
+    hands::{Finger, Hand},
+    kalamine::{Mod, PhysicalKey, Symbol},
+    keyseq::KeySymbol,
+};
+
+/// Name of the Shift key symbol in the statistics
+pub const SHIFT: char = '⇧';
+/// Name of the AltGr key symbol in the statistics
+pub const ALTGR: char = '⇮';
+
+/// Which Shift key is used to type a shifted symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ShiftSide {
+    Left,
+    Right,
+    /// Shift on the other hand than the shifted key, as in touch typing.
+    /// The left Shift is used for the thumb keys.
+    Opposite,
+}
+
+/// How the modifiers are pressed, when they are counted as keystrokes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifierConfig {
+    pub shift_side: ShiftSide,
+    pub left_shift_finger: Finger,
+    pub right_shift_finger: Finger,
+    pub altgr_finger: Finger,
+}
+
+impl Default for ModifierConfig {
+    fn default() -> Self {
+        ModifierConfig {
+            shift_side: ShiftSide::Opposite,
+            left_shift_finger: Finger::LeftPinky,
+            right_shift_finger: Finger::RightPinky,
+            altgr_finger: Finger::Thumb,
+        }
+    }
+}
+
+impl ModifierConfig {
+    /// Assign the configured fingers to the modifier keys
+    pub fn apply_fingers(&self, geometry: &mut Geometry) {
+        geometry.set_finger(PhysicalKey::ShiftLeft, self.left_shift_finger);
+        geometry.set_finger(PhysicalKey::ShiftRight, self.right_shift_finger);
+        geometry.set_finger(PhysicalKey::AltRight, self.altgr_finger);
+    }
+
+    fn shift_key(&self, key: PhysicalKey, geometry: &Geometry) -> PhysicalKey {
+        match self.shift_side {
+            ShiftSide::Left => PhysicalKey::ShiftLeft,
+            ShiftSide::Right => PhysicalKey::ShiftRight,
+            ShiftSide::Opposite => match geometry.hand(key) {
+                Hand::Left => PhysicalKey::ShiftRight,
+                Hand::Right | Hand::Thumbs => PhysicalKey::ShiftLeft,
+            },
+        }
+    }
+
+    /// Modifier key symbols to press before the given key symbol
+    fn modifier_keysyms(&self, keysym: &KeySymbol, geometry: &Geometry) -> Vec<KeySymbol> {
+        let shift = || {
+            KeySymbol::new(
+                Symbol::Character(SHIFT),
+                self.shift_key(keysym.key, geometry),
+                Mod::Base,
+            )
+        };
+        let altgr = || KeySymbol::new(Symbol::Character(ALTGR), PhysicalKey::AltRight, Mod::Base);
+        match keysym.modifier {
+            Mod::Base => vec![],
+            Mod::Shift => vec![shift()],
+            Mod::Altgr => vec![altgr()],
+            Mod::AltgrShift => vec![altgr(), shift()],
+        }
+    }
+}
+
+/// Insert the modifier key presses before each key symbol that needs them,
+/// so they are counted as any other keystroke
+pub fn expand_modifiers(
+    keyseq_map: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    config: &ModifierConfig,
+    geometry: &Geometry,
+) -> HashMap<char, Vec<Vec<KeySymbol>>> {
+    let expand = |keyseq: &Vec<KeySymbol>| {
+        keyseq
+            .iter()
+            .flat_map(|keysym| {
+                let mut keysyms = config.modifier_keysyms(keysym, geometry);
+                keysyms.push(keysym.clone());
+                keysyms
+            })
+            .collect()
+    };
+    keyseq_map
+        .iter()
+        .map(|(c, keyseqs)| (*c, keyseqs.iter().map(expand).collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::PhysicalKey::*;
+    use Symbol::{Character, DeadKey};
+
+    fn shift(key: PhysicalKey) -> KeySymbol {
+        KeySymbol::new(Character(SHIFT), key, Mod::Base)
+    }
+
+    fn altgr() -> KeySymbol {
+        KeySymbol::new(Character(ALTGR), AltRight, Mod::Base)
+    }
+
+    #[test]
+    fn test_expand_modifiers() {
+        let ks_a_maj = KeySymbol::new(Character('A'), KeyA, Mod::Shift);
+        let ks_j_maj = KeySymbol::new(Character('J'), KeyJ, Mod::Shift);
+        let ks_lp = KeySymbol::new(Character('('), KeyS, Mod::Altgr);
+        let ks_rp = KeySymbol::new(Character(')'), KeyL, Mod::AltgrShift);
+        let ks_dk = KeySymbol::new(DeadKey('^'), KeyQ, Mod::Altgr);
+        let ks_e = KeySymbol::new(Character('e'), KeyD, Mod::Base);
+        let keyseq_map = HashMap::from([
+            ('A', vec![vec![ks_a_maj.clone()]]),
+            ('J', vec![vec![ks_j_maj.clone()]]),
+            ('(', vec![vec![ks_lp.clone()]]),
+            (')', vec![vec![ks_rp.clone()]]),
+            ('ê', vec![vec![ks_dk.clone(), ks_e.clone()]]),
+            ('e', vec![vec![ks_e.clone()]]),
+        ]);
+        let result = expand_modifiers(
+            &keyseq_map,
+            &ModifierConfig::default(),
+            &Geometry::from(BuiltinGeometry::ISO),
+        );
+        let expected = HashMap::from([
+            ('A', vec![vec![shift(ShiftRight), ks_a_maj]]),
+            ('J', vec![vec![shift(ShiftLeft), ks_j_maj]]),
+            ('(', vec![vec![altgr(), ks_lp]]),
+            (')', vec![vec![altgr(), shift(ShiftLeft), ks_rp]]),
+            ('ê', vec![vec![altgr(), ks_dk, ks_e.clone()]]),
+            ('e', vec![vec![ks_e]]),
+        ]);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn shift_side() {
+        let ks_a_maj = KeySymbol::new(Character('A'), KeyA, Mod::Shift);
+        let keyseq_map = HashMap::from([('A', vec![vec![ks_a_maj.clone()]])]);
+        let config = ModifierConfig {
+            shift_side: ShiftSide::Left,
+            ..Default::default()
+        };
+        let result = expand_modifiers(&keyseq_map, &config, &Geometry::from(BuiltinGeometry::ISO));
+        assert_eq!(result[&'A'], vec![vec![shift(ShiftLeft), ks_a_maj]]);
+    }
+
+    #[test]
+    fn modifier_fingers() {
+        let config = ModifierConfig {
+            left_shift_finger: Finger::Thumb,
+            altgr_finger: Finger::RightPinky,
+            ..Default::default()
+        };
+        let mut geometry = Geometry::from(BuiltinGeometry::ISO);
+        config.apply_fingers(&mut geometry);
+        assert_eq!(geometry.finger(ShiftLeft), Finger::Thumb);
+        assert_eq!(geometry.finger(ShiftRight), Finger::RightPinky);
+        assert_eq!(geometry.finger(AltRight), Finger::RightPinky);
+        assert_eq!(geometry.finger(KeyA), Finger::LeftPinky);
+    }
+}
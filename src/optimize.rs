@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+
+use log::info;
+
+use crate::{
+    geometry::Geometry,
+    kalamine::{Corpus, Layout, PhysicalKey},
+    score::{Score, Weights},
+    stats::{self, Options},
+    Error,
+};
+
+/// Settings of the simulated annealing
+#[derive(Debug, Clone)]
+pub struct OptimizeConfig {
+    pub iterations: usize,
+    /// Temperature at the first iteration, in score unit.
+    /// It decreases exponentially to a thousandth of it at the last iteration.
+    pub temperature: f32,
+    pub seed: u64,
+    /// Keys whose content must not move
+    pub pinned: HashSet<PhysicalKey>,
+}
+
+impl OptimizeConfig {
+    /// At least one iteration, and a positive temperature
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.iterations == 0 {
+            return Err(Error::InvalidConfig(String::from(
+                "there must be at least one iteration",
+            )));
+        }
+        if !self.temperature.is_finite() || self.temperature <= 0.0 {
+            return Err(Error::InvalidConfig(format!(
+                "the temperature must be a positive number, not {}",
+                self.temperature
+            )));
+        }
+        Ok(())
+    }
+
+    /// Temperature of the given iteration, on the exponential schedule
+    fn temperature_at(&self, iteration: usize) -> f32 {
+        let last = self.iterations.saturating_sub(1).max(1);
+        self.temperature * 0.001f32.powf(iteration as f32 / last as f32)
+    }
+}
+
+impl Default for OptimizeConfig {
+    fn default() -> Self {
+        OptimizeConfig {
+            iterations: 10_000,
+            temperature: 1.0,
+            seed: 0,
+            pinned: HashSet::new(),
+        }
+    }
+}
+
+/// Search a better layout by swapping the content of the keys (all the levels together),
+/// using simulated annealing on the score.
+/// Returns the best layout found and its score, or the error of invalid settings,
+/// of an invalid corpus or of a layout key missing from the geometry.
+pub fn optimize(
+    layout: &Layout,
+    corpus: &Corpus,
+    geometry: &Geometry,
+    options: &Options,
+    weights: &Weights,
+    config: &OptimizeConfig,
+) -> Result<(Layout, Score), Error> {
+    config.validate()?;
+    corpus.validate()?;
+    stats::check_keys(layout, geometry)?;
+    let evaluate = |layout: &Layout| -> Score {
+        stats::analyse_unchecked(layout, corpus, geometry, options).score(weights)
+    };
+
+    // the content can also move to the keys of the geometry that are empty in the layout
+    let mut swappable: Vec<PhysicalKey> = geometry
+        .keys
+        .keys()
+        .filter(|key| !config.pinned.contains(key) && !key.is_modifier())
+        .cloned()
+        .collect();
+    swappable.sort(); // for reproducible results with the same seed
+
+    let mut current = layout.clone();
+    let mut current_score = evaluate(&current);
+    let mut best = current.clone();
+    let mut best_score = current_score.clone();
+
+    if swappable.len() < 2 {
+        return Ok((best, best_score));
+    }
+
+    let mut rng = fastrand::Rng::with_seed(config.seed);
+
+    for i in 0..config.iterations {
+        // the schedule does not depend on the skipped swaps
+        let temperature = config.temperature_at(i);
+        if i % 1000 == 0 {
+            info!(
+                "iteration {i}: score {:.3}, best {:.3}",
+                current_score.total, best_score.total
+            );
+        }
+        let key1 = swappable[rng.usize(..swappable.len())];
+        let key2 = swappable[rng.usize(..swappable.len())];
+        if key1 == key2
+            || !(current.keymap.contains_key(&key1) || current.keymap.contains_key(&key2))
+        {
+            continue;
+        }
+
+        swap_keys(&mut current, key1, key2);
+        let score = evaluate(&current);
+        let delta = score.total - current_score.total;
+
+        if delta <= 0.0 || rng.f32() < (-delta / temperature).exp() {
+            current_score = score;
+            if current_score.total < best_score.total {
+                best = current.clone();
+                best_score = current_score.clone();
+            }
+        } else {
+            swap_keys(&mut current, key1, key2); // revert
+        }
+    }
+
+    Ok((best, best_score))
+}
+
+fn swap_keys(layout: &mut Layout, key1: PhysicalKey, key2: PhysicalKey) {
+    let mapping1 = layout.keymap.remove(&key1);
+    let mapping2 = layout.keymap.remove(&key2);
+    if let Some(mapping) = mapping1 {
+        layout.keymap.insert(key2, mapping);
+    }
+    if let Some(mapping) = mapping2 {
+        layout.keymap.insert(key1, mapping);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{ModMapping, PhysicalKey::*};
+    use crate::score::Metric;
+
+    #[test]
+    fn optimize_sfb() {
+        // "a" and "b" are on the same finger, and always typed together
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyF, ModMapping::from(vec!["a", "A"])),
+                (KeyR, ModMapping::from(vec!["b", "B"])),
+                (KeyJ, ModMapping::from(vec!["c", "C"])),
+                (KeyK, ModMapping::from(vec!["d", "D"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('a', 1.0), ('b', 1.0)]),
+            digrams: HashMap::from([(['a', 'b'], 1.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let weights = Weights {
+            metrics: HashMap::from([(Metric::Sfb, 1.0)]),
+            finger_usage: HashMap::new(),
+            rows: HashMap::new(),
+        };
+        let config = OptimizeConfig {
+            iterations: 100,
+            pinned: HashSet::from([KeyF]),
+            ..Default::default()
+        };
+
+        let (best, score) = optimize(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Options::default(),
+            &weights,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(score.total, 0.0);
+        assert_eq!(best.keymap[&KeyF], layout.keymap[&KeyF]);
+        assert_ne!(best.keymap.get(&KeyR), layout.keymap.get(&KeyR));
+        // The levels stay together
+        assert!(best
+            .keymap
+            .values()
+            .any(|mapping| *mapping == ModMapping::from(vec!["b", "B"])));
+    }
+
+    #[test]
+    fn optimize_to_empty_key() {
+        // "b" can only leave the finger of "a" for a key missing from the layout
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyF, ModMapping::from(vec!["a"])),
+                (KeyR, ModMapping::from(vec!["b"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('a', 1.0), ('b', 1.0)]),
+            digrams: HashMap::from([(['a', 'b'], 1.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let weights = Weights {
+            metrics: HashMap::from([(Metric::Sfb, 1.0)]),
+            finger_usage: HashMap::new(),
+            rows: HashMap::new(),
+        };
+        let config = OptimizeConfig {
+            iterations: 1000,
+            pinned: HashSet::from([KeyF]),
+            ..Default::default()
+        };
+
+        let (best, score) = optimize(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Options::default(),
+            &weights,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(score.total, 0.0);
+        assert_eq!(best.keymap.len(), 2);
+        assert!(!best.keymap.contains_key(&KeyR));
+    }
+
+    #[test]
+    fn optimize_config() {
+        let config = OptimizeConfig {
+            iterations: 100,
+            temperature: 2.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.temperature_at(0), 2.0);
+        assert!((config.temperature_at(99) - 0.002).abs() < 1e-6);
+
+        for config in [
+            OptimizeConfig {
+                iterations: 0,
+                ..Default::default()
+            },
+            OptimizeConfig {
+                temperature: 0.0,
+                ..Default::default()
+            },
+            OptimizeConfig {
+                temperature: f32::NAN,
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+        }
+    }
+
+    #[test]
+    fn swap_missing_key() {
+        let mut layout = Layout {
+            keymap: HashMap::from([(KeyA, ModMapping::from(vec!["a"]))]),
+            deadkeys: HashMap::new(),
+        };
+        swap_keys(&mut layout, KeyA, KeyB);
+        assert_eq!(
+            layout.keymap,
+            HashMap::from([(KeyB, ModMapping::from(vec!["a"]))])
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{geometry::Row, hands::Finger, stats::Stats};
+
+/// Statistics that can be weighted in the score
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::Display,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Metric {
+    Unsupported,
+    Sku,
+    Sfb,
+    /// SFBs weighted by the distance between their keys
+    SfbDistance,
+    /// Distance travelled by all the fingers
+    Travel,
+    Lsb,
+    Scissors,
+    InRolls,
+    OutRolls,
+    Sks,
+    Sfs,
+    Redirects,
+    BadRedirects,
+    Alternations,
+    OneHandInRolls,
+    OneHandOutRolls,
+    RollAlternations,
+    /// Same finger skipgrams of all the distances, weighted by their decay
+    SkipgramSfs,
+}
+
+impl Metric {
+    pub fn value(&self, stats: &Stats) -> f32 {
+        match self {
+            Metric::Unsupported => stats.symbols.total_unsupported,
+            Metric::Sku => stats.bigrams.total_sku,
+            Metric::Sfb => stats.bigrams.total_sfb,
+            Metric::SfbDistance => stats.bigrams.total_sfb_distance,
+            Metric::Travel => stats.bigrams.total_travel,
+            Metric::Lsb => stats.bigrams.total_lsb,
+            Metric::Scissors => stats.bigrams.total_scissors,
+            Metric::InRolls => stats.bigrams.total_in_rolls,
+            Metric::OutRolls => stats.bigrams.total_out_rolls,
+            Metric::Sks => stats.trigrams.total_sks,
+            Metric::Sfs => stats.trigrams.total_sfs,
+            Metric::Redirects => stats.trigrams.total_redirects,
+            Metric::BadRedirects => stats.trigrams.total_bad_redirects,
+            Metric::Alternations => stats.trigrams.total_alternations,
+            Metric::OneHandInRolls => stats.trigrams.total_one_hand_in_rolls,
+            Metric::OneHandOutRolls => stats.trigrams.total_one_hand_out_rolls,
+            Metric::RollAlternations => stats.trigrams.total_roll_alternations,
+            Metric::SkipgramSfs => stats.skipgrams.total_sfs,
+        }
+    }
+}
+
+/// Weights of the score, read from a json file (see files/weights.json).
+/// The score is a penalty: the lower, the better.
+/// Use negative weights for the statistics that should be maximized (e.g. rolls).
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Weights {
+    /// Weight of each statistic total
+    #[serde(default)]
+    pub metrics: HashMap<Metric, f32>,
+    /// Ideal usage of each finger, the distance to the target is penalized
+    #[serde(default)]
+    pub finger_usage: HashMap<Finger, FingerTarget>,
+    /// Weight of the usage of each row
+    #[serde(default)]
+    pub rows: HashMap<Row, f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FingerTarget {
+    pub target: f32,
+    pub weight: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        use Metric::*;
+        Weights {
+            metrics: HashMap::from([
+                (Unsupported, 10.0),
+                (Sfb, 5.0),
+                (Lsb, 1.0),
+                (Scissors, 2.0),
+                (InRolls, -0.5),
+                (OutRolls, -0.25),
+                (Sfs, 1.0),
+                (Redirects, 1.0),
+                (BadRedirects, 3.0),
+            ]),
+            finger_usage: HashMap::new(),
+            rows: HashMap::from([(Row::Digits, 0.5), (Row::Upper, 0.1), (Row::Lower, 0.2)]),
+        }
+    }
+}
+
+/// Part of the score due to one weighted statistic, named after its key in the
+/// weights file (e.g. `sfb`, `finger_usage.left_middle`, `rows.upper`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Contribution {
+    pub name: String,
+    pub value: f32,
+    pub weight: f32,
+    pub score: f32,
+}
+
+impl Contribution {
+    fn new(name: String, value: f32, weight: f32) -> Self {
+        Contribution {
+            name,
+            value,
+            weight,
+            score: value * weight,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Score {
+    pub total: f32,
+    pub contributions: Vec<Contribution>,
+}
+
+impl Stats {
+    /// Combine the statistics into a single score, lower is better
+    pub fn score(&self, weights: &Weights) -> Score {
+        let mut contributions = Vec::new();
+
+        let metrics: BTreeMap<_, _> = weights.metrics.iter().collect();
+        for (metric, weight) in metrics {
+            contributions.push(Contribution::new(
+                metric.to_string(),
+                metric.value(self),
+                *weight,
+            ));
+        }
+
+        let finger_usage: BTreeMap<_, _> = weights.finger_usage.iter().collect();
+        for (finger, target) in finger_usage {
+            let usage = self.unigrams.finger_usage[finger];
+            contributions.push(Contribution::new(
+                format!("finger_usage.{}", key_name(finger)),
+                (usage - target.target).abs(),
+                target.weight,
+            ));
+        }
+
+        let rows: BTreeMap<_, _> = weights.rows.iter().collect();
+        for (row, weight) in rows {
+            contributions.push(Contribution::new(
+                format!("rows.{}", key_name(row)),
+                self.unigrams.row_usage[row],
+                *weight,
+            ));
+        }
+
+        Score {
+            total: contributions.iter().map(|c| c.score).sum(),
+            contributions,
+        }
+    }
+}
+
+/// Name of a finger or a row in the weights file
+fn key_name<T: serde::Serialize>(key: &T) -> String {
+    match serde_json::to_value(key) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!("keys of the weights are serialized as strings"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+
+    use super::*;
+    use crate::{
+        geometry::{BuiltinGeometry, Geometry},
+        kalamine::{Corpus, Layout, ModMapping, PhysicalKey::*},
+    };
+
+    fn stats() -> Stats {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyD, ModMapping::from(vec!["e"])),
+                (KeyE, ModMapping::from(vec!["d"])),
+                (KeyJ, ModMapping::from(vec!["n"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('e', 6.0), ('d', 2.0), ('n', 1.0), ('z', 1.0)]),
+            digrams: HashMap::from([(['e', 'd'], 2.0), (['e', 'n'], 1.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        crate::analyse(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Default::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn weighted_score() {
+        let weights = Weights {
+            metrics: HashMap::from([(Metric::Sfb, 2.0), (Metric::Unsupported, 10.0)]),
+            finger_usage: HashMap::from([(
+                Finger::LeftMiddle,
+                FingerTarget {
+                    target: 5.0,
+                    weight: 0.5,
+                },
+            )]),
+            rows: HashMap::from([(Row::Upper, 1.0)]),
+        };
+        let score = stats().score(&weights);
+        let expected = Vec::from([
+            Contribution::new(String::from("unsupported"), 1.0, 10.0),
+            Contribution::new(String::from("sfb"), 2.0, 2.0),
+            Contribution::new(String::from("finger_usage.left_middle"), 3.0, 0.5),
+            Contribution::new(String::from("rows.upper"), 2.0, 1.0),
+        ]);
+        assert_eq!(score.contributions, expected);
+        assert_eq!(score.total, 17.5);
+    }
+
+    #[test]
+    fn deserialize_weights() {
+        let json = r#"{
+            "metrics": { "sfb": 2.0, "in_rolls": -1.0 },
+            "finger_usage": { "left_pinkie": { "target": 8.0, "weight": 0.5 } },
+            "rows": { "digits": 1.0 }
+        }"#;
+        let weights: Weights = serde_json::from_str(json).unwrap();
+        let expected = Weights {
+            metrics: HashMap::from([(Metric::Sfb, 2.0), (Metric::InRolls, -1.0)]),
+            finger_usage: HashMap::from([(
+                Finger::LeftPinky,
+                FingerTarget {
+                    target: 8.0,
+                    weight: 0.5,
+                },
+            )]),
+            rows: HashMap::from([(Row::Digits, 1.0)]),
+        };
+        assert_eq!(weights, expected);
+    }
+
+    #[test]
+    fn deserialize_weights_unknown_metric() {
+        let json = r#"{ "metrics": { "sfbs": 2.0 } }"#;
+        let result: Result<Weights, _> = serde_json::from_str(json);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn example_weights_file() {
+        let file = File::open("files/weights.json").unwrap();
+        let weights: Weights = serde_json::from_reader(file).unwrap();
+        assert_eq!(weights.metrics, Weights::default().metrics);
+        assert_eq!(weights.rows, Weights::default().rows);
+    }
+}
@@ -1,32 +1,124 @@
 pub mod bigrams;
+pub mod deadkeys;
+mod json;
+pub mod skipgrams;
 pub mod symbols;
 pub mod trigrams;
 pub mod unigrams;
 mod utils;
 
+use crate::corpus;
 use crate::geometry::Geometry;
-use crate::kalamine::{Corpus, Layout};
-use crate::{corpus, keyseq};
+use crate::kalamine::{Corpus, Layout, PhysicalKey};
+use crate::keyseq::{self, KeySeqChoice};
+use crate::modifiers::{self, ModifierConfig};
+use crate::Error;
 
-pub fn analyse(layout: &Layout, corpus: &Corpus, geometry: Geometry) -> Stats {
-    let char_to_keyseq = keyseq::build_keyseq_map(&layout.keymap, &layout.deadkeys);
+/// Optional settings of the analysis
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Count the modifier presses (Shift, AltGr) as keystrokes
+    pub modifiers: Option<ModifierConfig>,
+    /// Key sequences used for the characters typed in several ways
+    pub keyseq_choice: KeySeqChoice,
+}
+
+/// Statistics of a layout typing a corpus, which must have valid frequencies.
+/// The keys of the layout with symbols must be on the keyboard of the geometry.
+pub fn analyse(
+    layout: &Layout,
+    corpus: &Corpus,
+    geometry: &Geometry,
+    options: &Options,
+) -> Result<Stats, Error> {
+    corpus.validate()?;
+    check_keys(layout, geometry)?;
+    Ok(analyse_unchecked(layout, corpus, geometry, options))
+}
+
+/// Error for the first key of the layout with symbols that is not in the geometry
+pub(crate) fn check_keys(layout: &Layout, geometry: &Geometry) -> Result<(), Error> {
+    let mut keys: Vec<PhysicalKey> = layout
+        .keymap
+        .iter()
+        .filter(|(_, mapping)| mapping.map.iter().any(|(_, symbol)| symbol.is_some()))
+        .map(|(key, _)| *key)
+        .collect();
+    keys.sort(); // same error between runs
+    for key in keys {
+        geometry.key(key)?;
+    }
+    Ok(())
+}
+
+/// Same as `analyse`, for a corpus and keys already checked (e.g. analysed many times)
+pub(crate) fn analyse_unchecked(
+    layout: &Layout,
+    corpus: &Corpus,
+    geometry: &Geometry,
+    options: &Options,
+) -> Stats {
+    let keyseq_map = keyseq::build_keyseq_map(&layout.keymap, &layout.deadkeys);
+    let mut geometry = geometry.clone();
+
+    let expanded_keyseq_map;
+    let mut char_to_keyseq = &keyseq_map;
+    if let Some(config) = &options.modifiers {
+        config.apply_fingers(&mut geometry);
+        expanded_keyseq_map = modifiers::expand_modifiers(&keyseq_map, config, &geometry);
+        char_to_keyseq = &expanded_keyseq_map;
+    }
+
+    let keyseq_usage = corpus::keyseq_usage(
+        &corpus.symbols,
+        &corpus.digrams,
+        char_to_keyseq,
+        options.keyseq_choice,
+        &geometry,
+    );
+    let symbol_stats = symbols::symbol_stats(&corpus.symbols, &keyseq_map, &keyseq_usage);
 
-    let symbol_stats = symbols::symbol_stats(&corpus.symbols, &char_to_keyseq);
+    let symbol_freq = corpus::keysym_freq(&corpus.symbols, char_to_keyseq, &keyseq_usage);
+    let unigram_stats = unigrams::unigram_stats(&symbol_freq, &geometry);
 
-    let symbol_freq = corpus::keysym_freq(&corpus.symbols, &char_to_keyseq);
-    let unigram_stats = unigrams::unigram_stats(&symbol_freq);
+    let bigrams_freq = corpus::keysym_ngram_freq(
+        &corpus.digrams,
+        char_to_keyseq,
+        options.keyseq_choice,
+        &geometry,
+    );
+    let bigram_stats = bigrams::bigram_stats(&bigrams_freq, &geometry);
 
-    let bigrams_freq = corpus::keysym_ngram_freq(&corpus.digrams, &char_to_keyseq);
-    let bigram_stats = bigrams::bigram_stats(&bigrams_freq, geometry);
+    let trigrams_freq = corpus::keysym_ngram_freq(
+        &corpus.trigrams,
+        char_to_keyseq,
+        options.keyseq_choice,
+        &geometry,
+    );
+    let trigram_stats = trigrams::trigram_stats(&trigrams_freq, &geometry);
 
-    let trigrams_freq = corpus::keysym_ngram_freq(&corpus.trigrams, &char_to_keyseq);
-    let trigram_stats = trigrams::trigram_stats(&trigrams_freq);
+    let skipgrams_freq: Vec<_> = corpus
+        .skipgrams_or_from_trigrams()
+        .iter()
+        .map(|skipgrams| corpus::keysym_skipgram_freq(skipgrams, char_to_keyseq, &keyseq_usage))
+        .collect();
+    let skipgram_stats = skipgrams::skipgram_stats(&skipgrams_freq, &geometry);
+
+    let dead_key_stats = deadkeys::dead_key_stats(
+        &corpus.symbols,
+        char_to_keyseq,
+        &keyseq_usage,
+        &bigram_stats,
+        &trigram_stats,
+    );
 
     Stats {
         symbols: symbol_stats,
         unigrams: unigram_stats,
         bigrams: bigram_stats,
         trigrams: trigram_stats,
+        skipgrams: skipgram_stats,
+        dead_keys: dead_key_stats,
     }
 }
 
@@ -35,4 +127,48 @@ pub struct Stats {
     pub unigrams: unigrams::UnigramStats,
     pub bigrams: bigrams::BigramStats,
     pub trigrams: trigrams::TrigramStats,
+    pub skipgrams: skipgrams::SkipgramStats,
+    pub dead_keys: deadkeys::DeadKeyStats,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{ModMapping, PhysicalKey::*};
+
+    #[test]
+    fn key_not_in_geometry() {
+        let mut layout = Layout {
+            keymap: HashMap::from([
+                (KeyQ, ModMapping::from(vec!["a"])),
+                (IntlBackslash, ModMapping::from(vec!["<", ">"])),
+            ]),
+            deadkeys: HashMap::new(),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('a', 1.0)]),
+            digrams: HashMap::new(),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let ansi = Geometry::from(BuiltinGeometry::ANSI);
+        let iso = Geometry::from(BuiltinGeometry::ISO);
+
+        assert!(matches!(
+            analyse(&layout, &corpus, &ansi, &Default::default()),
+            Err(Error::UnsupportedKey(IntlBackslash))
+        ));
+        assert!(analyse(&layout, &corpus, &iso, &Default::default()).is_ok());
+
+        // a key without symbols does not need to be on the keyboard
+        layout
+            .keymap
+            .insert(IntlBackslash, ModMapping::from(Vec::<&str>::new()));
+        assert!(analyse(&layout, &corpus, &ansi, &Default::default()).is_ok());
+    }
 }
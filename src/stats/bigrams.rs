@@ -1,6 +1,6 @@
 use strum::IntoEnumIterator;
 
-use crate::geometry::{Geometry, Row, U};
+use crate::geometry::{Geometry, Row};
 use crate::hands::{Finger, Hand, RollDirection};
 use crate::kalamine::{PhysicalKey, Symbol};
 use crate::keyseq::KeySymbol;
@@ -10,11 +10,15 @@ use super::utils::{self, add_or_insert};
 
 type Bigram = [Symbol; 2];
 
-pub fn bigram_stats(bigrams_freq: &Vec<([KeySymbol; 2], f32)>, geometry: Geometry) -> BigramStats {
+pub fn bigram_stats(bigrams_freq: &[([KeySymbol; 2], f32)], geometry: &Geometry) -> BigramStats {
     let mut sfb: Vec<(Bigram, f32)> = Vec::new();
+    let mut sfb_keys: HashMap<[PhysicalKey; 2], f32> = HashMap::new();
     let mut sku: Vec<(Bigram, f32)> = Vec::new();
     let mut per_finger_sfb: HashMap<Finger, f32> = Finger::iter().map(|f| (f, 0.0)).collect();
     let mut per_finger_sku: HashMap<Finger, f32> = Finger::iter().map(|f| (f, 0.0)).collect();
+    let mut per_finger_sfb_distance: HashMap<Finger, f32> =
+        Finger::iter().map(|f| (f, 0.0)).collect();
+    let mut per_finger_travel: HashMap<Finger, f32> = Finger::iter().map(|f| (f, 0.0)).collect();
     let mut in_rolls: Vec<(Bigram, f32)> = Vec::new();
     let mut out_rolls: Vec<(Bigram, f32)> = Vec::new();
     let mut lsb: Vec<(Bigram, f32)> = Vec::new();
@@ -25,17 +29,32 @@ pub fn bigram_stats(bigrams_freq: &Vec<([KeySymbol; 2], f32)>, geometry: Geometr
         let freq = *freq;
         let key1 = bigram_keys[0].key;
         let key2 = bigram_keys[1].key;
+        let finger2 = geometry.finger(key2);
+
+        // the finger comes from its home key, unless it just pressed the first key
+        let from = if geometry.finger(key1) == finger2 {
+            Some(key1)
+        } else {
+            geometry.home_key(finger2)
+        };
+        if let Some(distance) = from.and_then(|from| geometry.distance(from, key2)) {
+            add_or_insert(per_finger_travel.entry(finger2), freq * distance);
+        }
 
         if key1 == key2 {
             sku.push((bigram, freq));
-            add_or_insert(per_finger_sku.entry(key1.finger()), freq);
-        } else if key1.finger() == key2.finger() {
+            add_or_insert(per_finger_sku.entry(geometry.finger(key1)), freq);
+        } else if geometry.finger(key1) == geometry.finger(key2) {
             sfb.push((bigram, freq));
-            add_or_insert(per_finger_sfb.entry(key1.finger()), freq);
+            add_or_insert(sfb_keys.entry([key1, key2]), freq);
+            add_or_insert(per_finger_sfb.entry(geometry.finger(key1)), freq);
+            if let Some(distance) = geometry.distance(key1, key2) {
+                add_or_insert(per_finger_sfb_distance.entry(finger2), freq * distance);
+            }
         } else {
-            if is_in_roll(key1, key2) {
+            if is_in_roll(key1, key2, geometry) {
                 in_rolls.push((bigram, freq));
-            } else if is_out_roll(key1, key2) {
+            } else if is_out_roll(key1, key2, geometry) {
                 out_rolls.push((bigram, freq));
             }
             if is_lsb(key1, key2, geometry) {
@@ -47,6 +66,11 @@ pub fn bigram_stats(bigrams_freq: &Vec<([KeySymbol; 2], f32)>, geometry: Geometr
         }
     }
 
+    let mut per_hand_travel: HashMap<Hand, f32> = Hand::iter().map(|h| (h, 0.0)).collect();
+    for (finger, travel) in &per_finger_travel {
+        add_or_insert(per_hand_travel.entry(finger.hand()), *travel);
+    }
+
     BigramStats {
         total_sku: utils::result_sum(&sku),
         total_sfb: utils::result_sum(&sfb),
@@ -55,10 +79,16 @@ pub fn bigram_stats(bigrams_freq: &Vec<([KeySymbol; 2], f32)>, geometry: Geometr
         total_in_rolls: utils::result_sum(&in_rolls),
         total_out_rolls: utils::result_sum(&out_rolls),
         total_all_rolls: utils::result_sum(&in_rolls) + utils::result_sum(&out_rolls),
-        per_finger_sku: per_finger_sku,
-        per_finger_sfb: per_finger_sfb,
+        total_sfb_distance: per_finger_sfb_distance.values().sum(),
+        total_travel: per_finger_travel.values().sum(),
+        per_finger_sku,
+        per_finger_sfb,
+        per_finger_sfb_distance,
+        per_finger_travel,
+        per_hand_travel,
         list_sku: utils::result_vec(sku),
         list_sfb: utils::result_vec(sfb),
+        list_sfb_keys: utils::result_vec(sfb_keys.into_iter().collect()),
         list_lsb: utils::result_vec(lsb),
         list_in_rolls: utils::result_vec(in_rolls),
         list_out_rolls: utils::result_vec(out_rolls),
@@ -68,8 +98,8 @@ pub fn bigram_stats(bigrams_freq: &Vec<([KeySymbol; 2], f32)>, geometry: Geometr
 
 /// Using Keyboard layout doc definition
 /// https://docs.google.com/document/d/1W0jhfqJI2ueJ2FNseR4YAFpNfsUM-_FlREHbpNGmC2o/edit?tab=t.i8oe0bwffr95
-pub fn is_lsb(key1: PhysicalKey, key2: PhysicalKey, geometry: Geometry) -> bool {
-    let finger_dist = match Finger::distance(key1.finger(), key2.finger()) {
+pub fn is_lsb(key1: PhysicalKey, key2: PhysicalKey, geometry: &Geometry) -> bool {
+    let finger_dist = match Finger::distance(geometry.finger(key1), geometry.finger(key2)) {
         Some(dist) => dist,
         None => return false,
     };
@@ -78,8 +108,8 @@ pub fn is_lsb(key1: PhysicalKey, key2: PhysicalKey, geometry: Geometry) -> bool
         None => return false,
     };
     match finger_dist {
-        1 => horizontal_dist >= 2 * U,         // adjacent fingers (2U)
-        2 => horizontal_dist >= 3 * U + U / 2, // semi-adjacent fingers (3.5U)
+        1 => horizontal_dist >= 2.0, // adjacent fingers (2U)
+        2 => horizontal_dist >= 3.5, // semi-adjacent fingers (3.5U)
         _ => false,
     }
 }
@@ -88,38 +118,46 @@ pub fn is_lsb(key1: PhysicalKey, key2: PhysicalKey, geometry: Geometry) -> bool
 /// https://docs.google.com/document/d/1W0jhfqJI2ueJ2FNseR4YAFpNfsUM-_FlREHbpNGmC2o/edit?tab=t.3j7hpqkn3etl
 /// TODO: maybe split in half/full scissors, or find a "bad scissors" definition
 /// (for now the worst scissors are buried in the list because of low frequencies)
-pub fn is_scissors(key1: PhysicalKey, key2: PhysicalKey, geometry: Geometry) -> bool {
-    if key1.hand() != key2.hand() || key1.hand() == Hand::Thumbs {
+pub fn is_scissors(key1: PhysicalKey, key2: PhysicalKey, geometry: &Geometry) -> bool {
+    if geometry.hand(key1) != geometry.hand(key2) || geometry.hand(key1) == Hand::Thumbs {
         return false;
     };
+    let finger1 = geometry.finger(key1);
+    let finger2 = geometry.finger(key2);
     let horizontal_dist = match geometry.horizontal_distance(key1, key2) {
         Some(dist) => dist,
         None => return false,
     };
-    if Row::distance(key1.row(), key2.row()) >= 2 && horizontal_dist <= 3 * U
-    && key1.finger() != Finger::LeftIndex && key1.finger() != Finger::RightIndex
-    && key2.finger() != Finger::LeftIndex && key2.finger() != Finger::RightIndex {
+    let row1 = geometry.row(key1);
+    let row2 = geometry.row(key2);
+    if Row::distance(row1, row2) >= 2
+        && horizontal_dist <= 3.0
+        && finger1 != Finger::LeftIndex
+        && finger1 != Finger::RightIndex
+        && finger2 != Finger::LeftIndex
+        && finger2 != Finger::RightIndex
+    {
         true
-    } else if Row::distance(key1.row(), key2.row()) >= 1 && horizontal_dist <= 2 * U + U / 2 {
-        if key1.row() > key2.row() {
-            key2.finger().prefers_being_higher(key1.finger())
+    } else if Row::distance(row1, row2) >= 1 && horizontal_dist <= 2.5 {
+        if row1 > row2 {
+            finger2.prefers_being_higher(finger1)
         } else {
-            key1.finger().prefers_being_higher(key2.finger())
+            finger1.prefers_being_higher(finger2)
         }
     } else {
         false
     }
 }
 
-pub fn is_in_roll(key1: PhysicalKey, key2: PhysicalKey) -> bool {
-    let finger1 = key1.finger();
-    let finger2 = key2.finger();
+pub fn is_in_roll(key1: PhysicalKey, key2: PhysicalKey, geometry: &Geometry) -> bool {
+    let finger1 = geometry.finger(key1);
+    let finger2 = geometry.finger(key2);
     finger1.roll_direction(finger2) == RollDirection::Inside
 }
 
-pub fn is_out_roll(key1: PhysicalKey, key2: PhysicalKey) -> bool {
-    let finger1 = key1.finger();
-    let finger2 = key2.finger();
+pub fn is_out_roll(key1: PhysicalKey, key2: PhysicalKey, geometry: &Geometry) -> bool {
+    let finger1 = geometry.finger(key1);
+    let finger2 = geometry.finger(key2);
     finger1.roll_direction(finger2) == RollDirection::Outside
 }
 
@@ -131,10 +169,20 @@ pub struct BigramStats {
     pub total_in_rolls: f32,
     pub total_out_rolls: f32,
     pub total_all_rolls: f32,
+    /// SFBs weighted by the distance between their keys ("SFB severity")
+    pub total_sfb_distance: f32,
+    /// Distance travelled by the fingers, from their home key or from the
+    /// first key of an SFB
+    pub total_travel: f32,
     pub per_finger_sku: HashMap<Finger, f32>,
     pub per_finger_sfb: HashMap<Finger, f32>,
+    pub per_finger_sfb_distance: HashMap<Finger, f32>,
+    pub per_finger_travel: HashMap<Finger, f32>,
+    pub per_hand_travel: HashMap<Hand, f32>,
     pub list_sku: Vec<(Bigram, f32)>,
     pub list_sfb: Vec<(Bigram, f32)>,
+    /// SFBs by pair of keys, whatever the symbols typed on them
+    pub list_sfb_keys: Vec<([PhysicalKey; 2], f32)>,
     pub list_lsb: Vec<(Bigram, f32)>,
     pub list_in_rolls: Vec<(Bigram, f32)>,
     pub list_out_rolls: Vec<(Bigram, f32)>,
@@ -147,82 +195,153 @@ mod tests {
 
     use super::*;
     use crate::kalamine::PhysicalKey::*;
-    use crate::geometry::Geometry::*;
+    use crate::geometry::BuiltinGeometry::*;
 
     #[test]
     fn in_roll() {
-        assert!(is_in_roll(KeyD, KeyF));
-        assert!(!is_in_roll(KeyF, KeyD));
-        assert!(is_in_roll(KeyK, KeyJ));
-        assert!(!is_in_roll(KeyJ, KeyK));
-        assert!(!is_in_roll(KeyA, Space));
+        let g = &Geometry::from(ISO);
+        assert!(is_in_roll(KeyD, KeyF, g));
+        assert!(!is_in_roll(KeyF, KeyD, g));
+        assert!(is_in_roll(KeyK, KeyJ, g));
+        assert!(!is_in_roll(KeyJ, KeyK, g));
+        assert!(!is_in_roll(KeyA, Space, g));
     }
 
     #[test]
     fn out_roll() {
-        assert!(!is_out_roll(KeyD, KeyF));
-        assert!(is_out_roll(KeyF, KeyD));
-        assert!(!is_out_roll(KeyK, KeyJ));
-        assert!(is_out_roll(KeyJ, KeyK));
-        assert!(!is_out_roll(KeyB, Space));
+        let g = &Geometry::from(ISO);
+        assert!(!is_out_roll(KeyD, KeyF, g));
+        assert!(is_out_roll(KeyF, KeyD, g));
+        assert!(!is_out_roll(KeyK, KeyJ, g));
+        assert!(is_out_roll(KeyJ, KeyK, g));
+        assert!(!is_out_roll(KeyB, Space, g));
     }
 
     #[test]
     fn lsb() {
-        assert!(!is_lsb(KeyQ, KeyT, Ortho));
-        assert!(!is_lsb(KeyW, KeyT, Ortho));
-        assert!(is_lsb(KeyE, KeyT, Ortho)); // Middle-Index LSB
-        assert!(!is_lsb(KeyR, KeyT, Ortho));
-        assert!(!is_lsb(KeyT, KeyT, Ortho));
-        assert!(!is_lsb(KeyY, KeyT, Ortho));
+        let ortho = &Geometry::from(Ortho);
+        let ansi = &Geometry::from(ANSI);
+        assert!(!is_lsb(KeyQ, KeyT, ortho));
+        assert!(!is_lsb(KeyW, KeyT, ortho));
+        assert!(is_lsb(KeyE, KeyT, ortho)); // Middle-Index LSB
+        assert!(!is_lsb(KeyR, KeyT, ortho));
+        assert!(!is_lsb(KeyT, KeyT, ortho));
+        assert!(!is_lsb(KeyY, KeyT, ortho));
         
-        assert!(!is_lsb(KeyQ, KeyB, ANSI));
-        assert!(is_lsb(KeyW, KeyB, ANSI)); // LSB due to stagger
-        assert!(is_lsb(KeyE, KeyB, ANSI)); 
-        assert!(!is_lsb(KeyR, KeyB, ANSI));
-        assert!(!is_lsb(KeyT, KeyB, ANSI));
+        assert!(!is_lsb(KeyQ, KeyB, ansi));
+        assert!(is_lsb(KeyW, KeyB, ansi)); // LSB due to stagger
+        assert!(is_lsb(KeyE, KeyB, ansi)); 
+        assert!(!is_lsb(KeyR, KeyB, ansi));
+        assert!(!is_lsb(KeyT, KeyB, ansi));
 
-        assert!(!is_lsb(KeyW, KeyG, ANSI)); // stagger not big enough
+        assert!(!is_lsb(KeyW, KeyG, ansi)); // stagger not big enough
         
-        assert!(!is_lsb(KeyH, Quote, ANSI));
-        assert!(!is_lsb(KeyJ, Quote, ANSI));
-        assert!(!is_lsb(KeyK, Quote, ANSI));
-        assert!(is_lsb(KeyL, Quote, ANSI)); // Ring-Pinky LSB
-        assert!(!is_lsb(Semicolon, Quote, ANSI));
+        assert!(!is_lsb(KeyH, Quote, ansi));
+        assert!(!is_lsb(KeyJ, Quote, ansi));
+        assert!(!is_lsb(KeyK, Quote, ansi));
+        assert!(is_lsb(KeyL, Quote, ansi)); // Ring-Pinky LSB
+        assert!(!is_lsb(Semicolon, Quote, ansi));
         
-        assert!(!is_lsb(KeyA, Space, Ortho));
+        assert!(!is_lsb(KeyA, Space, ortho));
+
+        let ansi_opti = &Geometry::from(ANSIOpti);
+        assert!(!is_lsb(KeyX, KeyT, ansi));
+        assert!(is_lsb(KeyX, KeyT, ansi_opti)); // Middle-Index LSB with angle mod
     }
 
     #[test]
     fn scissors() {
-        assert!(is_scissors(KeyQ, KeyS, ANSI));
-        assert!(is_scissors(KeyQ, KeyD, ANSI));
-        assert!(!is_scissors(KeyQ, KeyF, ANSI));
-        assert!(!is_scissors(KeyQ, KeyG, ANSI));
-        assert!(is_scissors(KeyQ, KeyX, ANSI));
-        assert!(is_scissors(KeyQ, KeyC, ANSI));
-        assert!(!is_scissors(KeyQ, KeyV, ANSI));
-        assert!(!is_scissors(KeyQ, KeyB, ANSI));
-
-        assert!(!is_scissors(KeyV, KeyA, ANSI));
-        assert!(!is_scissors(KeyV, KeyS, ANSI));
-        assert!(!is_scissors(KeyV, KeyD, ANSI));
-        assert!(!is_scissors(KeyV, KeyQ, ANSI));
-        assert!(!is_scissors(KeyV, KeyW, ANSI));
-        assert!(!is_scissors(KeyV, KeyE, ANSI));
-        assert!(!is_scissors(KeyV, KeyB, ANSI));
+        let ansi = &Geometry::from(ANSI);
+        assert!(is_scissors(KeyQ, KeyS, ansi));
+        assert!(is_scissors(KeyQ, KeyD, ansi));
+        assert!(!is_scissors(KeyQ, KeyF, ansi));
+        assert!(!is_scissors(KeyQ, KeyG, ansi));
+        assert!(is_scissors(KeyQ, KeyX, ansi));
+        assert!(is_scissors(KeyQ, KeyC, ansi));
+        assert!(!is_scissors(KeyQ, KeyV, ansi));
+        assert!(!is_scissors(KeyQ, KeyB, ansi));
+
+        assert!(!is_scissors(KeyV, KeyA, ansi));
+        assert!(!is_scissors(KeyV, KeyS, ansi));
+        assert!(!is_scissors(KeyV, KeyD, ansi));
+        assert!(!is_scissors(KeyV, KeyQ, ansi));
+        assert!(!is_scissors(KeyV, KeyW, ansi));
+        assert!(!is_scissors(KeyV, KeyE, ansi));
+        assert!(!is_scissors(KeyV, KeyB, ansi));
         
         // 2 rows of difference with no index is almost always scissors
-        assert!(is_scissors(KeyZ, KeyW, ANSI));
-        assert!(is_scissors(KeyX, KeyE, ANSI));
-        assert!(is_scissors(KeyC, KeyR, ANSI));
-        assert!(!is_scissors(KeyE, KeyV, ANSI));
-        assert!(is_scissors(KeyC, KeyW, ANSI));
-        assert!(is_scissors(KeyX, KeyQ, ANSI));
+        assert!(is_scissors(KeyZ, KeyW, ansi));
+        assert!(is_scissors(KeyX, KeyE, ansi));
+        assert!(is_scissors(KeyC, KeyR, ansi));
+        assert!(!is_scissors(KeyE, KeyV, ansi));
+        assert!(is_scissors(KeyC, KeyW, ansi));
+        assert!(is_scissors(KeyX, KeyQ, ansi));
         
-        assert!(!is_scissors(KeyS, KeyE, ANSI));
-        assert!(is_scissors(KeyS, KeyR, ANSI));
-        assert!(!is_scissors(KeyS, KeyT, ANSI));
+        assert!(!is_scissors(KeyS, KeyE, ansi));
+        assert!(is_scissors(KeyS, KeyR, ansi));
+        assert!(!is_scissors(KeyS, KeyT, ansi));
+
+        // C on the index with angle mod
+        let ansi_opti = &Geometry::from(ANSIOpti);
+        assert!(!is_scissors(KeyC, KeyW, ansi_opti));
+        assert!(!is_scissors(KeyC, KeyR, ansi_opti));
+    }
+
+    #[test]
+    fn angle_mod_sfb() {
+        use crate::kalamine::{Mod::Base, Symbol::Character};
+        let bigrams = [
+            ([KeySymbol::new(Character('c'), KeyC, Base), KeySymbol::new(Character('v'), KeyV, Base)], 1.0),
+            ([KeySymbol::new(Character('x'), KeyX, Base), KeySymbol::new(Character('s'), KeyS, Base)], 2.0),
+        ];
+        let iso = bigram_stats(&bigrams, &Geometry::from(ISO));
+        assert_eq!(iso.total_sfb, 2.0);
+        assert_eq!(iso.per_finger_sfb[&Finger::LeftRing], 2.0);
+        let iso_opti = bigram_stats(&bigrams, &Geometry::from(ISOOpti));
+        assert_eq!(iso_opti.total_sfb, 1.0);
+        assert_eq!(iso_opti.per_finger_sfb[&Finger::LeftIndex], 1.0);
+    }
+
+    #[test]
+    fn sfb_distance_and_travel() {
+        use crate::kalamine::{Mod::Base, Symbol::Character};
+        let ks = |c, key| KeySymbol::new(Character(c), key, Base);
+        let bigrams = [
+            ([ks('r', KeyR), ks('f', KeyF)], 2.0),
+            ([ks('4', Digit4), ks('v', KeyV)], 1.0),
+            ([ks('f', KeyF), ks('j', KeyJ)], 4.0),
+            ([ks('f', KeyF), ks('e', KeyE)], 1.0),
+        ];
+        let stats = bigram_stats(&bigrams, &Geometry::from(Ortho));
+        assert_eq!(stats.total_sfb, 3.0);
+        assert_eq!(stats.list_sfb_keys, vec![([KeyR, KeyF], 2.0), ([Digit4, KeyV], 1.0)]);
+        assert_eq!(stats.total_sfb_distance, 2.0 * 1.0 + 3.0);
+        assert_eq!(stats.per_finger_sfb_distance[&Finger::LeftIndex], 5.0);
+        // home keys are not travel
+        assert_eq!(stats.per_finger_travel[&Finger::RightIndex], 0.0);
+        assert_eq!(stats.per_finger_travel[&Finger::LeftMiddle], 1.0);
+        assert_eq!(stats.per_finger_travel[&Finger::LeftIndex], 5.0);
+        assert_eq!(stats.per_hand_travel[&Hand::Left], 6.0);
+        assert_eq!(stats.total_travel, 6.0);
+    }
+
+    #[test]
+    fn iso_intl_backslash() {
+        // on ISO, IntlBackslash sits between the 1.25U left shift and KeyZ
+        use crate::kalamine::{Mod::Base, Symbol::Character};
+        let ks = |c, key| KeySymbol::new(Character(c), key, Base);
+        let bigrams = [
+            ([ks('<', IntlBackslash), ks('z', KeyZ)], 1.0),
+            ([ks('<', IntlBackslash), ks('w', KeyW)], 1.0),
+            ([ks('<', IntlBackslash), ks('f', KeyF)], 1.0),
+        ];
+        let stats = bigram_stats(&bigrams, &Geometry::from(ISO));
+        assert_eq!(stats.total_sfb, 1.0);
+        assert_eq!(stats.total_sfb_distance, 1.0);
+        assert_eq!(stats.total_lsb, 0.0);
+        // KeyF is 3.5U away, too far for a scissors
+        assert_eq!(stats.list_scissors.len(), 1);
+        assert_eq!(stats.list_scissors[0].0[1], Character('w'));
     }
 
 }
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use super::bigrams::BigramStats;
+use super::trigrams::TrigramStats;
+use super::utils::{self, add_or_insert};
+use crate::kalamine::{DeadKey, Symbol};
+use crate::keyseq::KeySymbol;
+
+/// Usage of the dead keys, and the part of the SFBs and redirects made inside
+/// dead key sequences (e.g. a dead key and its letter on the same finger)
+pub fn dead_key_stats(
+    char_freq: &HashMap<char, f32>,
+    sym_to_keystrokes: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    keyseq_usage: &HashMap<char, Vec<f32>>,
+    bigrams: &BigramStats,
+    trigrams: &TrigramStats,
+) -> DeadKeyStats {
+    let mut per_dead_key: HashMap<DeadKey, DeadKeyUsage> = HashMap::new();
+    let mut typed = 0.0;
+    let mut keys = 0.0;
+
+    for (c, freq) in char_freq {
+        let Some(keyseqs) = sym_to_keystrokes.get(c) else {
+            continue;
+        };
+        let shares = match keyseq_usage.get(c) {
+            Some(shares) => shares.clone(),
+            None => vec![1.0],
+        };
+        for (keyseq, share) in keyseqs.iter().zip(shares) {
+            let freq = freq * share;
+            typed += freq;
+            keys += freq * keyseq.len() as f32;
+            for dk in keyseq.iter().filter_map(|keysym| dead_key(keysym.symbol())) {
+                let usage = per_dead_key.entry(dk).or_default();
+                usage.presses += freq;
+                add_or_insert(usage.chars.entry(*c), freq);
+            }
+        }
+    }
+
+    // a dead key combines with the next key, so an n-gram starting with a dead key
+    // begins inside the sequence of one character
+    for ([first, _], freq) in &bigrams.list_sfb {
+        if let Some(dk) = dead_key(*first) {
+            per_dead_key.entry(dk).or_default().sfb += freq;
+        }
+    }
+    let redirects = trigrams
+        .list_redirects
+        .iter()
+        .chain(&trigrams.list_bad_redirects);
+    for ([first, _, _], freq) in redirects {
+        if let Some(dk) = dead_key(*first) {
+            per_dead_key.entry(dk).or_default().redirects += freq;
+        }
+    }
+
+    let total_sfb = per_dead_key.values().map(|usage| usage.sfb).sum();
+    let total_redirects = per_dead_key.values().map(|usage| usage.redirects).sum();
+    DeadKeyStats {
+        total_presses: per_dead_key.values().map(|usage| usage.presses).sum(),
+        average_keyseq_len: if typed > 0.0 { keys / typed } else { 0.0 },
+        total_sfb,
+        total_sfb_across_chars: bigrams.total_sfb - total_sfb,
+        total_redirects,
+        total_redirects_across_chars: trigrams.total_all_redirects - total_redirects,
+        per_dead_key_presses: per_dead_key
+            .iter()
+            .map(|(dk, usage)| (*dk, usage.presses))
+            .collect(),
+        per_dead_key_sfb: per_dead_key
+            .iter()
+            .map(|(dk, usage)| (*dk, usage.sfb))
+            .collect(),
+        per_dead_key_redirects: per_dead_key
+            .iter()
+            .map(|(dk, usage)| (*dk, usage.redirects))
+            .collect(),
+        list_chars: per_dead_key
+            .into_iter()
+            .map(|(dk, usage)| (dk, utils::result_vec(usage.chars.into_iter().collect())))
+            .collect(),
+    }
+}
+
+fn dead_key(symbol: Symbol) -> Option<DeadKey> {
+    match symbol {
+        Symbol::DeadKey(name) => Some(DeadKey { name }),
+        Symbol::Character(_) => None,
+    }
+}
+
+#[derive(Default)]
+struct DeadKeyUsage {
+    presses: f32,
+    chars: HashMap<char, f32>,
+    sfb: f32,
+    redirects: f32,
+}
+
+pub struct DeadKeyStats {
+    pub total_presses: f32,
+    /// Keys pressed per typed character, dead keys included
+    pub average_keyseq_len: f32,
+    /// SFBs inside dead key sequences
+    pub total_sfb: f32,
+    pub total_sfb_across_chars: f32,
+    /// Redirects (bad ones included) starting with a dead key
+    pub total_redirects: f32,
+    pub total_redirects_across_chars: f32,
+    pub per_dead_key_presses: HashMap<DeadKey, f32>,
+    pub per_dead_key_sfb: HashMap<DeadKey, f32>,
+    pub per_dead_key_redirects: HashMap<DeadKey, f32>,
+    /// Characters typed with each dead key, the most frequent first
+    pub list_chars: HashMap<DeadKey, Vec<(char, f32)>>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::{BuiltinGeometry, Geometry};
+    use crate::kalamine::{Corpus, Layout, ModMapping, PhysicalKey::*};
+
+    #[test]
+    fn dead_key_usage() {
+        // the dead keys are on KeyE, above e and typed by the same finger
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyD, ModMapping::from(vec!["e"])),
+                (KeyE, ModMapping::from(vec!["*´", "*^"])),
+                (KeyJ, ModMapping::from(vec!["a"])),
+            ]),
+            deadkeys: HashMap::from([
+                (
+                    serde_json::from_str(r#""*´""#).unwrap(),
+                    HashMap::from([(Symbol::Character('e'), Symbol::Character('é'))]),
+                ),
+                (
+                    serde_json::from_str(r#""*^""#).unwrap(),
+                    HashMap::from([
+                        (Symbol::Character('e'), Symbol::Character('ê')),
+                        (Symbol::Character('a'), Symbol::Character('â')),
+                    ]),
+                ),
+            ]),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('e', 4.0), ('é', 3.0), ('ê', 2.0), ('â', 1.0)]),
+            digrams: HashMap::from([(['a', 'é'], 1.0), (['e', 'a'], 1.0)]),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let stats = crate::analyse(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Default::default(),
+        )
+        .unwrap();
+        let dead_keys = stats.dead_keys;
+
+        let acute = DeadKey { name: '´' };
+        let circumflex = DeadKey { name: '^' };
+        assert_eq!(dead_keys.total_presses, 6.0);
+        assert_eq!(dead_keys.per_dead_key_presses[&acute], 3.0);
+        assert_eq!(dead_keys.per_dead_key_presses[&circumflex], 3.0);
+        assert_eq!(
+            dead_keys.list_chars[&circumflex],
+            vec![('ê', 2.0), ('â', 1.0)]
+        );
+        assert_eq!(dead_keys.average_keyseq_len, 16.0 / 10.0);
+        // "aé" is typed a, *´, e: only the dead key and e are on the same finger
+        assert_eq!(dead_keys.total_sfb, 1.0);
+        assert_eq!(dead_keys.per_dead_key_sfb[&acute], 1.0);
+        assert_eq!(dead_keys.total_sfb_across_chars, 0.0);
+    }
+
+    #[test]
+    fn dead_key_redirects() {
+        // the dead key is on the index, between s (ring) and e (middle)
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyS, ModMapping::from(vec!["s"])),
+                (KeyF, ModMapping::from(vec!["*´"])),
+                (KeyE, ModMapping::from(vec!["e"])),
+                (KeyG, ModMapping::from(vec!["g"])),
+            ]),
+            deadkeys: HashMap::from([(
+                serde_json::from_str(r#""*´""#).unwrap(),
+                HashMap::from([(Symbol::Character('e'), Symbol::Character('é'))]),
+            )]),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('s', 1.0), ('é', 1.0), ('g', 1.0)]),
+            digrams: HashMap::new(),
+            trigrams: HashMap::from([(['s', 'é', 'g'], 1.0)]),
+            skipgrams: Vec::new(),
+        };
+        let stats = crate::analyse(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Default::default(),
+        )
+        .unwrap();
+
+        // "ség" is typed s, *´, e, g: both trigrams are redirects,
+        // but only *´, e, g starts with the dead key
+        assert_eq!(stats.trigrams.total_all_redirects, 2.0);
+        assert_eq!(stats.dead_keys.total_redirects, 1.0);
+        assert_eq!(
+            stats.dead_keys.per_dead_key_redirects[&DeadKey { name: '´' }],
+            1.0
+        );
+        assert_eq!(stats.dead_keys.total_redirects_across_chars, 1.0);
+    }
+}
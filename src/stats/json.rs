@@ -0,0 +1,538 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use super::Stats;
+use crate::geometry::{Column, Row};
+use crate::hands::{Finger, Hand};
+use crate::kalamine::{DeadKey, PhysicalKey, Symbol};
+use crate::keyseq::{keyseq_name, KeySymbol};
+
+// The JSON report layout (see files/result.json) does not follow the layout of the
+// stats structs, so they are first converted into these borrowing wrappers.
+impl Serialize for Stats {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let report = StatsJSON {
+            symbols: SymbolsJSON {
+                total: SymbolsTotal {
+                    unsupported: self.symbols.total_unsupported,
+                },
+                per_key: PerKey {
+                    usage: sorted(&self.unigrams.key_usage),
+                },
+                per_finger: SymbolsPerFinger {
+                    usage: sorted(&self.unigrams.finger_usage),
+                },
+                per_hand: PerHand {
+                    usage: sorted(&self.unigrams.hand_usage),
+                },
+                per_row: PerRow {
+                    usage: sorted(&self.unigrams.row_usage),
+                },
+                per_column: PerColumn {
+                    usage: sorted(&self.unigrams.column_usage),
+                },
+                detail: SymbolsDetail {
+                    unsupported: Detail::from_chars(&self.symbols.list_unsupported),
+                    alternatives: self
+                        .symbols
+                        .list_alternatives
+                        .iter()
+                        .map(|(c, keyseqs)| (c.to_string(), Detail::from_keyseqs(keyseqs)))
+                        .collect(),
+                },
+            },
+            bigrams: BigramsJSON {
+                total: BigramsTotal {
+                    sku: self.bigrams.total_sku,
+                    sfb: self.bigrams.total_sfb,
+                    lsb: self.bigrams.total_lsb,
+                    scissors: self.bigrams.total_scissors,
+                    in_rolls: self.bigrams.total_in_rolls,
+                    out_rolls: self.bigrams.total_out_rolls,
+                    all_rolls: self.bigrams.total_all_rolls,
+                    sfb_distance: self.bigrams.total_sfb_distance,
+                    travel: self.bigrams.total_travel,
+                },
+                per_finger: BigramsPerFinger {
+                    sku: sorted(&self.bigrams.per_finger_sku),
+                    sfb: sorted(&self.bigrams.per_finger_sfb),
+                    sfb_distance: sorted(&self.bigrams.per_finger_sfb_distance),
+                    travel: sorted(&self.bigrams.per_finger_travel),
+                },
+                per_hand: BigramsPerHand {
+                    travel: sorted(&self.bigrams.per_hand_travel),
+                },
+                detail: BigramsDetail {
+                    sku: Detail::from_ngrams(&self.bigrams.list_sku),
+                    sfb: Detail::from_ngrams(&self.bigrams.list_sfb),
+                    lsb: Detail::from_ngrams(&self.bigrams.list_lsb),
+                    scissors: Detail::from_ngrams(&self.bigrams.list_scissors),
+                    in_rolls: Detail::from_ngrams(&self.bigrams.list_in_rolls),
+                    out_rolls: Detail::from_ngrams(&self.bigrams.list_out_rolls),
+                },
+            },
+            trigrams: TrigramsJSON {
+                total: TrigramsTotal {
+                    sks: self.trigrams.total_sks,
+                    sfs: self.trigrams.total_sfs,
+                    redirects: self.trigrams.total_redirects,
+                    bad_redirects: self.trigrams.total_bad_redirects,
+                    all_redirects: self.trigrams.total_all_redirects,
+                    alternations: self.trigrams.total_alternations,
+                    one_hand_in_rolls: self.trigrams.total_one_hand_in_rolls,
+                    one_hand_out_rolls: self.trigrams.total_one_hand_out_rolls,
+                    roll_alternations: self.trigrams.total_roll_alternations,
+                    others: self.trigrams.total_others,
+                },
+                detail: TrigramsDetail {
+                    sks: Detail::from_ngrams(&self.trigrams.list_sks),
+                    sfs: Detail::from_ngrams(&self.trigrams.list_sfs),
+                    redirects: Detail::from_ngrams(&self.trigrams.list_redirects),
+                    bad_redirects: Detail::from_ngrams(&self.trigrams.list_bad_redirects),
+                    alternations: Detail::from_ngrams(&self.trigrams.list_alternations),
+                    one_hand_in_rolls: Detail::from_ngrams(&self.trigrams.list_one_hand_in_rolls),
+                    one_hand_out_rolls: Detail::from_ngrams(&self.trigrams.list_one_hand_out_rolls),
+                    roll_alternations: Detail::from_ngrams(&self.trigrams.list_roll_alternations),
+                    others: Detail::from_ngrams(&self.trigrams.list_others),
+                },
+            },
+            skipgrams: SkipgramsJSON {
+                total: SkipgramsTotal {
+                    sfs: self.skipgrams.total_sfs,
+                },
+                per_distance: SkipgramsPerDistance {
+                    sfs: by_distance(&self.skipgrams.per_distance_sfs, |sfs| *sfs),
+                },
+                per_finger: SkipgramsPerFinger {
+                    sfs: sorted(&self.skipgrams.per_finger_sfs),
+                },
+                detail: SkipgramsDetail {
+                    sfs: by_distance(&self.skipgrams.list_sfs, |list| Detail::from_ngrams(list)),
+                },
+            },
+            dead_keys: DeadKeysJSON {
+                total: DeadKeysTotal {
+                    presses: self.dead_keys.total_presses,
+                    average_keyseq_len: self.dead_keys.average_keyseq_len,
+                    sfb: self.dead_keys.total_sfb,
+                    sfb_across_chars: self.dead_keys.total_sfb_across_chars,
+                    redirects: self.dead_keys.total_redirects,
+                    redirects_across_chars: self.dead_keys.total_redirects_across_chars,
+                },
+                per_dead_key: PerDeadKey {
+                    presses: sorted(&self.dead_keys.per_dead_key_presses),
+                    sfb: sorted(&self.dead_keys.per_dead_key_sfb),
+                    redirects: sorted(&self.dead_keys.per_dead_key_redirects),
+                },
+                detail: DeadKeysDetail {
+                    chars: self
+                        .dead_keys
+                        .list_chars
+                        .iter()
+                        .map(|(dk, list)| (*dk, Detail::from_chars(list)))
+                        .collect(),
+                },
+            },
+        };
+        report.serialize(s)
+    }
+}
+
+/// Sort the map by key, so the output is stable between runs
+fn sorted<K: Ord + Copy>(map: &HashMap<K, f32>) -> BTreeMap<K, f32> {
+    map.iter().map(|(k, v)| (*k, *v)).collect()
+}
+
+/// Values of each skipgram distance, from 1
+fn by_distance<T, U>(values: &[T], f: impl Fn(&T) -> U) -> BTreeMap<usize, U> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (i + 1, f(v)))
+        .collect()
+}
+
+/// List of n-grams serialized as a JSON object,
+/// keeping the order of the list (most frequent first)
+struct Detail(Vec<(String, f32)>);
+
+impl Detail {
+    fn from_ngrams<const N: usize>(list: &[([Symbol; N], f32)]) -> Self {
+        Detail(
+            list.iter()
+                .map(|(ngram, freq)| (ngram.iter().map(|s| s.to_string()).collect(), *freq))
+                .collect(),
+        )
+    }
+
+    fn from_keyseqs(list: &[(Vec<KeySymbol>, f32)]) -> Self {
+        Detail(
+            list.iter()
+                .map(|(keyseq, usage)| (keyseq_name(keyseq), *usage))
+                .collect(),
+        )
+    }
+
+    fn from_chars(list: &[(char, f32)]) -> Self {
+        Detail(
+            list.iter()
+                .map(|(c, freq)| (c.to_string(), *freq))
+                .collect(),
+        )
+    }
+}
+
+impl Serialize for Detail {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+#[derive(Serialize)]
+struct StatsJSON {
+    symbols: SymbolsJSON,
+    bigrams: BigramsJSON,
+    trigrams: TrigramsJSON,
+    skipgrams: SkipgramsJSON,
+    dead_keys: DeadKeysJSON,
+}
+
+#[derive(Serialize)]
+struct SymbolsJSON {
+    total: SymbolsTotal,
+    per_key: PerKey,
+    per_finger: SymbolsPerFinger,
+    per_hand: PerHand,
+    per_row: PerRow,
+    per_column: PerColumn,
+    detail: SymbolsDetail,
+}
+
+#[derive(Serialize)]
+struct SymbolsTotal {
+    unsupported: f32,
+}
+
+#[derive(Serialize)]
+struct PerKey {
+    usage: BTreeMap<PhysicalKey, f32>,
+}
+
+#[derive(Serialize)]
+struct SymbolsPerFinger {
+    usage: BTreeMap<Finger, f32>,
+}
+
+#[derive(Serialize)]
+struct PerHand {
+    usage: BTreeMap<Hand, f32>,
+}
+
+#[derive(Serialize)]
+struct PerRow {
+    usage: BTreeMap<Row, f32>,
+}
+
+#[derive(Serialize)]
+struct PerColumn {
+    usage: BTreeMap<Column, f32>,
+}
+
+#[derive(Serialize)]
+struct SymbolsDetail {
+    unsupported: Detail,
+    alternatives: OrderedMap<Detail>,
+}
+
+/// Map serialized in the order of the list
+struct OrderedMap<T>(Vec<(String, T)>);
+
+impl<T> FromIterator<(String, T)> for OrderedMap<T> {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        OrderedMap(iter.into_iter().collect())
+    }
+}
+
+impl<T: Serialize> Serialize for OrderedMap<T> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+#[derive(Serialize)]
+struct BigramsJSON {
+    total: BigramsTotal,
+    per_finger: BigramsPerFinger,
+    per_hand: BigramsPerHand,
+    // the only section named in the plural in files/result.json
+    #[serde(rename = "details")]
+    detail: BigramsDetail,
+}
+
+#[derive(Serialize)]
+struct BigramsTotal {
+    sku: f32,
+    sfb: f32,
+    lsb: f32,
+    scissors: f32,
+    in_rolls: f32,
+    out_rolls: f32,
+    all_rolls: f32,
+    sfb_distance: f32,
+    travel: f32,
+}
+
+#[derive(Serialize)]
+struct BigramsPerFinger {
+    sku: BTreeMap<Finger, f32>,
+    sfb: BTreeMap<Finger, f32>,
+    sfb_distance: BTreeMap<Finger, f32>,
+    travel: BTreeMap<Finger, f32>,
+}
+
+#[derive(Serialize)]
+struct BigramsPerHand {
+    travel: BTreeMap<Hand, f32>,
+}
+
+#[derive(Serialize)]
+struct BigramsDetail {
+    sku: Detail,
+    sfb: Detail,
+    lsb: Detail,
+    scissors: Detail,
+    in_rolls: Detail,
+    out_rolls: Detail,
+}
+
+#[derive(Serialize)]
+struct TrigramsJSON {
+    total: TrigramsTotal,
+    detail: TrigramsDetail,
+}
+
+#[derive(Serialize)]
+struct TrigramsTotal {
+    sks: f32,
+    sfs: f32,
+    redirects: f32,
+    bad_redirects: f32,
+    all_redirects: f32,
+    alternations: f32,
+    one_hand_in_rolls: f32,
+    one_hand_out_rolls: f32,
+    roll_alternations: f32,
+    others: f32,
+}
+
+#[derive(Serialize)]
+struct TrigramsDetail {
+    sks: Detail,
+    sfs: Detail,
+    redirects: Detail,
+    bad_redirects: Detail,
+    alternations: Detail,
+    one_hand_in_rolls: Detail,
+    one_hand_out_rolls: Detail,
+    roll_alternations: Detail,
+    others: Detail,
+}
+
+#[derive(Serialize)]
+struct SkipgramsJSON {
+    total: SkipgramsTotal,
+    per_distance: SkipgramsPerDistance,
+    per_finger: SkipgramsPerFinger,
+    detail: SkipgramsDetail,
+}
+
+#[derive(Serialize)]
+struct SkipgramsTotal {
+    sfs: f32,
+}
+
+#[derive(Serialize)]
+struct SkipgramsPerDistance {
+    sfs: BTreeMap<usize, f32>,
+}
+
+#[derive(Serialize)]
+struct SkipgramsPerFinger {
+    sfs: BTreeMap<Finger, f32>,
+}
+
+#[derive(Serialize)]
+struct SkipgramsDetail {
+    sfs: BTreeMap<usize, Detail>,
+}
+
+#[derive(Serialize)]
+struct DeadKeysJSON {
+    total: DeadKeysTotal,
+    per_dead_key: PerDeadKey,
+    detail: DeadKeysDetail,
+}
+
+#[derive(Serialize)]
+struct DeadKeysTotal {
+    presses: f32,
+    average_keyseq_len: f32,
+    sfb: f32,
+    sfb_across_chars: f32,
+    redirects: f32,
+    redirects_across_chars: f32,
+}
+
+#[derive(Serialize)]
+struct PerDeadKey {
+    presses: BTreeMap<DeadKey, f32>,
+    sfb: BTreeMap<DeadKey, f32>,
+    redirects: BTreeMap<DeadKey, f32>,
+}
+
+#[derive(Serialize)]
+struct DeadKeysDetail {
+    chars: BTreeMap<DeadKey, Detail>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::{BuiltinGeometry, Geometry};
+    use crate::kalamine::{Corpus, Layout, ModMapping, PhysicalKey::*};
+
+    #[test]
+    fn serialize_stats() {
+        let layout = Layout {
+            keymap: HashMap::from([
+                (KeyD, ModMapping::from(vec!["e", "E"])),
+                (KeyE, ModMapping::from(vec!["*^"])),
+                (KeyK, ModMapping::from(vec!["e"])),
+            ]),
+            deadkeys: HashMap::from([(
+                serde_json::from_str(r#""*^""#).unwrap(),
+                HashMap::from([(Symbol::Character('e'), Symbol::Character('ê'))]),
+            )]),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('e', 6.0), ('ê', 3.0), ('z', 1.0)]),
+            digrams: HashMap::from([(['ê', 'e'], 2.0), (['e', 'e'], 1.0)]),
+            // "eê" skipgram from the trigrams: KeyD, then the dead key on KeyE
+            trigrams: HashMap::from([(['e', 'z', 'ê'], 1.0)]),
+            skipgrams: Vec::new(),
+        };
+        let stats = crate::analyse(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Default::default(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&stats).unwrap();
+
+        assert_eq!(json["symbols"]["total"]["unsupported"], 1.0);
+        assert_eq!(json["symbols"]["per_key"]["usage"]["KeyD"], 9.0);
+        assert!(json["symbols"]["per_key"]["usage"]
+            .get("ShiftLeft")
+            .is_none());
+        assert_eq!(json["symbols"]["per_finger"]["usage"]["left_middle"], 12.0);
+        assert_eq!(json["symbols"]["per_hand"]["usage"]["left"], 12.0);
+        assert_eq!(json["symbols"]["per_row"]["usage"]["middle"], 9.0);
+        assert_eq!(json["symbols"]["per_column"]["usage"]["left_middle"], 12.0);
+        assert_eq!(
+            json["symbols"]["per_column"]["usage"]["right_index_inner"],
+            0.0
+        );
+        assert_eq!(json["symbols"]["detail"]["unsupported"]["z"], 1.0);
+        assert_eq!(
+            json["symbols"]["detail"]["alternatives"]["e"]["KeyD"],
+            100.0
+        );
+        assert_eq!(json["symbols"]["detail"]["alternatives"]["e"]["KeyK"], 0.0);
+        assert_eq!(json["bigrams"]["total"]["sku"], 3.0);
+        assert_eq!(json["bigrams"]["total"]["sfb"], 2.0);
+        assert_eq!(json["bigrams"]["per_finger"]["sfb"]["left_middle"], 2.0);
+        assert_eq!(json["bigrams"]["details"]["sfb"]["*^e"], 2.0);
+        assert_eq!(json["bigrams"]["details"]["sku"]["ee"], 3.0);
+        assert_eq!(
+            json["bigrams"]["total"]["sfb_distance"],
+            json["bigrams"]["per_finger"]["sfb_distance"]["left_middle"]
+        );
+        assert_eq!(
+            json["bigrams"]["total"]["travel"],
+            json["bigrams"]["per_hand"]["travel"]["left"]
+        );
+        assert_eq!(json["trigrams"]["total"]["sks"], 0.0);
+        assert_eq!(json["trigrams"]["total"]["alternations"], 0.0);
+        assert!(json["trigrams"]["detail"]["one_hand_in_rolls"].is_object());
+        assert_eq!(json["skipgrams"]["total"]["sfs"], 1.0);
+        assert_eq!(json["skipgrams"]["per_distance"]["sfs"]["1"], 1.0);
+        assert_eq!(json["skipgrams"]["per_finger"]["sfs"]["left_middle"], 1.0);
+        assert_eq!(json["skipgrams"]["detail"]["sfs"]["1"]["e*^"], 1.0);
+        assert_eq!(json["dead_keys"]["total"]["presses"], 3.0);
+        assert_eq!(json["dead_keys"]["total"]["sfb"], 2.0);
+        assert_eq!(json["dead_keys"]["total"]["sfb_across_chars"], 0.0);
+        assert_eq!(json["dead_keys"]["per_dead_key"]["presses"]["*^"], 3.0);
+        assert_eq!(json["dead_keys"]["detail"]["chars"]["*^"]["ê"], 3.0);
+    }
+
+    #[test]
+    fn same_sections_as_result_file() {
+        /// Names of the sections, subsections and stats, the deeper keys being the data
+        fn sections(value: &serde_json::Value, depth: usize) -> Vec<String> {
+            let serde_json::Value::Object(map) = value else {
+                return Vec::new();
+            };
+            let mut names = Vec::new();
+            for (name, value) in map {
+                names.push(name.clone());
+                if depth > 1 {
+                    names.extend(
+                        sections(value, depth - 1)
+                            .into_iter()
+                            .map(|section| format!("{name}.{section}")),
+                    );
+                }
+            }
+            names.sort();
+            names
+        }
+
+        let result = std::fs::read_to_string("files/result.json").unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        let layout = Layout {
+            keymap: HashMap::from([(KeyD, ModMapping::from(vec!["e"]))]),
+            deadkeys: HashMap::new(),
+        };
+        let corpus = Corpus {
+            path: String::new(),
+            symbols: HashMap::from([('e', 1.0)]),
+            digrams: HashMap::new(),
+            trigrams: HashMap::new(),
+            skipgrams: Vec::new(),
+        };
+        let stats = crate::analyse(
+            &layout,
+            &corpus,
+            &Geometry::from(BuiltinGeometry::ISO),
+            &Default::default(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&stats).unwrap();
+
+        assert_eq!(sections(&json, 3), sections(&result, 3));
+    }
+
+    #[test]
+    fn detail_keeps_list_order() {
+        let list = vec![
+            ([Symbol::Character('b'), Symbol::Character('a')], 2.0),
+            ([Symbol::Character('a'), Symbol::Character('b')], 1.0),
+        ];
+        let json = serde_json::to_string(&Detail::from_ngrams(&list)).unwrap();
+        assert_eq!(json, r#"{"ba":2.0,"ab":1.0}"#);
+    }
+}
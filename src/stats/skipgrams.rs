@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use strum::IntoEnumIterator;
+
+use super::utils::{self, add_or_insert};
+use crate::{geometry::Geometry, hands::Finger, kalamine::Symbol, keyseq::KeySymbol};
+
+type Skipgram = [Symbol; 2];
+
+/// Weight of the same finger skipgrams in the weighted total is divided by this factor
+/// at each distance, as the finger has more time to move
+pub const DECAY: f32 = 2.0;
+
+/// Statistics of the key pairs typing the skipgrams of each distance, from distance 1
+/// (one key between them)
+pub fn skipgram_stats(
+    skipgrams_freq: &[Vec<([KeySymbol; 2], f32)>],
+    geometry: &Geometry,
+) -> SkipgramStats {
+    let mut per_distance_sfs = Vec::new();
+    let mut per_finger_sfs: HashMap<Finger, f32> = Finger::iter().map(|f| (f, 0.0)).collect();
+    let mut list_sfs = Vec::new();
+
+    for (i, skipgrams) in skipgrams_freq.iter().enumerate() {
+        let weight = decay_weight(i + 1);
+        let mut sfs: Vec<(Skipgram, f32)> = Vec::new();
+        for (keys, freq) in skipgrams {
+            let [key1, key2] = [keys[0].key, keys[1].key];
+            // a repeated key is no SFS, as in trigrams
+            if key1 != key2 && geometry.finger(key1) == geometry.finger(key2) {
+                sfs.push(([keys[0].symbol(), keys[1].symbol()], *freq));
+                add_or_insert(per_finger_sfs.entry(geometry.finger(key1)), freq * weight);
+            }
+        }
+        per_distance_sfs.push(utils::result_sum(&sfs));
+        list_sfs.push(utils::result_vec(sfs));
+    }
+
+    SkipgramStats {
+        total_sfs: per_distance_sfs
+            .iter()
+            .enumerate()
+            .map(|(i, sfs)| sfs * decay_weight(i + 1))
+            .sum(),
+        per_distance_sfs,
+        per_finger_sfs,
+        list_sfs,
+    }
+}
+
+/// Weight of the skipgrams of a distance in the weighted totals: 1 for distance 1
+pub fn decay_weight(distance: usize) -> f32 {
+    DECAY.powi(1 - distance as i32)
+}
+
+pub struct SkipgramStats {
+    /// Same finger skipgrams of all the distances, weighted by `decay_weight`
+    pub total_sfs: f32,
+    /// Same finger skipgrams of each distance
+    pub per_distance_sfs: Vec<f32>,
+    /// Same finger skipgrams of each finger, weighted by `decay_weight`
+    pub per_finger_sfs: HashMap<Finger, f32>,
+    pub list_sfs: Vec<Vec<(Skipgram, f32)>>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::geometry::BuiltinGeometry;
+    use crate::kalamine::{Mod::Base, PhysicalKey::*};
+
+    #[test]
+    fn skipgram_sfs() {
+        let ks = |c, key| KeySymbol::new(Symbol::Character(c), key, Base);
+        let skipgrams = vec![
+            vec![
+                ([ks('e', KeyE), ks('d', KeyD)], 2.0),
+                ([ks('e', KeyE), ks('e', KeyE)], 5.0),
+                ([ks('e', KeyE), ks('j', KeyJ)], 1.0),
+            ],
+            vec![([ks('e', KeyE), ks('d', KeyD)], 1.0)],
+            vec![([ks('j', KeyJ), ks('h', KeyH)], 4.0)],
+        ];
+        let stats = skipgram_stats(&skipgrams, &Geometry::from(BuiltinGeometry::ISO));
+
+        assert_eq!(stats.per_distance_sfs, vec![2.0, 1.0, 4.0]);
+        assert_eq!(stats.total_sfs, 2.0 + 0.5 + 1.0);
+        assert_eq!(stats.per_finger_sfs[&Finger::LeftMiddle], 2.5);
+        assert_eq!(stats.per_finger_sfs[&Finger::RightIndex], 1.0);
+        assert_eq!(
+            stats.list_sfs[0],
+            vec![([Symbol::Character('e'), Symbol::Character('d')], 2.0)]
+        );
+    }
+}
@@ -4,18 +4,48 @@ use crate::keyseq::KeySymbol;
 
 use super::utils;
 
+/// Equivalent key sequences of a character, with their usage in %
+pub type KeySeqUsage = Vec<(Vec<KeySymbol>, f32)>;
+
 pub fn symbol_stats(
     corpus_symbols: &HashMap<char, f32>,
-    layout_symbols: &HashMap<char, Vec<KeySymbol>>,
+    layout_symbols: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    keyseq_usage: &HashMap<char, Vec<f32>>,
 ) -> SymbolStats {
     let unsupported = unsupported_characters(corpus_symbols, layout_symbols);
 
     SymbolStats {
         total_unsupported: utils::result_sum(&unsupported),
         list_unsupported: utils::result_vec(unsupported),
+        list_alternatives: alternatives(corpus_symbols, layout_symbols, keyseq_usage),
     }
 }
 
+/// Usage of the key sequences of the characters typed in several ways,
+/// the most frequent characters first
+fn alternatives(
+    corpus_symbols: &HashMap<char, f32>,
+    layout_symbols: &HashMap<char, Vec<Vec<KeySymbol>>>,
+    keyseq_usage: &HashMap<char, Vec<f32>>,
+) -> Vec<(char, KeySeqUsage)> {
+    let mut chars: Vec<(char, f32)> = keyseq_usage
+        .keys()
+        .filter_map(|c| Some((*c, *corpus_symbols.get(c)?)))
+        .collect();
+    chars.sort_by(|(c1, f1), (c2, f2)| f2.total_cmp(f1).then(c1.cmp(c2)));
+    chars
+        .into_iter()
+        .map(|(c, _)| {
+            let keyseqs = layout_symbols[&c]
+                .iter()
+                .zip(&keyseq_usage[&c])
+                .map(|(keyseq, share)| (keyseq.clone(), share * 100.0))
+                .collect();
+            (c, keyseqs)
+        })
+        .collect()
+}
+
 /// Using a generic because we don't care about actual type,
 /// so it is easier to test without having to define everything
 fn unsupported_characters<T>(
@@ -32,6 +62,8 @@ fn unsupported_characters<T>(
 pub struct SymbolStats {
     pub total_unsupported: f32,
     pub list_unsupported: Vec<(char, f32)>,
+    /// Usage in % of each equivalent key sequence, for the characters having several
+    pub list_alternatives: Vec<(char, KeySeqUsage)>,
 }
 
 #[cfg(test)]
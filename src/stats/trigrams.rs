@@ -1,17 +1,23 @@
 use super::utils;
 use crate::{
-    hands::{Finger, RollDirection},
+    geometry::Geometry,
+    hands::{Finger, Hand, RollDirection},
     kalamine::{PhysicalKey, Symbol},
     keyseq::KeySymbol,
 };
 
 type Trigram = [Symbol; 3];
 
-pub fn trigram_stats(trigrams_freq: &Vec<([KeySymbol; 3], f32)>) -> TrigramStats {
+pub fn trigram_stats(trigrams_freq: &[([KeySymbol; 3], f32)], geometry: &Geometry) -> TrigramStats {
     let mut sks: Vec<(Trigram, f32)> = Vec::new();
     let mut sfs: Vec<(Trigram, f32)> = Vec::new();
     let mut redirects: Vec<(Trigram, f32)> = Vec::new();
     let mut bad_redirects: Vec<(Trigram, f32)> = Vec::new();
+    let mut alternations: Vec<(Trigram, f32)> = Vec::new();
+    let mut one_hand_in_rolls: Vec<(Trigram, f32)> = Vec::new();
+    let mut one_hand_out_rolls: Vec<(Trigram, f32)> = Vec::new();
+    let mut roll_alternations: Vec<(Trigram, f32)> = Vec::new();
+    let mut others: Vec<(Trigram, f32)> = Vec::new();
 
     for (trigram_keys, freq) in trigrams_freq {
         let trigram = [
@@ -26,15 +32,25 @@ pub fn trigram_stats(trigrams_freq: &Vec<([KeySymbol; 3], f32)>) -> TrigramStats
 
         if is_sks(key1, key2, key3) {
             sks.push((trigram, freq));
-        } else if is_sfs(key1, key2, key3) {
+        } else if is_sfs(key1, key2, key3, geometry) {
             sfs.push((trigram, freq));
         }
-        if is_redirect(key1, key2, key3) {
-            if is_redirect_bad(key1, key2, key3) {
+        if is_redirect(key1, key2, key3, geometry) {
+            if is_redirect_bad(key1, key2, key3, geometry) {
                 bad_redirects.push((trigram, freq));
             } else {
                 redirects.push((trigram, freq));
             }
+        } else if is_alternation(key1, key2, key3, geometry) {
+            alternations.push((trigram, freq));
+        } else if is_one_hand_roll(key1, key2, key3, geometry, RollDirection::Inside) {
+            one_hand_in_rolls.push((trigram, freq));
+        } else if is_one_hand_roll(key1, key2, key3, geometry, RollDirection::Outside) {
+            one_hand_out_rolls.push((trigram, freq));
+        } else if is_roll_alternation(key1, key2, key3, geometry) {
+            roll_alternations.push((trigram, freq));
+        } else {
+            others.push((trigram, freq));
         }
     }
 
@@ -44,10 +60,20 @@ pub fn trigram_stats(trigrams_freq: &Vec<([KeySymbol; 3], f32)>) -> TrigramStats
         total_redirects: utils::result_sum(&redirects),
         total_bad_redirects: utils::result_sum(&bad_redirects),
         total_all_redirects: utils::result_sum(&redirects) + utils::result_sum(&bad_redirects),
+        total_alternations: utils::result_sum(&alternations),
+        total_one_hand_in_rolls: utils::result_sum(&one_hand_in_rolls),
+        total_one_hand_out_rolls: utils::result_sum(&one_hand_out_rolls),
+        total_roll_alternations: utils::result_sum(&roll_alternations),
+        total_others: utils::result_sum(&others),
         list_sks: utils::result_vec(sks),
         list_sfs: utils::result_vec(sfs),
         list_redirects: utils::result_vec(redirects),
         list_bad_redirects: utils::result_vec(bad_redirects),
+        list_alternations: utils::result_vec(alternations),
+        list_one_hand_in_rolls: utils::result_vec(one_hand_in_rolls),
+        list_one_hand_out_rolls: utils::result_vec(one_hand_out_rolls),
+        list_roll_alternations: utils::result_vec(roll_alternations),
+        list_others: utils::result_vec(others),
     }
 }
 
@@ -55,13 +81,19 @@ pub fn is_sks(key1: PhysicalKey, _: PhysicalKey, key3: PhysicalKey) -> bool {
     key1 == key3
 }
 
-pub fn is_sfs(key1: PhysicalKey, _: PhysicalKey, key3: PhysicalKey) -> bool {
-    key1.finger() == key3.finger()
+pub fn is_sfs(key1: PhysicalKey, _: PhysicalKey, key3: PhysicalKey, geometry: &Geometry) -> bool {
+    geometry.finger(key1) == geometry.finger(key3)
 }
 
-pub fn is_redirect(key1: PhysicalKey, key2: PhysicalKey, key3: PhysicalKey) -> bool {
-    let roll1 = key1.finger().roll_direction(key2.finger());
-    let roll2 = key2.finger().roll_direction(key3.finger());
+pub fn is_redirect(
+    key1: PhysicalKey,
+    key2: PhysicalKey,
+    key3: PhysicalKey,
+    geometry: &Geometry,
+) -> bool {
+    let [finger1, finger2, finger3] = [key1, key2, key3].map(|k| geometry.finger(k));
+    let roll1 = finger1.roll_direction(finger2);
+    let roll2 = finger2.roll_direction(finger3);
     match (roll1, roll2) {
         (RollDirection::Inside, RollDirection::Outside) => true,
         (RollDirection::Outside, RollDirection::Inside) => true,
@@ -71,15 +103,64 @@ pub fn is_redirect(key1: PhysicalKey, key2: PhysicalKey, key3: PhysicalKey) -> b
 
 /// This function assumes the keys correspond to a redirect,
 /// and just checks if it is a *bad* redirect
-pub fn is_redirect_bad(key1: PhysicalKey, key2: PhysicalKey, key3: PhysicalKey) -> bool {
-    is_bad_finger(key1.finger()) && is_bad_finger(key2.finger()) && is_bad_finger(key3.finger())
+pub fn is_redirect_bad(
+    key1: PhysicalKey,
+    key2: PhysicalKey,
+    key3: PhysicalKey,
+    geometry: &Geometry,
+) -> bool {
+    [key1, key2, key3]
+        .iter()
+        .all(|k| is_bad_finger(geometry.finger(*k)))
 }
 
-fn is_bad_finger(finger: Finger) -> bool {
-    match finger {
-        Finger::LeftIndex | Finger::RightIndex | Finger::Thumb => false,
-        _ => true,
+/// Hands switching at each key (left, right, left or right, left, right).
+/// Thumbs do not alternate.
+pub fn is_alternation(
+    key1: PhysicalKey,
+    key2: PhysicalKey,
+    key3: PhysicalKey,
+    geometry: &Geometry,
+) -> bool {
+    let [hand1, hand2, hand3] = [key1, key2, key3].map(|k| geometry.hand(k));
+    ![hand1, hand2, hand3].contains(&Hand::Thumbs) && hand1 != hand2 && hand2 != hand3
+}
+
+/// Three fingers of one hand rolling in the given direction (e.g. KeyA KeyS KeyD inward)
+pub fn is_one_hand_roll(
+    key1: PhysicalKey,
+    key2: PhysicalKey,
+    key3: PhysicalKey,
+    geometry: &Geometry,
+    direction: RollDirection,
+) -> bool {
+    let [finger1, finger2, finger3] = [key1, key2, key3].map(|k| geometry.finger(k));
+    finger1.roll_direction(finger2) == direction && finger2.roll_direction(finger3) == direction
+}
+
+/// Roll of two fingers of one hand, before or after a key of the other hand
+pub fn is_roll_alternation(
+    key1: PhysicalKey,
+    key2: PhysicalKey,
+    key3: PhysicalKey,
+    geometry: &Geometry,
+) -> bool {
+    let [finger1, finger2, finger3] = [key1, key2, key3].map(|k| geometry.finger(k));
+    if [finger1, finger2, finger3].contains(&Finger::Thumb) {
+        return false;
     }
+    let is_roll = |d: &RollDirection| matches!(d, RollDirection::Inside | RollDirection::Outside);
+    let roll1 = finger1.roll_direction(finger2);
+    let roll2 = finger2.roll_direction(finger3);
+    (is_roll(&roll1) && roll2 == RollDirection::DifferentHands)
+        || (roll1 == RollDirection::DifferentHands && is_roll(&roll2))
+}
+
+fn is_bad_finger(finger: Finger) -> bool {
+    !matches!(
+        finger,
+        Finger::LeftIndex | Finger::RightIndex | Finger::Thumb
+    )
 }
 
 pub struct TrigramStats {
@@ -88,31 +169,84 @@ pub struct TrigramStats {
     pub total_redirects: f32,
     pub total_bad_redirects: f32,
     pub total_all_redirects: f32,
+    pub total_alternations: f32,
+    pub total_one_hand_in_rolls: f32,
+    pub total_one_hand_out_rolls: f32,
+    pub total_roll_alternations: f32,
+    /// Trigrams in none of the redirect, alternation and roll classes
+    /// (e.g. with an SFB or a thumb key)
+    pub total_others: f32,
     pub list_sks: Vec<(Trigram, f32)>,
     pub list_sfs: Vec<(Trigram, f32)>,
     pub list_redirects: Vec<(Trigram, f32)>,
     pub list_bad_redirects: Vec<(Trigram, f32)>,
+    pub list_alternations: Vec<(Trigram, f32)>,
+    pub list_one_hand_in_rolls: Vec<(Trigram, f32)>,
+    pub list_one_hand_out_rolls: Vec<(Trigram, f32)>,
+    pub list_roll_alternations: Vec<(Trigram, f32)>,
+    pub list_others: Vec<(Trigram, f32)>,
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::geometry::BuiltinGeometry;
     use PhysicalKey::*;
 
     #[test]
     fn redirect() {
-        assert!(is_redirect(KeyJ, KeyL, KeyK));
-        assert!(is_redirect(KeyK, KeyL, KeyJ));
-        assert!(!is_redirect(KeyJ, KeyK, KeyL));
-        assert!(!is_redirect(KeyK, KeyH, KeyJ)); // same finger
-        assert!(!is_redirect(KeyL, KeyG, KeyK)); // different hands
-        assert!(!is_redirect(KeyL, Space, Semicolon)); // space
+        let g = &Geometry::from(BuiltinGeometry::ISO);
+        assert!(is_redirect(KeyJ, KeyL, KeyK, g));
+        assert!(is_redirect(KeyK, KeyL, KeyJ, g));
+        assert!(!is_redirect(KeyJ, KeyK, KeyL, g));
+        assert!(!is_redirect(KeyK, KeyH, KeyJ, g)); // same finger
+        assert!(!is_redirect(KeyL, KeyG, KeyK, g)); // different hands
+        assert!(!is_redirect(KeyL, Space, Semicolon, g)); // space
     }
 
     #[test]
     fn bad_redirect() {
-        assert!(!is_redirect_bad(KeyJ, KeyL, KeyK));
-        assert!(is_redirect_bad(KeyK, Semicolon, KeyL));
+        let g = &Geometry::from(BuiltinGeometry::ISO);
+        assert!(!is_redirect_bad(KeyJ, KeyL, KeyK, g));
+        assert!(is_redirect_bad(KeyK, Semicolon, KeyL, g));
+    }
+
+    #[test]
+    fn trigram_classes() {
+        use crate::kalamine::Mod::Base;
+        let g = &Geometry::from(BuiltinGeometry::ISO);
+        let trigram = |keys: [PhysicalKey; 3], freq| {
+            let ks = |key| KeySymbol::new(Symbol::Character('a'), key, Base);
+            (keys.map(ks), freq)
+        };
+        let trigrams = [
+            trigram([KeyA, KeyJ, KeyD], 1.0),  // alternation
+            trigram([KeyA, KeyS, KeyD], 2.0),  // one-hand in roll
+            trigram([KeyL, KeyK, KeyJ], 3.0),  // one-hand in roll
+            trigram([KeyD, KeyS, KeyA], 4.0),  // one-hand out roll
+            trigram([KeyS, KeyD, KeyJ], 5.0),  // roll, then alternation
+            trigram([KeyJ, KeyS, KeyD], 6.0),  // alternation, then roll
+            trigram([KeyD, KeyE, KeyJ], 7.0),  // SFB
+            trigram([KeyA, Space, KeyJ], 8.0), // thumb
+            trigram([KeyJ, KeyL, KeyK], 9.0),  // redirect
+        ];
+        let stats = trigram_stats(&trigrams, g);
+        assert_eq!(stats.total_alternations, 1.0);
+        assert_eq!(stats.total_one_hand_in_rolls, 5.0);
+        assert_eq!(stats.total_one_hand_out_rolls, 4.0);
+        assert_eq!(stats.total_roll_alternations, 11.0);
+        assert_eq!(stats.total_others, 15.0);
+        assert_eq!(stats.total_redirects, 9.0);
+
+        assert!(!is_alternation(KeyA, Space, KeyJ, g));
+        assert!(!is_roll_alternation(KeyA, KeyA, KeyJ, g));
+        assert!(!is_one_hand_roll(
+            KeyA,
+            KeyS,
+            KeyK,
+            g,
+            RollDirection::Inside
+        ));
     }
 }
@@ -1,28 +1,43 @@
 use std::collections::HashMap;
 
 use super::utils::add_or_insert;
+use crate::geometry::{Column, Geometry, Row};
 use crate::hands::{Finger, Hand};
 use crate::kalamine::PhysicalKey;
 use crate::keyseq::KeySymbol;
 use strum::IntoEnumIterator;
 
-pub fn unigram_stats(keysym_freq: &Vec<(KeySymbol, f32)>) -> UnigramStats {
-    let mut key_usage: HashMap<PhysicalKey, f32> = PhysicalKey::iter().map(|k| (k, 0.0)).collect();
+pub fn unigram_stats(keysym_freq: &[(KeySymbol, f32)], geometry: &Geometry) -> UnigramStats {
+    // the modifiers only have a usage when their presses are counted
+    let mut key_usage: HashMap<PhysicalKey, f32> = PhysicalKey::iter()
+        .filter(|k| !k.is_modifier())
+        .map(|k| (k, 0.0))
+        .collect();
     let mut finger_usage: HashMap<Finger, f32> = Finger::iter().map(|f| (f, 0.0)).collect();
     let mut hand_usage: HashMap<Hand, f32> = Hand::iter().map(|h| (h, 0.0)).collect();
+    let mut row_usage: HashMap<Row, f32> = Row::iter().map(|r| (r, 0.0)).collect();
+    let mut column_usage: HashMap<Column, f32> = Column::iter().map(|c| (c, 0.0)).collect();
 
     for (keysym, freq) in keysym_freq.iter() {
         let key = keysym.key;
         let freq = *freq;
         add_or_insert(key_usage.entry(key), freq);
-        add_or_insert(finger_usage.entry(key.finger()), freq);
-        add_or_insert(hand_usage.entry(key.hand()), freq);
+        add_or_insert(finger_usage.entry(geometry.finger(key)), freq);
+        add_or_insert(hand_usage.entry(geometry.hand(key)), freq);
+        // rows and columns are penalized for their reach, which the modifiers held down
+        // don't have
+        if !key.is_modifier() {
+            add_or_insert(row_usage.entry(geometry.row(key)), freq);
+            add_or_insert(column_usage.entry(geometry.column(key)), freq);
+        }
     }
 
     UnigramStats {
-        key_usage: key_usage,
-        finger_usage: finger_usage,
-        hand_usage: hand_usage,
+        key_usage,
+        finger_usage,
+        hand_usage,
+        row_usage,
+        column_usage,
     }
 }
 
@@ -30,12 +45,15 @@ pub struct UnigramStats {
     pub key_usage: HashMap<PhysicalKey, f32>,
     pub finger_usage: HashMap<Finger, f32>,
     pub hand_usage: HashMap<Hand, f32>,
+    pub row_usage: HashMap<Row, f32>,
+    pub column_usage: HashMap<Column, f32>,
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::geometry::BuiltinGeometry;
     use crate::kalamine::{Mod::*, PhysicalKey::*};
     use crate::stats::utils;
 
@@ -78,12 +96,52 @@ mod tests {
         ]);
         let hand_usage: HashMap<Hand, f32> =
             HashMap::from([(Hand::Left, 21.0), (Hand::Right, 24.0), (Hand::Thumbs, 0.0)]);
-        let result = unigram_stats(&keysym_freq);
+        let result = unigram_stats(&keysym_freq, &Geometry::from(BuiltinGeometry::ISO));
         assert_eq!(utils::round_result_map(result.finger_usage), finger_usage);
         assert_eq!(utils::round_result_map(result.hand_usage), hand_usage);
+        assert!(!result.key_usage.contains_key(&ShiftLeft));
         let result_key_usage = utils::round_result_map(result.key_usage);
         for (key, usage) in key_usage.iter() {
             assert_eq!(result_key_usage.get(key).unwrap(), usage);
         }
+        let row_usage = utils::round_result_map(result.row_usage);
+        assert_eq!(row_usage[&Row::Upper], 45.0);
+        assert_eq!(row_usage[&Row::Middle], 0.0);
+        let column_usage = utils::round_result_map(result.column_usage);
+        assert_eq!(column_usage[&Column::LeftIndex], 5.0);
+        assert_eq!(column_usage[&Column::LeftIndexInner], 6.0);
+        assert_eq!(column_usage[&Column::RightIndexInner], 7.0);
+        assert_eq!(column_usage[&Column::LeftPinkyOuter], 0.0);
+    }
+
+    #[test]
+    fn modifiers_not_in_row_and_column_usage() {
+        use crate::kalamine::Symbol::Character;
+        let keysym_freq: Vec<(KeySymbol, f32)> = Vec::from([
+            (KeySymbol::new(Character('Z'), KeyZ, Shift), 1.0),
+            (KeySymbol::new(Character('Z'), ShiftRight, Base), 1.0),
+        ]);
+        let result = unigram_stats(&keysym_freq, &Geometry::from(BuiltinGeometry::ISO));
+        assert_eq!(result.row_usage[&Row::Lower], 1.0);
+        assert_eq!(result.column_usage[&Column::RightPinky], 0.0);
+        assert_eq!(result.key_usage[&ShiftRight], 1.0);
+        assert_eq!(result.finger_usage[&Finger::RightPinky], 1.0);
+    }
+
+    #[test]
+    fn angle_mod_unigram_stats() {
+        use crate::kalamine::Symbol::Character;
+        let keysym_freq: Vec<(KeySymbol, f32)> = Vec::from([
+            (KeySymbol::new(Character('z'), KeyZ, Base), 1.0),
+            (KeySymbol::new(Character('x'), KeyX, Base), 2.0),
+            (KeySymbol::new(Character('c'), KeyC, Base), 3.0),
+        ]);
+        use crate::hands::Finger::*;
+        let result = unigram_stats(&keysym_freq, &Geometry::from(BuiltinGeometry::ISOOpti));
+        let finger_usage = utils::round_result_map(result.finger_usage);
+        assert_eq!(finger_usage[&LeftPinky], 0.0);
+        assert_eq!(finger_usage[&LeftRing], 1.0);
+        assert_eq!(finger_usage[&LeftMiddle], 2.0);
+        assert_eq!(finger_usage[&LeftIndex], 3.0);
     }
 }